@@ -0,0 +1,175 @@
+use crate::{bitboard::{board_from_square, file, pop_lsb, rank, Color, Square, EMPTY_BITBOARD}, piece_info::{KING, MOVE_BOARDS, PAWN_ATTACK_BOARDS}};
+
+// King and pawn against king with white as the strong side and the pawn on files a to d, other positions are
+// mirrored onto these when probing. Indexed by pawn square, side to move, white king and black king.
+const KPK_PAWN_SQUARES: usize = 24;
+const KPK_POSITIONS: usize = KPK_PAWN_SQUARES * 2 * 64 * 64;
+
+// One bit per position, set when white wins.
+static mut KPK_BITBASE: [u64; KPK_POSITIONS / 64] = [0; KPK_POSITIONS / 64];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum KpkResult {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+#[inline(always)]
+fn kpk_index(white_king: Square, black_king: Square, pawn: Square, white_to_move: bool) -> usize {
+    let pawn_index = (rank(pawn) as usize - 1) * 4 + file(pawn) as usize;
+    ((pawn_index * 2 + white_to_move as usize) * 64 + white_king as usize) * 64 + black_king as usize
+}
+
+#[inline(always)]
+fn king_moves(square: Square) -> u64 {
+    unsafe { MOVE_BOARDS[KING as usize][square as usize] }
+}
+
+#[inline(always)]
+fn pawn_attacks(square: Square) -> u64 {
+    unsafe { PAWN_ATTACK_BOARDS[Color::White as usize][square as usize] }
+}
+
+// Retrograde analysis, positions are resolved from the ones already known until nothing changes and whatever is left
+// can't be won. Needs the move boards so runs after move_gen_init.
+#[cold]
+pub fn kpk_bitbase_init() {
+    let mut results = vec![KpkResult::Unknown; KPK_POSITIONS];
+    for pawn in kpk_pawn_squares() {
+        for white_king in 0..64 {
+            for black_king in 0..64 {
+                for white_to_move in [false, true] {
+                    results[kpk_index(white_king, black_king, pawn, white_to_move)] = initial_kpk_result(white_king, black_king, pawn, white_to_move);
+                }
+            }
+        }
+    }
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for pawn in kpk_pawn_squares() {
+            for white_king in 0..64 {
+                for black_king in 0..64 {
+                    for white_to_move in [false, true] {
+                        let index = kpk_index(white_king, black_king, pawn, white_to_move);
+                        if results[index] != KpkResult::Unknown {
+                            continue;
+                        }
+                        let result = if white_to_move {
+                            white_to_move_result(&results, white_king, black_king, pawn)
+                        } else {
+                            black_to_move_result(&results, white_king, black_king, pawn)
+                        };
+                        if result != KpkResult::Unknown {
+                            results[index] = result;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    for (index, result) in results.iter().enumerate() {
+        if *result == KpkResult::Win {
+            unsafe { KPK_BITBASE[index / 64] |= 1 << (index % 64) };
+        }
+    }
+}
+
+fn kpk_pawn_squares() -> impl Iterator<Item = Square> {
+    (1..7).flat_map(|rank| (0..4).map(move |file| rank * 8 + file))
+}
+
+fn initial_kpk_result(white_king: Square, black_king: Square, pawn: Square, white_to_move: bool) -> KpkResult {
+    // Overlapping pieces, touching kings or black in check with white to move
+    if white_king == black_king || white_king == pawn || black_king == pawn
+        || king_moves(white_king) & board_from_square(black_king) != EMPTY_BITBOARD
+        || (white_to_move && pawn_attacks(pawn) & board_from_square(black_king) != EMPTY_BITBOARD) {
+        return KpkResult::Invalid;
+    }
+    let promotion_square = pawn + 8;
+    if white_to_move {
+        // The pawn promotes and the new queen can't be taken.
+        if rank(pawn) == 6 && promotion_square != white_king && promotion_square != black_king
+            && (king_moves(black_king) & board_from_square(promotion_square) == EMPTY_BITBOARD
+                || king_moves(white_king) & board_from_square(promotion_square) != EMPTY_BITBOARD) {
+            return KpkResult::Win;
+        }
+    } else {
+        let white_attacks = king_moves(white_king) | pawn_attacks(pawn);
+        // Stalemate or the pawn can be taken.
+        if king_moves(black_king) & !white_attacks == EMPTY_BITBOARD
+            || king_moves(black_king) & board_from_square(pawn) & !king_moves(white_king) != EMPTY_BITBOARD {
+            return KpkResult::Draw;
+        }
+    }
+    KpkResult::Unknown
+}
+
+// White needs one move into a won position, the position is only drawn once every move is.
+fn white_to_move_result(results: &[KpkResult], white_king: Square, black_king: Square, pawn: Square) -> KpkResult {
+    let mut all_drawn = true;
+    let mut consider = |result: KpkResult| -> bool {
+        match result {
+            KpkResult::Win => true,
+            KpkResult::Unknown => { all_drawn = false; false },
+            _ => false,
+        }
+    };
+    let mut moves = king_moves(white_king) & !king_moves(black_king) & !board_from_square(pawn);
+    while moves != EMPTY_BITBOARD {
+        let destination = pop_lsb(&mut moves);
+        if consider(results[kpk_index(destination, black_king, pawn, false)]) {
+            return KpkResult::Win;
+        }
+    }
+    // Promotions were resolved in the initial pass.
+    if rank(pawn) < 6 {
+        let push = pawn + 8;
+        if push != white_king && push != black_king {
+            if consider(results[kpk_index(white_king, black_king, push, false)]) {
+                return KpkResult::Win;
+            }
+            let double_push = push + 8;
+            if rank(pawn) == 1 && double_push != white_king && double_push != black_king
+                && consider(results[kpk_index(white_king, black_king, double_push, false)]) {
+                return KpkResult::Win;
+            }
+        }
+    }
+    if all_drawn { KpkResult::Draw } else { KpkResult::Unknown }
+}
+
+// Black needs one move into a drawn position, the position is only won once every move is.
+fn black_to_move_result(results: &[KpkResult], white_king: Square, black_king: Square, pawn: Square) -> KpkResult {
+    let mut all_won = true;
+    let mut moves = king_moves(black_king) & !king_moves(white_king) & !pawn_attacks(pawn) & !board_from_square(pawn);
+    while moves != EMPTY_BITBOARD {
+        let destination = pop_lsb(&mut moves);
+        match results[kpk_index(white_king, destination, pawn, true)] {
+            KpkResult::Draw => return KpkResult::Draw,
+            KpkResult::Unknown => all_won = false,
+            _ => (),
+        }
+    }
+    if all_won { KpkResult::Win } else { KpkResult::Unknown }
+}
+
+// Whether the side with the pawn wins, from any orientation.
+pub fn kpk_probe(strong_side: Color, strong_king: Square, weak_king: Square, pawn: Square, strong_to_move: bool) -> bool {
+    let (mut white_king, mut black_king, mut pawn) = match strong_side {
+        Color::White => (strong_king, weak_king, pawn),
+        Color::Black => (strong_king ^ 56, weak_king ^ 56, pawn ^ 56),
+    };
+    if file(pawn) > 3 {
+        white_king ^= 7;
+        black_king ^= 7;
+        pawn ^= 7;
+    }
+    let index = kpk_index(white_king, black_king, pawn, strong_to_move);
+    unsafe { KPK_BITBASE[index / 64] & (1 << (index % 64)) != 0 }
+}
@@ -34,6 +34,10 @@ pub const FILE5: Bitboard = FILE0 << 5;
 pub const FILE6: Bitboard = FILE0 << 6;
 pub const FILE7: Bitboard = FILE0 << 7;
 
+// a1 is a dark square
+pub const DARK_SQUARES: Bitboard = 0xaa55aa55aa55aa55;
+pub const LIGHT_SQUARES: Bitboard = !DARK_SQUARES;
+
 pub const NULL_SQUARE: Square = 100;
 
 pub const WHITE_VAL: u8 = 0;
@@ -134,6 +138,13 @@ impl Color {
 pub const fn rank(s: Square) -> u8 { s / 8 }
 #[inline(always)]
 pub const fn file(s: Square) -> u8 { s % 8 }
+// Number of king moves between two squares
+#[inline(always)]
+pub const fn square_distance(a: Square, b: Square) -> u8 {
+    let rank_distance = rank(a).abs_diff(rank(b));
+    let file_distance = file(a).abs_diff(file(b));
+    if rank_distance > file_distance { rank_distance } else { file_distance }
+}
 
 #[inline(always)]
 pub const fn get_lsb(b: Bitboard) -> Square {
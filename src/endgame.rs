@@ -0,0 +1,218 @@
+use crate::{bitbase::kpk_probe, bitboard::{bit_count, board_from_square, file, get_lsb, rank, square_distance, Color, Square, COLORS, DARK_SQUARES, EMPTY_BITBOARD, FILES}, evaluation::{relative_rank, Evaluation, BISHOP_EVAL, CENTI_PAWN, PAWN_EVAL, PIECE_EVAL_TABLE, QUEEN_PHASE_VALUE, ROOK_EVAL}, piece_info::PieceType, state::State};
+
+// Piece counts for each color, 4 bits per piece type from queen to pawn.
+pub type MaterialKey = u64;
+
+// Scale factors are out of SCALE_FACTOR_NORMAL, 0 being a dead draw.
+pub const SCALE_FACTOR_NORMAL: Evaluation = 64;
+const SCALE_FACTOR_NO_PAWNS: Evaluation = 16;
+const SCALE_FACTOR_OPPOSITE_BISHOPS: Evaluation = 24;
+const SCALE_FACTOR_OPPOSITE_BISHOPS_WITH_PIECES: Evaluation = 48;
+
+// Added to the strong side's material in known wins so the search heads for them.
+const KNOWN_WIN_EVAL: Evaluation = CENTI_PAWN * 500;
+const PUSH_TO_EDGE_EVAL: Evaluation = CENTI_PAWN * 20;
+const PUSH_CLOSE_EVAL: Evaluation = CENTI_PAWN * 10;
+const PUSH_TO_CORNER_EVAL: Evaluation = CENTI_PAWN * 40;
+const PUSH_PAWN_EVAL: Evaluation = CENTI_PAWN * 20;
+
+// No registered endgame has more than a queen on the board.
+const ENDGAME_MAX_PHASE: u8 = QUEEN_PHASE_VALUE;
+
+#[derive(Clone, Copy, Debug)]
+pub enum EndgameKind {
+    // Replaces the whole evaluation, from the strong side's perspective.
+    Evaluation(fn(&State, Color) -> Evaluation),
+    // Scale factor for the normal evaluation when the strong side is ahead.
+    Scale(fn(&State, Color) -> Evaluation),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Endgame {
+    pub name: &'static str,
+    pub strong_side: Color,
+    pub kind: EndgameKind,
+}
+
+// Sorted by key so lookups can binary search.
+static mut ENDGAMES: Vec<(MaterialKey, Endgame)> = Vec::new();
+
+#[cold]
+pub fn endgame_init() {
+    let mut endgames: Vec<(MaterialKey, Endgame)> = Vec::new();
+    let mut register = |name: &'static str, strong_pieces: &str, weak_pieces: &str, kind: EndgameKind| {
+        for strong_side in COLORS {
+            let mut counts = [[0; 5]; 2];
+            counts[strong_side as usize] = piece_counts(strong_pieces);
+            counts[strong_side.other() as usize] = piece_counts(weak_pieces);
+            let key = material_key(counts);
+            // Symmetric material only needs one entry.
+            if !endgames.iter().any(|(other_key, _)| *other_key == key) {
+                endgames.push((key, Endgame { name, strong_side, kind }));
+            }
+        }
+    };
+    register("KK", "", "", EndgameKind::Evaluation(draw));
+    register("KNK", "N", "", EndgameKind::Evaluation(draw));
+    register("KBK", "B", "", EndgameKind::Evaluation(draw));
+    register("KNNK", "NN", "", EndgameKind::Evaluation(draw));
+    register("KQK", "Q", "", EndgameKind::Evaluation(mate_drive));
+    register("KRK", "R", "", EndgameKind::Evaluation(mate_drive));
+    register("KBNK", "BN", "", EndgameKind::Evaluation(kbnk));
+    register("KPK", "P", "", EndgameKind::Evaluation(kpk));
+    for pawns in ["P", "PP", "PPP", "PPPP", "PPPPP", "PPPPPP", "PPPPPPP", "PPPPPPPP"] {
+        register("KBPsK", &format!("B{}", pawns), "", EndgameKind::Scale(wrong_rook_pawn));
+    }
+    endgames.sort_by_key(|(key, _)| *key);
+    unsafe { ENDGAMES = endgames };
+}
+
+// Piece letters other than the king, e.g. "BN".
+fn piece_counts(pieces: &str) -> [u8; 5] {
+    let mut counts = [0; 5];
+    for piece in pieces.chars() {
+        let piece_type = match piece {
+            'Q' => PieceType::Queen,
+            'R' => PieceType::Rook,
+            'B' => PieceType::Bishop,
+            'N' => PieceType::Knight,
+            'P' => PieceType::Pawn,
+            _ => unreachable!(),
+        };
+        counts[piece_type as usize - 1] += 1;
+    }
+    counts
+}
+
+pub fn material_key(counts: [[u8; 5]; 2]) -> MaterialKey {
+    let mut key = 0;
+    for (side, side_counts) in counts.iter().enumerate() {
+        for (piece, count) in side_counts.iter().enumerate() {
+            key |= (*count as MaterialKey) << ((side * 5 + piece) * 4);
+        }
+    }
+    key
+}
+
+impl State {
+    pub fn material_key(&self) -> MaterialKey {
+        let mut counts = [[0; 5]; 2];
+        for color in COLORS {
+            for piece_type in 1..6 {
+                counts[color as usize][piece_type as usize - 1] = bit_count(unsafe { self.get_piece_board_raw(color as u8, piece_type) }) as u8;
+            }
+        }
+        material_key(counts)
+    }
+
+    pub fn endgame(&self) -> Option<Endgame> {
+        if self.incremental_eval.phase > ENDGAME_MAX_PHASE {
+            return None;
+        }
+        let key = self.material_key();
+        unsafe { ENDGAMES.binary_search_by_key(&key, |(endgame_key, _)| *endgame_key).ok().map(|index| ENDGAMES[index].1) }
+    }
+
+    // How much of a white relative eval the side it favours can actually convert.
+    pub fn scale_factor(&self, eval: Evaluation, endgame: Option<Endgame>) -> Evaluation {
+        if eval == 0 {
+            return SCALE_FACTOR_NORMAL;
+        }
+        let strong_side = if eval > 0 { Color::White } else { Color::Black };
+        let mut scale_factor = SCALE_FACTOR_NORMAL;
+        if let Some(Endgame { kind: EndgameKind::Scale(scale), .. }) = endgame.filter(|endgame| endgame.strong_side == strong_side) {
+            scale_factor = scale(self, strong_side);
+        }
+
+        // Without pawns less than a rook's worth of extra material rarely wins, and a lone minor never does.
+        let strong_material = self.non_pawn_material(strong_side);
+        let weak_material = self.non_pawn_material(strong_side.other());
+        if self.get_piece_board(strong_side, PieceType::Pawn) == EMPTY_BITBOARD && strong_material - weak_material <= BISHOP_EVAL {
+            scale_factor = scale_factor.min(if strong_material < ROOK_EVAL { 0 } else { SCALE_FACTOR_NO_PAWNS });
+        }
+
+        let white_bishops = self.get_piece_board(Color::White, PieceType::Bishop);
+        let black_bishops = self.get_piece_board(Color::Black, PieceType::Bishop);
+        if bit_count(white_bishops) == 1 && bit_count(black_bishops) == 1
+            && (white_bishops & DARK_SQUARES == EMPTY_BITBOARD) != (black_bishops & DARK_SQUARES == EMPTY_BITBOARD) {
+            let opposite_bishops_scale = if strong_material == BISHOP_EVAL && weak_material == BISHOP_EVAL {
+                SCALE_FACTOR_OPPOSITE_BISHOPS
+            } else {
+                SCALE_FACTOR_OPPOSITE_BISHOPS_WITH_PIECES
+            };
+            scale_factor = scale_factor.min(opposite_bishops_scale);
+        }
+        scale_factor
+    }
+
+    // Material excluding pawns at the fixed piece values, so the endgame rules don't move with tuning.
+    pub fn non_pawn_material(&self, color: Color) -> Evaluation {
+        let mut material = 0;
+        for piece_type in 1..5 {
+            material += bit_count(unsafe { self.get_piece_board_raw(color as u8, piece_type) }) as Evaluation * PIECE_EVAL_TABLE[piece_type as usize];
+        }
+        material
+    }
+}
+
+fn king_square(state: &State, color: Color) -> Square {
+    get_lsb(state.get_piece_board(color, PieceType::King))
+}
+
+fn draw(_: &State, _: Color) -> Evaluation {
+    0
+}
+
+// Distance from the centre, 0 on the four central squares and 6 in the corners.
+fn edge_distance(square: Square) -> Evaluation {
+    let file_distance = (2 * file(square) as Evaluation - 7).abs() / 2;
+    let rank_distance = (2 * rank(square) as Evaluation - 7).abs() / 2;
+    file_distance + rank_distance
+}
+
+// Wins where the strong side only needs to push the lone king to the edge and bring its own king closer.
+fn mate_drive(state: &State, strong_side: Color) -> Evaluation {
+    let strong_king = king_square(state, strong_side);
+    let weak_king = king_square(state, strong_side.other());
+    state.non_pawn_material(strong_side) + KNOWN_WIN_EVAL + edge_distance(weak_king) * PUSH_TO_EDGE_EVAL
+        + (7 - square_distance(strong_king, weak_king) as Evaluation) * PUSH_CLOSE_EVAL
+}
+
+// Mate can only be forced in a corner of the bishop's colour.
+fn kbnk(state: &State, strong_side: Color) -> Evaluation {
+    let weak_king = king_square(state, strong_side.other());
+    let corners = if state.get_piece_board(strong_side, PieceType::Bishop) & DARK_SQUARES != EMPTY_BITBOARD { [0, 63] } else { [7, 56] };
+    let corner_distance = corners.iter().map(|corner| square_distance(*corner, weak_king)).min().unwrap();
+    mate_drive(state, strong_side) + (7 - corner_distance as Evaluation) * PUSH_TO_CORNER_EVAL
+}
+
+// Exact result from the bitbase, wins are worth more the further the pawn has come so the search makes progress.
+fn kpk(state: &State, strong_side: Color) -> Evaluation {
+    let pawn = get_lsb(state.get_piece_board(strong_side, PieceType::Pawn));
+    let strong_to_move = state.turn == strong_side;
+    if kpk_probe(strong_side, king_square(state, strong_side), king_square(state, strong_side.other()), pawn, strong_to_move) {
+        KNOWN_WIN_EVAL + PAWN_EVAL + relative_rank(strong_side, pawn) as Evaluation * PUSH_PAWN_EVAL
+    } else {
+        0
+    }
+}
+
+// Rook pawns with a bishop that doesn't control the promotion square can't drive the defending king out of the corner.
+fn wrong_rook_pawn(state: &State, strong_side: Color) -> Evaluation {
+    let pawns = state.get_piece_board(strong_side, PieceType::Pawn);
+    let promotion_file = if pawns & !FILES[0] == EMPTY_BITBOARD {
+        0
+    } else if pawns & !FILES[7] == EMPTY_BITBOARD {
+        7
+    } else {
+        return SCALE_FACTOR_NORMAL;
+    };
+    let promotion_square = match strong_side { Color::White => 56 + promotion_file, Color::Black => promotion_file };
+    let dark_bishop = state.get_piece_board(strong_side, PieceType::Bishop) & DARK_SQUARES != EMPTY_BITBOARD;
+    let dark_promotion = board_from_square(promotion_square) & DARK_SQUARES != EMPTY_BITBOARD;
+    if dark_bishop != dark_promotion && square_distance(king_square(state, strong_side.other()), promotion_square) <= 1 {
+        0
+    } else {
+        SCALE_FACTOR_NORMAL
+    }
+}
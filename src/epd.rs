@@ -0,0 +1,190 @@
+use std::{fs, time::{Duration, Instant}};
+
+use crate::{move_pick::clear_history_tables, parsing::{parse_fen_string, uci_move_from_string}, r#move::Move, san::san_move_from_string, search::{Depth, SearchLimits}, state::State, transposition::clear_ttable, worker::Worker};
+
+const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(1);
+
+pub struct EpdConfig {
+    pub path: String,
+    pub limits: SearchLimits,
+}
+
+// A position from an EPD file. The opcodes the runner uses are pulled out, every opcode is kept in operations in the
+// order it was written with quotes removed from string operands.
+pub struct EpdPosition {
+    pub state: State,
+    pub id: Option<String>,
+    pub best_moves: Vec<Move>,
+    pub avoid_moves: Vec<Move>,
+    pub comment: Option<String>,
+    pub operations: Vec<(String, Vec<String>)>,
+}
+
+impl EpdPosition {
+    // Positions without bm or am have nothing to check the search against.
+    pub fn is_scored(&self) -> bool {
+        !self.best_moves.is_empty() || !self.avoid_moves.is_empty()
+    }
+
+    pub fn is_solved_by(&self, m: Move) -> bool {
+        (self.best_moves.is_empty() || self.best_moves.contains(&m)) && !self.avoid_moves.contains(&m)
+    }
+}
+
+// Expects the tokens after epd, in the form <suite file> [time <ms>] [depth <plies>] [nodes <count>]
+pub fn parse_epd_command(tokens: &[&str]) -> Result<EpdConfig, String> {
+    let path = tokens.first().ok_or("Expected a suite file")?.to_string();
+    let mut limits = SearchLimits::default();
+    let mut index = 1;
+    while index < tokens.len() {
+        let value = tokens.get(index + 1).ok_or(format!("Expected a value after {}", tokens[index]))?;
+        let invalid = || format!("Invalid {} limit: {}", tokens[index], value);
+        match tokens[index] {
+            "time" => limits.time = Some(Duration::from_millis(value.parse().map_err(|_| invalid())?)),
+            "depth" => limits.depth = Some(value.parse::<Depth>().map_err(|_| invalid())?),
+            "nodes" => limits.nodes = Some(value.parse().map_err(|_| invalid())?),
+            other => return Err(format!("Unknown limit: {}", other)),
+        }
+        index += 2;
+    }
+    if limits.time.is_none() && limits.depth.is_none() && limits.nodes.is_none() {
+        limits.time = Some(DEFAULT_MOVE_TIME);
+    }
+    Ok(EpdConfig { path, limits })
+}
+
+// The four FEN fields without move counters, then operations each ended by a semicolon, for example
+// 2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";
+pub fn parse_epd_line(line: &str) -> Result<EpdPosition, String> {
+    let fields: Vec<&str> = line.split_whitespace().take(4).collect();
+    if fields.len() < 4 {
+        return Err("Expected board, side to move, castle availability and en passant fields".to_string());
+    }
+    let mut state = parse_fen_string(fields.join(" ")).map_err(|err| err.to_string())?;
+    let mut rest = line.trim_start();
+    for _ in 0..4 {
+        rest = rest[rest.find(char::is_whitespace).unwrap_or(rest.len())..].trim_start();
+    }
+
+    let (mut id, mut best_moves, mut avoid_moves, mut comment, mut operations) = (None, Vec::new(), Vec::new(), None, Vec::new());
+    for operation in split_operations(rest)? {
+        let Some((opcode, operands)) = operation.split_first() else { continue };
+        let parse_moves = |state: &State| -> Result<Vec<Move>, String> {
+            operands.iter().map(|operand| epd_move_from_string(state, operand).ok_or(format!("Illegal move {} in {}", operand, opcode))).collect()
+        };
+        match opcode.as_str() {
+            "bm" => best_moves = parse_moves(&state)?,
+            "am" => avoid_moves = parse_moves(&state)?,
+            "id" => id = operands.first().cloned(),
+            "c0" => comment = operands.first().cloned(),
+            "hmvc" => if let Some(clock) = operands.first().and_then(|clock| clock.parse().ok()) {
+                state.half_move_clock = clock;
+            },
+            "fmvn" => if let Some(number) = operands.first().and_then(|number| number.parse::<u16>().ok()) {
+                state.ply = number.saturating_sub(1).saturating_mul(2) + state.turn as u16;
+            },
+            _ => (),
+        }
+        operations.push((opcode.clone(), operands.to_vec()));
+    }
+    Ok(EpdPosition { state, id, best_moves, avoid_moves, comment, operations })
+}
+
+// Splits on semicolons outside of quoted strings, each operation split into its opcode and operands.
+fn split_operations(operations: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut result = Vec::new();
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut chars = operations.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => string.push(c),
+                        None => return Err("Unterminated string operand".to_string()),
+                    }
+                }
+                tokens.push(string);
+            },
+            ';' | ' ' | '\t' => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+                if c == ';' && !tokens.is_empty() {
+                    result.push(std::mem::take(&mut tokens));
+                }
+            },
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    // The last semicolon is sometimes left out
+    if !tokens.is_empty() {
+        result.push(tokens);
+    }
+    Ok(result)
+}
+
+// Suites give moves in SAN, coordinate notation is also accepted.
+fn epd_move_from_string(state: &State, move_string: &str) -> Option<Move> {
+    san_move_from_string(state, move_string).or_else(|| uci_move_from_string(state, move_string))
+}
+
+pub fn run_epd_suite(config: EpdConfig) -> Result<(), String> {
+    let contents = fs::read_to_string(&config.path).map_err(|err| format!("Unable to read suite file {}: {}", config.path, err))?;
+    let start = Instant::now();
+    let mut solved = 0;
+    let mut scored = 0;
+    let mut total_nodes = 0;
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut position = match parse_epd_line(line) {
+            Ok(position) => position,
+            Err(err) => {
+                println!("Skipping line {}: {}", line_number + 1, err);
+                continue;
+            },
+        };
+        // Every position starts from empty tables so results don't depend on the order of the suite.
+        clear_ttable();
+        clear_history_tables();
+        let mut worker = Worker::new();
+        let best_move = worker.limited_search(&mut position.state, config.limits, false);
+        total_nodes += worker.nodes_searched;
+
+        let name = position.id.clone().unwrap_or_else(|| format!("Line {}", line_number + 1));
+        let played = position.state.san_string_move(best_move);
+        if !position.is_scored() {
+            println!("{}: played {}", name, played);
+            continue;
+        }
+        scored += 1;
+        let result = if position.is_solved_by(best_move) {
+            solved += 1;
+            "solved"
+        } else {
+            "failed"
+        };
+        let mut expected = |moves: &[Move]| moves.iter().map(|m| position.state.san_string_move(*m)).collect::<Vec<String>>().join(" ");
+        let mut expected_string = String::new();
+        if !position.best_moves.is_empty() {
+            expected_string += &format!(" bm {}", expected(&position.best_moves));
+        }
+        if !position.avoid_moves.is_empty() {
+            expected_string += &format!(" am {}", expected(&position.avoid_moves));
+        }
+        println!("{}: {} played {},{}", name, result, played, expected_string);
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    let percent = if scored == 0 { 0.0 } else { solved as f64 * 100.0 / scored as f64 };
+    println!("Solved {} of {} ({:.1}%) in {:.1}s, {} nodes", solved, scored, percent, elapsed, total_nodes);
+    Ok(())
+}
@@ -0,0 +1,425 @@
+use std::{fmt, fs};
+
+use crate::{evaluation::{piece_square_table_init, Evaluation, CENTI_PAWN, MATE_VALUE_CUTOFF}, pawn_table::clear_pawn_table, score::Score};
+
+const PIECE_NAMES: [&str; 6] = ["king", "queen", "rook", "bishop", "knight", "pawn"];
+
+// Largest magnitude a single parameter may take, in centipawns.
+pub const MAX_PARAMETER_CENTI_PAWNS: i32 = MATE_VALUE_CUTOFF / CENTI_PAWN;
+
+// Piece square tables in centipawns from white's point of view, listed from a1 to h8.
+const DEFAULT_MIDGAME_TABLES: [[i32; 64]; 6] = [
+    // King
+    [
+        20, 30, 10, 0, 0, 10, 30, 20,
+        20, 20, 0, 0, 0, 0, 20, 20,
+        -10, -20, -20, -20, -20, -20, -20, -10,
+        -20, -30, -30, -40, -40, -30, -30, -20,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+    ],
+    // Queen
+    [
+        -20, -10, -10, -5, -5, -10, -10, -20,
+        -10, 0, 5, 0, 0, 0, 0, -10,
+        -10, 5, 5, 5, 5, 5, 0, -10,
+        0, 0, 5, 5, 5, 5, 0, -5,
+        -5, 0, 5, 5, 5, 5, 0, -5,
+        -10, 0, 5, 5, 5, 5, 0, -10,
+        -10, 0, 0, 0, 0, 0, 0, -10,
+        -20, -10, -10, -5, -5, -10, -10, -20,
+    ],
+    // Rook
+    [
+        0, 0, 0, 5, 5, 0, 0, 0,
+        -5, 0, 0, 0, 0, 0, 0, -5,
+        -5, 0, 0, 0, 0, 0, 0, -5,
+        -5, 0, 0, 0, 0, 0, 0, -5,
+        -5, 0, 0, 0, 0, 0, 0, -5,
+        -5, 0, 0, 0, 0, 0, 0, -5,
+        5, 10, 10, 10, 10, 10, 10, 5,
+        0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+    // Bishop
+    [
+        -20, -10, -10, -10, -10, -10, -10, -20,
+        -10, 5, 0, 0, 0, 0, 5, -10,
+        -10, 10, 10, 10, 10, 10, 10, -10,
+        -10, 0, 10, 10, 10, 10, 0, -10,
+        -10, 5, 5, 10, 10, 5, 5, -10,
+        -10, 0, 5, 10, 10, 5, 0, -10,
+        -10, 0, 0, 0, 0, 0, 0, -10,
+        -20, -10, -10, -10, -10, -10, -10, -20,
+    ],
+    // Knight
+    [
+        -50, -40, -30, -30, -30, -30, -40, -50,
+        -40, -20, 0, 5, 5, 0, -20, -40,
+        -30, 5, 10, 15, 15, 10, 5, -30,
+        -30, 0, 15, 20, 20, 15, 0, -30,
+        -30, 5, 15, 20, 20, 15, 5, -30,
+        -30, 0, 10, 15, 15, 10, 0, -30,
+        -40, -20, 0, 0, 0, 0, -20, -40,
+        -50, -40, -30, -30, -30, -30, -40, -50,
+    ],
+    // Pawn
+    [
+        0, 0, 0, 0, 0, 0, 0, 0,
+        5, 10, 10, -20, -20, 10, 10, 5,
+        5, -5, -10, 0, 0, -10, -5, 5,
+        0, 0, 0, 20, 20, 0, 0, 0,
+        5, 5, 10, 25, 25, 10, 5, 5,
+        10, 10, 20, 30, 30, 20, 10, 10,
+        50, 50, 50, 50, 50, 50, 50, 50,
+        0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+];
+
+const DEFAULT_ENDGAME_TABLES: [[i32; 64]; 6] = [
+    // King
+    [
+        -50, -30, -30, -30, -30, -30, -30, -50,
+        -30, -30, 0, 0, 0, 0, -30, -30,
+        -30, -10, 20, 30, 30, 20, -10, -30,
+        -30, -10, 30, 40, 40, 30, -10, -30,
+        -30, -10, 30, 40, 40, 30, -10, -30,
+        -30, -10, 20, 30, 30, 20, -10, -30,
+        -30, -20, -10, 0, 0, -10, -20, -30,
+        -50, -40, -30, -20, -20, -30, -40, -50,
+    ],
+    // Queen
+    [
+        -20, -10, -10, -5, -5, -10, -10, -20,
+        -10, 0, 5, 0, 0, 0, 0, -10,
+        -10, 5, 5, 5, 5, 5, 0, -10,
+        0, 0, 5, 5, 5, 5, 0, -5,
+        -5, 0, 5, 5, 5, 5, 0, -5,
+        -10, 0, 5, 5, 5, 5, 0, -10,
+        -10, 0, 0, 0, 0, 0, 0, -10,
+        -20, -10, -10, -5, -5, -10, -10, -20,
+    ],
+    // Rook
+    [
+        0, 0, 0, 5, 5, 0, 0, 0,
+        -5, 0, 0, 0, 0, 0, 0, -5,
+        -5, 0, 0, 0, 0, 0, 0, -5,
+        -5, 0, 0, 0, 0, 0, 0, -5,
+        -5, 0, 0, 0, 0, 0, 0, -5,
+        -5, 0, 0, 0, 0, 0, 0, -5,
+        5, 10, 10, 10, 10, 10, 10, 5,
+        0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+    // Bishop
+    [
+        -20, -10, -10, -10, -10, -10, -10, -20,
+        -10, 5, 0, 0, 0, 0, 5, -10,
+        -10, 10, 10, 10, 10, 10, 10, -10,
+        -10, 0, 10, 10, 10, 10, 0, -10,
+        -10, 5, 5, 10, 10, 5, 5, -10,
+        -10, 0, 5, 10, 10, 5, 0, -10,
+        -10, 0, 0, 0, 0, 0, 0, -10,
+        -20, -10, -10, -10, -10, -10, -10, -20,
+    ],
+    // Knight
+    [
+        -50, -40, -30, -30, -30, -30, -40, -50,
+        -40, -20, 0, 5, 5, 0, -20, -40,
+        -30, 5, 10, 15, 15, 10, 5, -30,
+        -30, 0, 15, 20, 20, 15, 0, -30,
+        -30, 5, 15, 20, 20, 15, 5, -30,
+        -30, 0, 10, 15, 15, 10, 0, -30,
+        -40, -20, 0, 0, 0, 0, -20, -40,
+        -50, -40, -30, -30, -30, -30, -40, -50,
+    ],
+    // Pawn
+    [
+        0, 0, 0, 0, 0, 0, 0, 0,
+        -30, -30, -30, -30, -30, -30, -30, -30,
+        -10, -10, -10, -10, -10, -10, -10, -10,
+        0, 0, 0, 0, 0, 0, 0, 0,
+        20, 20, 20, 20, 20, 20, 20, 20,
+        40, 40, 40, 40, 40, 40, 40, 40,
+        60, 60, 60, 60, 60, 60, 60, 60,
+        0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+];
+
+// Midgame and endgame pairs in centipawns indexed like PIECE_EVAL_TABLE.
+const DEFAULT_PIECE_VALUES: [(i32, i32); 6] = [(0, 0), (900, 900), (500, 500), (330, 330), (300, 300), (100, 100)];
+// Indexed by relative rank.
+const DEFAULT_PASSED_RANK: [(i32, i32); 8] = [(0, 0), (5, 10), (10, 15), (20, 30), (35, 55), (60, 95), (90, 140), (0, 0)];
+// Index 0 is a file without a pawn.
+const DEFAULT_KING_SHELTER: [(i32, i32); 8] = [(-30, -5), (10, 0), (5, 0), (-5, 0), (-10, 0), (-15, 0), (-20, 0), (0, 0)];
+const DEFAULT_KING_STORM: [(i32, i32); 8] = [(0, 0), (-5, 0), (-30, -5), (-20, -5), (-10, 0), (-5, 0), (0, 0), (0, 0)];
+
+// Midgame values, the endgame ones are a quarter of these as king attacks matter far less once most pieces are gone.
+const DEFAULT_KING_SAFETY_TABLE: [i32; 100] = [
+    0, 0, 1, 2, 3, 5, 7, 9, 12, 15,
+    18, 22, 26, 30, 35, 39, 44, 50, 56, 62,
+    68, 75, 82, 85, 89, 97, 105, 113, 122, 131,
+    140, 150, 169, 180, 191, 202, 213, 225, 237, 248,
+    260, 272, 283, 295, 307, 319, 330, 342, 354, 366,
+    377, 389, 401, 412, 424, 436, 448, 459, 471, 483,
+    494, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+];
+
+// Every weight used by the classical eval, scaled by CENTI_PAWN like any other Evaluation. Parameter files store
+// them in centipawns. Everything except the piece square tables is a midgame and endgame pair.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EvalParams {
+    pub piece_values: [Score; 6],
+    // Indexed by piece type then square, from white's point of view.
+    pub midgame_tables: [[Evaluation; 64]; 6],
+    pub endgame_tables: [[Evaluation; 64]; 6],
+    pub mobility: Score,
+    pub pawn_isolated: Score,
+    pub pawn_doubled: Score,
+    // Bonus for the number of attack units against the enemy king.
+    pub king_safety: [Score; 100],
+    pub bishop_pair: Score,
+    pub rook_open_file: Score,
+    pub rook_semi_open_file: Score,
+    pub rook_seventh: Score,
+    // Knight on the 4th to 6th rank, defended by a pawn and out of reach of enemy pawns.
+    pub knight_outpost: Score,
+    pub pawn_backward: Score,
+    // Pawn defended by another pawn or standing next to one.
+    pub pawn_connected: Score,
+    // Passed pawn bonus by relative rank.
+    pub passed_rank: [Score; 8],
+    // Per square of king distance to the square in front of a passed pawn, scaled up as the pawn advances.
+    pub passed_own_king_distance: Score,
+    pub passed_enemy_king_distance: Score,
+    // Indexed by the relative rank of the closest own pawn in front of the king on each of the three files around it,
+    // 0 when there is none.
+    pub king_shelter: [Score; 8],
+    // Same as king_shelter for the closest enemy pawn.
+    pub king_storm: [Score; 8],
+    // Pieces attacked by a pawn, rooks and queens attacked by a minor piece and queens attacked by a rook.
+    pub threat_by_pawn: Score,
+    pub threat_by_minor: Score,
+    pub threat_by_rook: Score,
+}
+
+pub static mut EVAL_PARAMS: EvalParams = EvalParams::default_params();
+
+impl EvalParams {
+    pub const fn default_params() -> EvalParams {
+        let mut params = EvalParams {
+            piece_values: [Score::ZERO; 6],
+            midgame_tables: [[0; 64]; 6],
+            endgame_tables: [[0; 64]; 6],
+            mobility: centi_pawn_score(1, 2),
+            pawn_isolated: centi_pawn_score(-25, -30),
+            pawn_doubled: centi_pawn_score(-15, -20),
+            king_safety: [Score::ZERO; 100],
+            bishop_pair: centi_pawn_score(30, 50),
+            rook_open_file: centi_pawn_score(40, 15),
+            rook_semi_open_file: centi_pawn_score(20, 10),
+            rook_seventh: centi_pawn_score(20, 30),
+            knight_outpost: centi_pawn_score(25, 15),
+            pawn_backward: centi_pawn_score(-10, -15),
+            pawn_connected: centi_pawn_score(8, 10),
+            passed_rank: [Score::ZERO; 8],
+            passed_own_king_distance: centi_pawn_score(0, -3),
+            passed_enemy_king_distance: centi_pawn_score(0, 5),
+            king_shelter: [Score::ZERO; 8],
+            king_storm: [Score::ZERO; 8],
+            threat_by_pawn: centi_pawn_score(40, 30),
+            threat_by_minor: centi_pawn_score(25, 20),
+            threat_by_rook: centi_pawn_score(30, 20),
+        };
+        let mut piece_type = 0;
+        while piece_type < 6 {
+            params.piece_values[piece_type] = centi_pawn_score(DEFAULT_PIECE_VALUES[piece_type].0, DEFAULT_PIECE_VALUES[piece_type].1);
+            let mut square = 0;
+            while square < 64 {
+                params.midgame_tables[piece_type][square] = DEFAULT_MIDGAME_TABLES[piece_type][square] * CENTI_PAWN;
+                params.endgame_tables[piece_type][square] = DEFAULT_ENDGAME_TABLES[piece_type][square] * CENTI_PAWN;
+                square += 1;
+            }
+            piece_type += 1;
+        }
+        let mut units = 0;
+        while units < 100 {
+            params.king_safety[units] = centi_pawn_score(DEFAULT_KING_SAFETY_TABLE[units], DEFAULT_KING_SAFETY_TABLE[units] / 4);
+            units += 1;
+        }
+        let mut rank = 0;
+        while rank < 8 {
+            params.passed_rank[rank] = centi_pawn_score(DEFAULT_PASSED_RANK[rank].0, DEFAULT_PASSED_RANK[rank].1);
+            params.king_shelter[rank] = centi_pawn_score(DEFAULT_KING_SHELTER[rank].0, DEFAULT_KING_SHELTER[rank].1);
+            params.king_storm[rank] = centi_pawn_score(DEFAULT_KING_STORM[rank].0, DEFAULT_KING_STORM[rank].1);
+            rank += 1;
+        }
+        params
+    }
+
+    // Parses a parameter file. Each line is `name = value` or `name = [values]` with arrays allowed to span lines and
+    // # starting a comment. Parameters missing from the file keep their default value. Tapered parameters given a
+    // single value per entry, as in files written before the eval was tapered, use it for both midgame and endgame.
+    pub fn parse(text: &str) -> Result<EvalParams, String> {
+        let mut params = EvalParams::default_params();
+        let mut lines = text.lines().map(|line| line.split('#').next().unwrap_or_default().trim());
+        while let Some(line) = lines.next() {
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = line.split_once('=').ok_or(format!("Expected name = value: {}", line))?;
+            let mut value = value.trim().to_string();
+            while value.starts_with('[') && !value.ends_with(']') {
+                value += " ";
+                value += lines.next().ok_or(format!("Unterminated array for {}", name.trim()))?;
+            }
+            let values = parse_values(&value).map_err(|err| format!("{}: {}", name.trim(), err))?;
+            match params.tapered_parameter(name.trim()) {
+                Some(target) => set_scores(name.trim(), target, &values)?,
+                None => params.set_parameter(name.trim(), &values)?,
+            }
+        }
+        Ok(params)
+    }
+
+    fn set_parameter(&mut self, name: &str, values: &[Evaluation]) -> Result<(), String> {
+        // Only the piece square tables are left once the tapered parameters are handled.
+        let (phase, piece) = name.split_once('_').ok_or(format!("Unknown parameter: {}", name))?;
+        let piece_type = PIECE_NAMES.iter().position(|piece_name| *piece_name == piece).ok_or(format!("Unknown parameter: {}", name))?;
+        let target: &mut [Evaluation] = match phase {
+            "midgame" => &mut self.midgame_tables[piece_type],
+            "endgame" => &mut self.endgame_tables[piece_type],
+            _ => return Err(format!("Unknown parameter: {}", name)),
+        };
+        if target.len() != values.len() {
+            return Err(format!("{} expects {} values, found {}", name, target.len(), values.len()));
+        }
+        target.copy_from_slice(values);
+        Ok(())
+    }
+
+    // Midgame and endgame pairs, stored in files as alternating midgame and endgame values.
+    pub fn tapered_parameter(&mut self, name: &str) -> Option<&mut [Score]> {
+        Some(match name {
+            "piece_values" => &mut self.piece_values,
+            "mobility" => std::slice::from_mut(&mut self.mobility),
+            "pawn_isolated" => std::slice::from_mut(&mut self.pawn_isolated),
+            "pawn_doubled" => std::slice::from_mut(&mut self.pawn_doubled),
+            "king_safety" => &mut self.king_safety,
+            "bishop_pair" => std::slice::from_mut(&mut self.bishop_pair),
+            "rook_open_file" => std::slice::from_mut(&mut self.rook_open_file),
+            "rook_semi_open_file" => std::slice::from_mut(&mut self.rook_semi_open_file),
+            "rook_seventh" => std::slice::from_mut(&mut self.rook_seventh),
+            "knight_outpost" => std::slice::from_mut(&mut self.knight_outpost),
+            "pawn_backward" => std::slice::from_mut(&mut self.pawn_backward),
+            "pawn_connected" => std::slice::from_mut(&mut self.pawn_connected),
+            "passed_rank" => &mut self.passed_rank,
+            "passed_own_king_distance" => std::slice::from_mut(&mut self.passed_own_king_distance),
+            "passed_enemy_king_distance" => std::slice::from_mut(&mut self.passed_enemy_king_distance),
+            "king_shelter" => &mut self.king_shelter,
+            "king_storm" => &mut self.king_storm,
+            "threat_by_pawn" => std::slice::from_mut(&mut self.threat_by_pawn),
+            "threat_by_minor" => std::slice::from_mut(&mut self.threat_by_minor),
+            "threat_by_rook" => std::slice::from_mut(&mut self.threat_by_rook),
+            _ => return None,
+        })
+    }
+
+    pub const TAPERED_PARAMETER_NAMES: [&str; 20] = [
+        "piece_values", "mobility", "pawn_isolated", "pawn_doubled", "king_safety", "bishop_pair", "rook_open_file", "rook_semi_open_file", "rook_seventh", "knight_outpost", "pawn_backward", "pawn_connected",
+        "passed_rank", "passed_own_king_distance", "passed_enemy_king_distance", "king_shelter", "king_storm",
+        "threat_by_pawn", "threat_by_minor", "threat_by_rook",
+    ];
+}
+
+fn set_scores(name: &str, target: &mut [Score], values: &[Evaluation]) -> Result<(), String> {
+    if values.len() == target.len() {
+        for (score, value) in target.iter_mut().zip(values) {
+            *score = Score::new(*value, *value);
+        }
+        return Ok(());
+    }
+    if target.len() * 2 != values.len() {
+        return Err(format!("{} expects {} or {} values, found {}", name, target.len(), target.len() * 2, values.len()));
+    }
+    for (score, pair) in target.iter_mut().zip(values.chunks_exact(2)) {
+        *score = Score::new(pair[0], pair[1]);
+    }
+    Ok(())
+}
+
+const fn centi_pawn_score(midgame: i32, endgame: i32) -> Score {
+    Score::new(midgame * CENTI_PAWN, endgame * CENTI_PAWN)
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        Self::default_params()
+    }
+}
+
+// Values are written in centipawns and scaled on the way in. Anything past the mate cutoff can only be a mistake and
+// would overflow once scaled.
+fn parse_values(value: &str) -> Result<Vec<Evaluation>, String> {
+    value.trim_start_matches('[').trim_end_matches(']').split(',').map(str::trim).filter(|value| !value.is_empty())
+        .map(|value| {
+            let centi_pawns = value.parse::<i32>().map_err(|_| format!("Invalid value {}", value))?;
+            if !(-MAX_PARAMETER_CENTI_PAWNS..=MAX_PARAMETER_CENTI_PAWNS).contains(&centi_pawns) {
+                return Err(format!("Value {} out of range", value));
+            }
+            Ok(centi_pawns * CENTI_PAWN)
+        })
+        .collect()
+}
+
+fn format_values(values: &[Evaluation], per_line: usize) -> String {
+    let rows: Vec<String> = values.chunks(per_line).map(|row| {
+        row.iter().map(|value| (value / CENTI_PAWN).to_string()).collect::<Vec<String>>().join(", ")
+    }).collect();
+    if rows.len() == 1 {
+        format!("[{}]", rows[0])
+    } else {
+        format!("[\n    {},\n]", rows.join(",\n    "))
+    }
+}
+
+impl fmt::Display for EvalParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# Rhobos evaluation parameters in centipawns, tables are listed from a1 to h8 for white")?;
+        writeln!(f, "# Everything but the tables is a list of midgame and endgame pairs")?;
+        for (piece_type, name) in PIECE_NAMES.iter().enumerate() {
+            writeln!(f, "midgame_{} = {}", name, format_values(&self.midgame_tables[piece_type], 8))?;
+        }
+        for (piece_type, name) in PIECE_NAMES.iter().enumerate() {
+            writeln!(f, "endgame_{} = {}", name, format_values(&self.endgame_tables[piece_type], 8))?;
+        }
+        let mut params = self.clone();
+        for name in EvalParams::TAPERED_PARAMETER_NAMES {
+            let values: Vec<Evaluation> = params.tapered_parameter(name).unwrap().iter().flat_map(|score| [score.midgame(), score.endgame()]).collect();
+            writeln!(f, "{} = {}", name, format_values(&values, 20))?;
+        }
+        Ok(())
+    }
+}
+
+#[inline(always)]
+pub fn eval_params() -> &'static EvalParams {
+    unsafe { &EVAL_PARAMS }
+}
+
+// Replaces the parameters used by the eval. Existing states need their incremental eval refreshed afterwards.
+pub fn set_eval_params(params: EvalParams) {
+    unsafe { EVAL_PARAMS = params };
+    piece_square_table_init();
+    clear_pawn_table();
+}
+
+#[cold]
+pub fn load_eval_params(path: &str) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("Unable to read parameter file {}: {}", path, err))?;
+    set_eval_params(EvalParams::parse(&text)?);
+    Ok(())
+}
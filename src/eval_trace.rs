@@ -0,0 +1,137 @@
+use std::fmt;
+
+use crate::{bitboard::{Color, COLORS}, endgame::{Endgame, EndgameKind, SCALE_FACTOR_NORMAL}, evaluation::{Evaluation, CENTI_PAWN, TOTAL_PHASE_VALUE}, nnue::use_nnue, state::State};
+
+const PIECE_NAMES: [&str; 6] = ["King", "Queen", "Rook", "Bishop", "Knight", "Pawn"];
+
+// Breakdown of the hand crafted evaluation. Per side arrays are indexed by color and hold positive values for that
+// side, everything else is from white's perspective.
+#[derive(Clone, Default, Debug)]
+pub struct EvalTrace {
+    pub material: [Evaluation; 2],
+    pub midgame_table: [Evaluation; 2],
+    pub endgame_table: [Evaluation; 2],
+    // Midgame weight of the table blend, out of TOTAL_PHASE_VALUE.
+    pub phase: u8,
+    // Both sides' tables blended by phase.
+    pub table: Evaluation,
+    // Number of moves for each piece type, indexed like PIECE_EVAL_TABLE.
+    pub mobility_counts: [[u32; 6]; 2],
+    pub mobility: [Evaluation; 2],
+    // Attack units against the enemy king and the bonus they give.
+    pub king_attack_units: [u32; 2],
+    pub king_safety: [Evaluation; 2],
+    pub isolated_pawns: [u32; 2],
+    pub doubled_pawns: [u32; 2],
+    pub backward_pawns: [u32; 2],
+    pub connected_pawns: [u32; 2],
+    pub pawn_structure: [Evaluation; 2],
+    pub passed_pawns: [u32; 2],
+    // Passed pawn count by relative rank.
+    pub passed_ranks: [[u32; 8]; 2],
+    // Weighted king distances, see passed_king_distances.
+    pub passed_own_king_distance: [u32; 2],
+    pub passed_enemy_king_distance: [u32; 2],
+    pub passed: [Evaluation; 2],
+    pub bishop_pairs: [u32; 2],
+    pub rooks_open_file: [u32; 2],
+    pub rooks_semi_open_file: [u32; 2],
+    pub rooks_seventh: [u32; 2],
+    pub knight_outposts: [u32; 2],
+    pub pieces: [Evaluation; 2],
+    // Files around the king by relative rank of the closest pawn, see king_shelter_counts.
+    pub king_shelter: [[u32; 8]; 2],
+    pub king_storm: [[u32; 8]; 2],
+    pub shelter: [Evaluation; 2],
+    pub threats_by_pawn: [u32; 2],
+    pub threats_by_minor: [u32; 2],
+    pub threats_by_rook: [u32; 2],
+    pub threats: [Evaluation; 2],
+    // Registered endgame for the material on the board, if any.
+    pub endgame: Option<Endgame>,
+    // Out of SCALE_FACTOR_NORMAL, applied to the sum of the terms.
+    pub scale_factor: Evaluation,
+    // Final eval before clamping.
+    pub total: Evaluation,
+}
+
+impl State {
+    pub fn eval_trace(&self) -> EvalTrace {
+        let mut trace = EvalTrace::default();
+        self.classical_eval(Some(&mut trace));
+        trace
+    }
+}
+
+impl EvalTrace {
+    // Sum of the individual terms after scaling. Tapered terms are blended by phase separately here but together in the
+    // eval so this can differ from total by a few units of rounding. Specialized endgame evaluators replace every term.
+    pub fn term_sum(&self) -> Evaluation {
+        if let Some(Endgame { kind: EndgameKind::Evaluation(_), .. }) = self.endgame {
+            return self.total;
+        }
+        let side_sum = |color: Color| {
+            let side = color as usize;
+            self.material[side] + self.mobility[side] + self.king_safety[side] + self.pawn_structure[side]
+                + self.passed[side] + self.pieces[side] + self.shelter[side] + self.threats[side]
+        };
+        let sum = side_sum(Color::White) - side_sum(Color::Black) + self.table;
+        (sum as i64 * self.scale_factor as i64 / SCALE_FACTOR_NORMAL as i64) as Evaluation
+    }
+}
+
+fn pawns(eval: Evaluation) -> f64 {
+    eval as f64 / (CENTI_PAWN as f64 * 100.0)
+}
+
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let row = |f: &mut fmt::Formatter<'_>, name: &str, values: [Evaluation; 2], detail: String| {
+            writeln!(f, "{:<16}|{:>8.2} |{:>8.2} |{:>8.2} | {}", name, pawns(values[0]), pawns(values[1]), pawns(values[0] - values[1]), detail)
+        };
+        writeln!(f, "{:<16}|{:>8} |{:>8} |{:>8} |", "Term", "White", "Black", "Total")?;
+        writeln!(f, "{}", "-".repeat(48))?;
+        row(f, "Material", self.material, String::new())?;
+        row(f, "Midgame table", self.midgame_table, String::new())?;
+        row(f, "Endgame table", self.endgame_table, String::new())?;
+        writeln!(f, "{:<16}|{:>8} |{:>8} |{:>8.2} | phase {}/{}", "Blended table", "", "", pawns(self.table), self.phase, TOTAL_PHASE_VALUE)?;
+        for color in COLORS {
+            let counts: Vec<String> = PIECE_NAMES.iter().zip(self.mobility_counts[color as usize]).map(|(name, count)| format!("{} {}", name, count)).collect();
+            let mut values = [0; 2];
+            values[color as usize] = self.mobility[color as usize];
+            row(f, &format!("Mobility {:?}", color), values, counts.join(", "))?;
+        }
+        row(f, "King safety", self.king_safety, format!("attack units {} / {}", self.king_attack_units[0], self.king_attack_units[1]))?;
+        row(f, "Pawn structure", self.pawn_structure, format!(
+            "isolated {} / {}, doubled {} / {}, backward {} / {}, connected {} / {}",
+            self.isolated_pawns[0], self.isolated_pawns[1], self.doubled_pawns[0], self.doubled_pawns[1],
+            self.backward_pawns[0], self.backward_pawns[1], self.connected_pawns[0], self.connected_pawns[1]
+        ))?;
+        row(f, "Passed pawns", self.passed, format!(
+            "count {} / {}, king distance own {} / {}, enemy {} / {}", self.passed_pawns[0], self.passed_pawns[1],
+            self.passed_own_king_distance[0], self.passed_own_king_distance[1], self.passed_enemy_king_distance[0], self.passed_enemy_king_distance[1]
+        ))?;
+        row(f, "Pieces", self.pieces, format!(
+            "bishop pair {} / {}, rook open {} / {}, semi open {} / {}, 7th {} / {}, outposts {} / {}",
+            self.bishop_pairs[0], self.bishop_pairs[1], self.rooks_open_file[0], self.rooks_open_file[1], self.rooks_semi_open_file[0],
+            self.rooks_semi_open_file[1], self.rooks_seventh[0], self.rooks_seventh[1], self.knight_outposts[0], self.knight_outposts[1]
+        ))?;
+        row(f, "King shelter", self.shelter, format!("shelter {:?} / {:?}, storm {:?} / {:?}", self.king_shelter[0], self.king_shelter[1], self.king_storm[0], self.king_storm[1]))?;
+        row(f, "Threats", self.threats, format!(
+            "by pawn {} / {}, by minor {} / {}, by rook {} / {}",
+            self.threats_by_pawn[0], self.threats_by_pawn[1], self.threats_by_minor[0], self.threats_by_minor[1], self.threats_by_rook[0], self.threats_by_rook[1]
+        ))?;
+        match self.endgame {
+            Some(Endgame { name, strong_side, kind: EndgameKind::Evaluation(_) }) => writeln!(f, "{:<16}| {} evaluator, strong side {:?}, replaces the terms above", "Endgame", name, strong_side)?,
+            Some(Endgame { name, strong_side, kind: EndgameKind::Scale(_) }) => writeln!(f, "{:<16}| {} scaling, strong side {:?}", "Endgame", name, strong_side)?,
+            None => (),
+        }
+        writeln!(f, "{:<16}| {}/{}", "Scale factor", self.scale_factor, SCALE_FACTOR_NORMAL)?;
+        writeln!(f, "{}", "-".repeat(48))?;
+        writeln!(f, "{:<16}|{:>8} |{:>8} |{:>8.2} | white's perspective", "Total", "", "", pawns(self.total))?;
+        if use_nnue() {
+            writeln!(f, "NNUE is enabled so the search uses the network, not the terms above")?;
+        }
+        Ok(())
+    }
+}
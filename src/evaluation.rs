@@ -1,6 +1,6 @@
 use std::cmp::min;
 
-use crate::{bitboard::{bit_count, board_from_square, file, get_lsb, pop_lsb, rank, shift_bitboard, Bitboard, Color, COLORS, EMPTY_BITBOARD, FILES, RANKS}, piece_info::{move_bitboard, Direction, PieceType, BLACK_KING, KING, MOVE_BOARDS, PAWN, WHITE_BISHOP, WHITE_KING, WHITE_KNIGHT, WHITE_PAWN, WHITE_QUEEN, WHITE_ROOK}, search::Depth, state::State};
+use crate::{bitbase::kpk_bitbase_init, bitboard::{bit_count, file, get_lsb, pop_lsb, rank, shift_bitboard, square_distance, Bitboard, Color, Square, COLORS, EMPTY_BITBOARD, FILES, RANKS}, endgame::{endgame_init, Endgame, EndgameKind, SCALE_FACTOR_NORMAL}, eval_params::{eval_params, EVAL_PARAMS}, eval_trace::EvalTrace, nnue::use_nnue, pawn_table::PawnEntry, score::Score, piece_info::{move_bitboard, Direction, PieceType, KING, MOVE_BOARDS, PAWN}, search::Depth, state::State};
 
 pub type Evaluation = i32;
 
@@ -23,8 +23,6 @@ pub const PAWN_PHASE_VALUE: u8 = 0;
 pub const PIECE_PHASE_TABLE: [u8; 6] = [KING_PHASE_VALUE, QUEEN_PHASE_VALUE, ROOK_PHASE_VALUE, BISHOP_PHASE_VALUE, KNIGHT_PHASE_VALUE, PAWN_PHASE_VALUE];
 pub const TOTAL_PHASE_VALUE: u8 = (KING_PHASE_VALUE + QUEEN_PHASE_VALUE + ROOK_PHASE_VALUE*2 + BISHOP_PHASE_VALUE*2 + KNIGHT_PHASE_VALUE*2 + PAWN_PHASE_VALUE*8) * 2;
 
-pub const MOBILITY_VALUE: Evaluation = 1 * CENTI_PAWN;
-
 pub const KING_KING_SAFETY_VALUE: u32 = 1;
 pub const QUEEN_KING_SAFETY_VALUE: u32 = 5;
 pub const ROOK_KING_SAFETY_VALUE: u32 = 3;
@@ -33,8 +31,6 @@ pub const KNIGHT_KING_SAFETY_VALUE: u32 = 2;
 pub const PAWN_KING_SAFETY_VALUE: u32 = 1;
 pub const PIECE_KING_SAFETY_VALUES: [u32; 6] = [KING_KING_SAFETY_VALUE, QUEEN_KING_SAFETY_VALUE, ROOK_KING_SAFETY_VALUE, BISHOP_KING_SAFETY_VALUE, KNIGHT_KING_SAFETY_VALUE, PAWN_KING_SAFETY_VALUE];
 
-pub const KING_SAFETY_TABLE: [Evaluation; 100] = king_safety_table();
-
 pub const LOWEST_EVAL: Evaluation = -2147483646 + CENTI_PAWN - 2; // The lowest 32 bit value such that the 16 least significant bits are all 0
 pub const HIGHEST_EVAL: Evaluation = 2147483646 - CENTI_PAWN + 2; // The lowest 32 bit value such that the 16 least significant bits are all 0
 
@@ -43,124 +39,241 @@ pub const POSITIVE_MATE_ZERO: Evaluation = HIGHEST_EVAL - CENTI_PAWN;
 
 pub const MATE_VALUE_CUTOFF: Evaluation = CENTI_PAWN * 30_000;
 
-static mut MIDGAME_PIECE_SQUARE_TABLE: [[Evaluation; 64]; 12] = [[0; 64]; 12];
-static mut ENDGAME_PIECE_SQUARE_TABLE: [[Evaluation; 64]; 12] = [[0; 64]; 12];
-
-pub const PAWN_ISOLATED_VALUE: Evaluation = -25 * CENTI_PAWN;
-pub const PAWN_DOUBLED_VALUE: Evaluation = -15 * CENTI_PAWN;
-pub const PAWN_PASSED_VALUE: Evaluation = 50 * CENTI_PAWN;
+// Midgame and endgame piece square values packed together, indexed by colored piece.
+static mut PIECE_SQUARE_TABLE: [[Score; 64]; 12] = [[Score::ZERO; 64]; 12];
 
 //[white passed, black passed, isolated, empty for better cache line alignment]
 pub static mut PAWN_EVAL_LOOKUP_BOARDS: [[Bitboard; 4]; 64] = [[0; 4]; 64];
 
+// Evaluation terms that only depend on which pieces are on which squares, all from white's perspective.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct IncrementalEval {
+    pub material: Score,
+    pub phase: u8,
+    pub table: Score,
+}
+
+impl IncrementalEval {
+    pub const fn empty() -> IncrementalEval {
+        IncrementalEval { material: Score::ZERO, phase: 0, table: Score::ZERO }
+    }
+
+    #[inline(always)]
+    pub fn add_piece(&mut self, color: Color, piece_type: u8, square: Square) {
+        let colored_piece = (color.board_offset() + piece_type) as usize;
+        let sign = match color { Color::White => 1, Color::Black => -1 };
+        self.material += unsafe { EVAL_PARAMS.piece_values[piece_type as usize] } * sign;
+        self.phase += PIECE_PHASE_TABLE[piece_type as usize];
+        self.table += unsafe { PIECE_SQUARE_TABLE[colored_piece][square as usize] } * sign;
+    }
+
+    #[inline(always)]
+    pub fn remove_piece(&mut self, color: Color, piece_type: u8, square: Square) {
+        let colored_piece = (color.board_offset() + piece_type) as usize;
+        let sign = match color { Color::White => 1, Color::Black => -1 };
+        self.material -= unsafe { EVAL_PARAMS.piece_values[piece_type as usize] } * sign;
+        self.phase -= PIECE_PHASE_TABLE[piece_type as usize];
+        self.table -= unsafe { PIECE_SQUARE_TABLE[colored_piece][square as usize] } * sign;
+    }
+}
+
 impl State {
-    pub fn eval_state(&self, perspective: Color) -> Evaluation {
-        let mut eval: Evaluation = 0;
-
-        let mut phase_value = 0;
-        let mut midgame_table_eval = 0;
-        let mut endgame_table_eval = 0;
-
-        for piece_type in 1..6 {
-            let white_count = bit_count(unsafe { self.get_piece_board_raw(Color::White as u8, piece_type) });
-            let black_count = bit_count(unsafe { self.get_piece_board_raw(Color::Black as u8, piece_type) });
-            eval += white_count as i32 * PIECE_EVAL_TABLE[piece_type as usize];
-            eval -= black_count as i32 * PIECE_EVAL_TABLE[piece_type as usize];
-            phase_value += (white_count + black_count) as u8 * PIECE_PHASE_TABLE[piece_type as usize];
-            let mut white_board = unsafe { self.get_piece_board_raw(Color::White as u8, piece_type) };
-            while white_board != EMPTY_BITBOARD {
-                let square = pop_lsb(&mut white_board);
-                midgame_table_eval += unsafe { MIDGAME_PIECE_SQUARE_TABLE[piece_type as usize][square as usize] };
-                endgame_table_eval += unsafe { ENDGAME_PIECE_SQUARE_TABLE[piece_type as usize][square as usize] };
-            }
-            let mut black_board = unsafe { self.get_piece_board_raw(Color::Black as u8, piece_type) };
-            while black_board != EMPTY_BITBOARD {
-                let square = pop_lsb(&mut black_board);
-                midgame_table_eval -= unsafe { MIDGAME_PIECE_SQUARE_TABLE[piece_type as usize + 6][square as usize] };
-                endgame_table_eval -= unsafe { ENDGAME_PIECE_SQUARE_TABLE[piece_type as usize + 6][square as usize] };
+    pub fn computed_incremental_eval(&self) -> IncrementalEval {
+        let mut incremental_eval = IncrementalEval::empty();
+        for color in COLORS {
+            for piece_type in 0..6 {
+                let mut piece_board = unsafe { self.get_piece_board_raw(color as u8, piece_type) };
+                while piece_board != EMPTY_BITBOARD {
+                    incremental_eval.add_piece(color, piece_type, pop_lsb(&mut piece_board));
+                }
             }
         }
+        incremental_eval
+    }
 
-        let white_king_square = get_lsb(self.get_piece_board(Color::White, PieceType::King));
-        midgame_table_eval += unsafe { MIDGAME_PIECE_SQUARE_TABLE[WHITE_KING as usize][white_king_square as usize] };
-        endgame_table_eval += unsafe { ENDGAME_PIECE_SQUARE_TABLE[WHITE_KING as usize][white_king_square as usize] };
+    pub fn eval_state(&self, perspective: Color) -> Evaluation {
+        if use_nnue() {
+            return self.nnue_eval(perspective);
+        }
+        match perspective {
+            Color::White => self.classical_eval(None),
+            Color::Black => -self.classical_eval(None),
+        }.clamp(-MATE_VALUE_CUTOFF, MATE_VALUE_CUTOFF)
+    }
 
-        let black_king_square = get_lsb(self.get_piece_board(Color::Black, PieceType::King));
-        midgame_table_eval -= unsafe { MIDGAME_PIECE_SQUARE_TABLE[BLACK_KING as usize][black_king_square as usize] };
-        endgame_table_eval -= unsafe { ENDGAME_PIECE_SQUARE_TABLE[BLACK_KING as usize][black_king_square as usize] };
+    // Hand crafted evaluation from white's perspective. When a trace is given every term is also recorded in it.
+    #[inline(always)]
+    pub fn classical_eval(&self, mut trace: Option<&mut EvalTrace>) -> Evaluation {
+        let endgame = self.endgame();
+        if let Some(Endgame { strong_side, kind: EndgameKind::Evaluation(evaluate), .. }) = endgame {
+            let eval = match strong_side {
+                Color::White => evaluate(self, Color::White),
+                Color::Black => -evaluate(self, Color::Black),
+            };
+            if let Some(trace) = trace {
+                trace.endgame = endgame;
+                trace.scale_factor = SCALE_FACTOR_NORMAL;
+                trace.total = eval;
+            }
+            return eval;
+        }
 
-        let midgame_phase_val = u8::min(phase_value, TOTAL_PHASE_VALUE);
-        let endgame_phase_val = TOTAL_PHASE_VALUE - midgame_phase_val;
-        eval += (midgame_table_eval * midgame_phase_val as i32 + endgame_table_eval * endgame_phase_val as i32) / TOTAL_PHASE_VALUE as i32;
+        // Material and piece square tables are kept up to date by make_move so only the dynamic terms are computed here.
+        // Every term is a midgame and endgame pair, they are summed first and blended by phase once.
+        let params = eval_params();
+        let mut score = self.incremental_eval.material + self.incremental_eval.table;
+        let midgame_phase_val = u8::min(self.incremental_eval.phase, TOTAL_PHASE_VALUE);
+
+        if let Some(trace) = trace.as_deref_mut() {
+            trace.phase = midgame_phase_val;
+            trace.table = self.incremental_eval.table.blend(midgame_phase_val);
+            for color in COLORS {
+                let side_eval = self.side_incremental_eval(color);
+                trace.material[color as usize] = side_eval.material.blend(midgame_phase_val);
+                trace.midgame_table[color as usize] = side_eval.table.midgame();
+                trace.endgame_table[color as usize] = side_eval.table.endgame();
+            }
+        }
 
+        // Squares attacked by each side's minor pieces and rooks, needed for threats.
+        let mut minor_attacks = [EMPTY_BITBOARD; 2];
+        let mut rook_attacks = [EMPTY_BITBOARD; 2];
         for color in COLORS {
             let king_square = get_lsb(self.get_piece_board(color.other(), PieceType::King));
             let king_neighbors = unsafe {MOVE_BOARDS[KING as usize][king_square as usize]};
-            let mut running_mobility_count = 0;
+            let mut mobility_counts = [0; 6];
             let mut running_king_attacks = 0;
 
-            let (mobility_count, king_attacks) = self.mobility_and_king_attacks::<{PieceType::King}>(color, king_neighbors);
-            running_mobility_count += mobility_count; running_king_attacks += king_attacks;
-            let (mobility_count, king_attacks) = self.mobility_and_king_attacks::<{PieceType::Queen}>(color, king_neighbors);
-            running_mobility_count += mobility_count; running_king_attacks += king_attacks;
-            let (mobility_count, king_attacks) = self.mobility_and_king_attacks::<{PieceType::Rook}>(color, king_neighbors);
-            running_mobility_count += mobility_count; running_king_attacks += king_attacks;
-            let (mobility_count, king_attacks) = self.mobility_and_king_attacks::<{PieceType::Bishop}>(color, king_neighbors);
-            running_mobility_count += mobility_count; running_king_attacks += king_attacks;
-            let (mobility_count, king_attacks) = self.mobility_and_king_attacks::<{PieceType::Knight}>(color, king_neighbors);
-            running_mobility_count += mobility_count; running_king_attacks += king_attacks;
-            let (mobility_count, king_attacks) = self.mobility_and_king_attacks::<{PieceType::Pawn}>(color, king_neighbors);
-            running_mobility_count += mobility_count; running_king_attacks += king_attacks;
-
+            let (mobility_count, king_attacks, _) = self.mobility_and_king_attacks::<{PieceType::King}>(color, king_neighbors);
+            mobility_counts[PieceType::King as usize] = mobility_count; running_king_attacks += king_attacks;
+            let (mobility_count, king_attacks, _) = self.mobility_and_king_attacks::<{PieceType::Queen}>(color, king_neighbors);
+            mobility_counts[PieceType::Queen as usize] = mobility_count; running_king_attacks += king_attacks;
+            let (mobility_count, king_attacks, attacks) = self.mobility_and_king_attacks::<{PieceType::Rook}>(color, king_neighbors);
+            mobility_counts[PieceType::Rook as usize] = mobility_count; running_king_attacks += king_attacks;
+            rook_attacks[color as usize] = attacks;
+            let (mobility_count, king_attacks, attacks) = self.mobility_and_king_attacks::<{PieceType::Bishop}>(color, king_neighbors);
+            mobility_counts[PieceType::Bishop as usize] = mobility_count; running_king_attacks += king_attacks;
+            minor_attacks[color as usize] = attacks;
+            let (mobility_count, king_attacks, attacks) = self.mobility_and_king_attacks::<{PieceType::Knight}>(color, king_neighbors);
+            mobility_counts[PieceType::Knight as usize] = mobility_count; running_king_attacks += king_attacks;
+            minor_attacks[color as usize] |= attacks;
+            let (mobility_count, king_attacks, _) = self.mobility_and_king_attacks::<{PieceType::Pawn}>(color, king_neighbors);
+            mobility_counts[PieceType::Pawn as usize] = mobility_count; running_king_attacks += king_attacks;
+
+            let mobility_score = params.mobility * mobility_counts.iter().sum::<u32>() as i32;
+            let king_safety_score = params.king_safety[min(running_king_attacks, 99) as usize];
             match color {
-                Color::White => {
-                    eval += running_mobility_count as Evaluation * MOBILITY_VALUE;
-                    eval += KING_SAFETY_TABLE[min(running_king_attacks, 99) as usize] * CENTI_PAWN;
-                },
-                Color::Black => {
-                    eval -= running_mobility_count as Evaluation * MOBILITY_VALUE;
-                    eval -= KING_SAFETY_TABLE[min(running_king_attacks, 99) as usize] * CENTI_PAWN;
-                },
+                Color::White => score += mobility_score + king_safety_score,
+                Color::Black => score -= mobility_score + king_safety_score,
+            }
+
+            if let Some(trace) = trace.as_deref_mut() {
+                trace.mobility_counts[color as usize] = mobility_counts;
+                trace.mobility[color as usize] = mobility_score.blend(midgame_phase_val);
+                trace.king_attack_units[color as usize] = running_king_attacks;
+                trace.king_safety[color as usize] = king_safety_score.blend(midgame_phase_val);
             }
         }
 
+        let pawn_entry = self.pawn_entry();
+        score += pawn_entry.score[Color::White as usize] - pawn_entry.score[Color::Black as usize];
         for color in COLORS {
-            let mut eval_offset = 0;
-            let mut mut_friend_pawn_board = self.get_piece_board(color, PieceType::Pawn);
-            let friend_pawn_board = self.get_piece_board(color, PieceType::Pawn);
-            let enemy_pawn_board = self.get_piece_board(color.other(), PieceType::Pawn);
-            while mut_friend_pawn_board != EMPTY_BITBOARD {
-                let pawn_square = pop_lsb(&mut mut_friend_pawn_board);
-                if unsafe { PAWN_EVAL_LOOKUP_BOARDS[pawn_square as usize][2] & friend_pawn_board == EMPTY_BITBOARD} {
-                    // Isolated Pawn
-                    eval_offset += PAWN_ISOLATED_VALUE;
-                }
-                if FILES[file(pawn_square) as usize] & friend_pawn_board != board_from_square(pawn_square) {
-                    // Doubled pawn
-                    eval_offset += PAWN_DOUBLED_VALUE;
-                }
-                if unsafe { PAWN_EVAL_LOOKUP_BOARDS[pawn_square as usize][color as usize] & enemy_pawn_board == EMPTY_BITBOARD } {
-                    // Passed pawn
-                    eval_offset += PAWN_PASSED_VALUE;
-                }
+            let side = color as usize;
+            let bishop_pairs = self.bishop_pair_count(color);
+            let (open_files, semi_open_files, seventh_ranks) = self.rook_file_counts(color);
+            let outposts = self.knight_outpost_count(color, &pawn_entry);
+            let piece_score = params.bishop_pair * bishop_pairs as i32 + params.rook_open_file * open_files as i32
+                + params.rook_semi_open_file * semi_open_files as i32 + params.rook_seventh * seventh_ranks as i32 + params.knight_outpost * outposts as i32;
+
+            let (own_king_distance, enemy_king_distance) = self.passed_king_distances(color, pawn_entry.passed_pawns[side]);
+            let king_distance_score = params.passed_own_king_distance * own_king_distance as i32 + params.passed_enemy_king_distance * enemy_king_distance as i32;
+
+            let (shelter_ranks, storm_ranks) = self.king_shelter_counts(color);
+            let mut shelter_score = Score::ZERO;
+            for rank in 0..8 {
+                shelter_score += params.king_shelter[rank] * shelter_ranks[rank] as i32 + params.king_storm[rank] * storm_ranks[rank] as i32;
             }
+
+            let (pawn_threats, minor_threats, rook_threats) = self.threat_counts(color, pawn_entry.attacks[side], minor_attacks[side], rook_attacks[side]);
+            let threat_score = params.threat_by_pawn * pawn_threats as i32 + params.threat_by_minor * minor_threats as i32 + params.threat_by_rook * rook_threats as i32;
+
+            let side_score = piece_score + king_distance_score + shelter_score + threat_score;
             match color {
-                Color::White => { eval += eval_offset },
-                Color::Black => { eval -= eval_offset },
+                Color::White => score += side_score,
+                Color::Black => score -= side_score,
+            }
+
+            if let Some(trace) = trace.as_deref_mut() {
+                let mut passed_ranks = [0; 8];
+                let mut passed_pawns = pawn_entry.passed_pawns[side];
+                let mut passed_rank_score = Score::ZERO;
+                while passed_pawns != EMPTY_BITBOARD {
+                    let rank = relative_rank(color, pop_lsb(&mut passed_pawns)) as usize;
+                    passed_ranks[rank] += 1;
+                    passed_rank_score += params.passed_rank[rank];
+                }
+                let pawn_shape_score = params.pawn_isolated * pawn_entry.isolated_count[side] as i32 + params.pawn_doubled * pawn_entry.doubled_count[side] as i32
+                    + params.pawn_backward * pawn_entry.backward_count[side] as i32 + params.pawn_connected * pawn_entry.connected_count[side] as i32;
+                trace.isolated_pawns[side] = pawn_entry.isolated_count[side] as u32;
+                trace.doubled_pawns[side] = pawn_entry.doubled_count[side] as u32;
+                trace.backward_pawns[side] = pawn_entry.backward_count[side] as u32;
+                trace.connected_pawns[side] = pawn_entry.connected_count[side] as u32;
+                trace.pawn_structure[side] = pawn_shape_score.blend(midgame_phase_val);
+                trace.passed_pawns[side] = pawn_entry.passed_count[side] as u32;
+                trace.passed_ranks[side] = passed_ranks;
+                trace.passed_own_king_distance[side] = own_king_distance;
+                trace.passed_enemy_king_distance[side] = enemy_king_distance;
+                trace.passed[side] = (passed_rank_score + king_distance_score).blend(midgame_phase_val);
+                trace.bishop_pairs[side] = bishop_pairs;
+                trace.rooks_open_file[side] = open_files;
+                trace.rooks_semi_open_file[side] = semi_open_files;
+                trace.rooks_seventh[side] = seventh_ranks;
+                trace.knight_outposts[side] = outposts;
+                trace.pieces[side] = piece_score.blend(midgame_phase_val);
+                trace.king_shelter[side] = shelter_ranks;
+                trace.king_storm[side] = storm_ranks;
+                trace.shelter[side] = shelter_score.blend(midgame_phase_val);
+                trace.threats_by_pawn[side] = pawn_threats;
+                trace.threats_by_minor[side] = minor_threats;
+                trace.threats_by_rook[side] = rook_threats;
+                trace.threats[side] = threat_score.blend(midgame_phase_val);
             }
         }
+        let mut eval = score.blend(midgame_phase_val);
 
-        match perspective {
-            Color::White => eval,
-            Color::Black => -eval,
-        }.clamp(-MATE_VALUE_CUTOFF, MATE_VALUE_CUTOFF)
+        // Drawish material keeps only part of the advantage.
+        let scale_factor = self.scale_factor(eval, endgame);
+        eval = (eval as i64 * scale_factor as i64 / SCALE_FACTOR_NORMAL as i64) as Evaluation;
+
+        if let Some(trace) = trace {
+            trace.endgame = endgame;
+            trace.scale_factor = scale_factor;
+            trace.total = eval;
+        }
+        eval
+    }
 
+    // Incremental eval terms for a single side, as positive values for that side.
+    fn side_incremental_eval(&self, color: Color) -> IncrementalEval {
+        let mut incremental_eval = IncrementalEval::empty();
+        for piece_type in 0..6 {
+            let mut piece_board = unsafe { self.get_piece_board_raw(color as u8, piece_type) };
+            while piece_board != EMPTY_BITBOARD {
+                incremental_eval.add_piece(color, piece_type, pop_lsb(&mut piece_board));
+            }
+        }
+        if color == Color::Black {
+            incremental_eval.material = -incremental_eval.material;
+            incremental_eval.table = -incremental_eval.table;
+        }
+        incremental_eval
     }
 
     #[inline(always)]
-    fn mobility_and_king_attacks<const P: PieceType>(&self, color: Color, king_neighbors: Bitboard) -> (u32, u32) {
+    fn mobility_and_king_attacks<const P: PieceType>(&self, color: Color, king_neighbors: Bitboard) -> (u32, u32, Bitboard) {
         let mut mobility_count = 0;
         let mut king_attacks = 0;
+        let mut attacks = EMPTY_BITBOARD;
         let mut piece_board = self.get_piece_board(color, P);
         if P != PieceType::Pawn {
             while piece_board != EMPTY_BITBOARD {
@@ -168,6 +281,7 @@ impl State {
                 let moves = move_bitboard::<P>(piece_square, self.occupied);
                 mobility_count += bit_count(moves);
                 king_attacks += bit_count(moves & king_neighbors) * PIECE_KING_SAFETY_VALUES[P as usize];
+                attacks |= moves;
             }
         } else {
             let single_board;
@@ -190,15 +304,115 @@ impl State {
             }
             mobility_count += bit_count(single_board | double_board);
             king_attacks += (bit_count(up_right & king_neighbors) + bit_count(up_left & king_neighbors)) * PIECE_KING_SAFETY_VALUES[PAWN as usize];
+            attacks = up_right | up_left;
+        }
+        (mobility_count, king_attacks, attacks)
+    }
+
+    pub fn bishop_pair_count(&self, color: Color) -> u32 {
+        (bit_count(self.get_piece_board(color, PieceType::Bishop)) >= 2) as u32
+    }
+
+    // Rooks on files without pawns, on files with only enemy pawns and on the 7th rank.
+    pub fn rook_file_counts(&self, color: Color) -> (u32, u32, u32) {
+        let friend_pawns = self.get_piece_board(color, PieceType::Pawn);
+        let all_pawns = friend_pawns | self.get_piece_board(color.other(), PieceType::Pawn);
+        let (mut open_files, mut semi_open_files, mut seventh_ranks) = (0, 0, 0);
+        let mut rook_board = self.get_piece_board(color, PieceType::Rook);
+        while rook_board != EMPTY_BITBOARD {
+            let rook_square = pop_lsb(&mut rook_board);
+            let file_board = FILES[file(rook_square) as usize];
+            if file_board & all_pawns == EMPTY_BITBOARD {
+                open_files += 1;
+            } else if file_board & friend_pawns == EMPTY_BITBOARD {
+                semi_open_files += 1;
+            }
+            if relative_rank(color, rook_square) == 6 {
+                seventh_ranks += 1;
+            }
+        }
+        (open_files, semi_open_files, seventh_ranks)
+    }
+
+    // Knights on the 4th to 6th rank defended by a pawn where no enemy pawn can ever attack them.
+    pub fn knight_outpost_count(&self, color: Color, pawn_entry: &PawnEntry) -> u32 {
+        let outpost_ranks = match color {
+            Color::White => RANKS[3] | RANKS[4] | RANKS[5],
+            Color::Black => RANKS[2] | RANKS[3] | RANKS[4],
+        };
+        let outposts = outpost_ranks & pawn_entry.attacks[color as usize] & !pawn_entry.attack_spans[color.other() as usize];
+        bit_count(self.get_piece_board(color, PieceType::Knight) & outposts)
+    }
+
+    // King distances to the square in front of each passed pawn, weighted by how far the pawn has advanced.
+    // Returns the sums for the own and enemy king.
+    pub fn passed_king_distances(&self, color: Color, mut passed_pawns: Bitboard) -> (u32, u32) {
+        let own_king_square = get_lsb(self.get_piece_board(color, PieceType::King));
+        let enemy_king_square = get_lsb(self.get_piece_board(color.other(), PieceType::King));
+        let (mut own_distance, mut enemy_distance) = (0, 0);
+        while passed_pawns != EMPTY_BITBOARD {
+            let pawn_square = pop_lsb(&mut passed_pawns);
+            let weight = relative_rank(color, pawn_square).saturating_sub(2) as u32;
+            let stop_square = match color { Color::White => pawn_square + 8, Color::Black => pawn_square - 8 };
+            own_distance += square_distance(own_king_square, stop_square).min(5) as u32 * weight;
+            enemy_distance += square_distance(enemy_king_square, stop_square).min(5) as u32 * weight;
+        }
+        (own_distance, enemy_distance)
+    }
+
+    // For the king's file and its neighbours, counts the relative rank of the closest own pawn (shelter) and enemy
+    // pawn (storm) that is level with or in front of the king. Rank 0 counts files without such a pawn.
+    pub fn king_shelter_counts(&self, color: Color) -> ([u32; 8], [u32; 8]) {
+        let king_square = get_lsb(self.get_piece_board(color, PieceType::King));
+        let king_rank = relative_rank(color, king_square);
+        let center_file = file(king_square).clamp(1, 6);
+        let friend_pawns = self.get_piece_board(color, PieceType::Pawn);
+        let enemy_pawns = self.get_piece_board(color.other(), PieceType::Pawn);
+        let mut shelter_ranks = [0; 8];
+        let mut storm_ranks = [0; 8];
+        for file in center_file - 1..=center_file + 1 {
+            shelter_ranks[closest_pawn_rank(color, friend_pawns & FILES[file as usize], king_rank) as usize] += 1;
+            storm_ranks[closest_pawn_rank(color, enemy_pawns & FILES[file as usize], king_rank) as usize] += 1;
         }
-        (mobility_count, king_attacks)
+        (shelter_ranks, storm_ranks)
+    }
+
+    // Enemy pieces attacked by pawns, rooks and queens attacked by minor pieces and queens attacked by rooks.
+    pub fn threat_counts(&self, color: Color, pawn_attacks: Bitboard, minor_attacks: Bitboard, rook_attacks: Bitboard) -> (u32, u32, u32) {
+        let enemy = color.other();
+        let enemy_queens = self.get_piece_board(enemy, PieceType::Queen);
+        let enemy_majors = enemy_queens | self.get_piece_board(enemy, PieceType::Rook);
+        let enemy_pieces = enemy_majors | self.get_piece_board(enemy, PieceType::Bishop) | self.get_piece_board(enemy, PieceType::Knight);
+        (bit_count(pawn_attacks & enemy_pieces), bit_count(minor_attacks & enemy_majors), bit_count(rook_attacks & enemy_queens))
+    }
+}
+
+#[inline(always)]
+pub const fn relative_rank(color: Color, square: Square) -> u8 {
+    match color {
+        Color::White => rank(square),
+        Color::Black => 7 - rank(square),
     }
 }
 
+// Relative rank of the pawn closest to from_rank at or in front of it, 0 if there is none.
+fn closest_pawn_rank(color: Color, mut pawns: Bitboard, from_rank: u8) -> u8 {
+    let mut closest = 0;
+    while pawns != EMPTY_BITBOARD {
+        let pawn_rank = relative_rank(color, pop_lsb(&mut pawns));
+        if pawn_rank >= from_rank && (closest == 0 || pawn_rank < closest) {
+            closest = pawn_rank;
+        }
+    }
+    closest
+}
+
 #[cold]
 pub fn eval_info_init() {
     pawn_eval_boards();
     piece_square_table_init();
+    kpk_bitbase_init();
+    endgame_init();
 }
 
 #[cold]
@@ -226,157 +440,21 @@ fn pawn_eval_boards() {
     }
 }
 
+// Builds the per color piece square tables from the current eval parameters.
 #[cold]
-fn piece_square_table_init() {
-    unsafe {
-        MIDGAME_PIECE_SQUARE_TABLE[WHITE_KING as usize] = [
-            20, 30, 10, 0, 0, 10, 30, 20,
-            20, 20, 0, 0, 0, 0, 20, 20,
-            -10, -20, -20, -20, -20, -20, -20, -10,
-            -20, -30, -30, -40, -40, -30, -30, -20,
-            -30, -40, -40, -50, -50, -40, -40, -30,
-            -30, -40, -40, -50, -50, -40, -40, -30,
-            -30, -40, -40, -50, -50, -40, -40, -30,
-            -30, -40, -40, -50, -50, -40, -40, -30,
-        ];
-        ENDGAME_PIECE_SQUARE_TABLE[WHITE_KING as usize] = [
-            -50, -30, -30, -30, -30, -30, -30, -50,
-            -30, -30, 0, 0, 0, 0, -30, -30,
-            -30, -10, 20, 30, 30, 20, -10, -30,
-            -30, -10, 30, 40, 40, 30, -10, -30,
-            -30, -10, 30, 40, 40, 30, -10, -30,
-            -30, -10, 20, 30, 30, 20, -10, -30,
-            -30, -20, -10, 0, 0, -10, -20, -30,
-            -50, -40, -30, -20, -20, -30, -40, -50,
-        ];
-        MIDGAME_PIECE_SQUARE_TABLE[WHITE_QUEEN as usize] = [
-            -20, -10, -10, -5, -5, -10, -10, -20,
-            -10, 0, 5, 0, 0, 0, 0, -10,
-            -10, 5, 5, 5, 5, 5, 0, -10,
-            0, 0, 5, 5, 5, 5, 0, -5,
-            -5, 0, 5, 5, 5, 5, 0, -5,
-            -10, 0, 5, 5, 5, 5, 0, -10,
-            -10, 0, 0, 0, 0, 0, 0, -10,
-            -20, -10, -10, -5, -5, -10, -10, -20,
-        ];
-        ENDGAME_PIECE_SQUARE_TABLE[WHITE_QUEEN as usize] = [
-            -20, -10, -10, -5, -5, -10, -10, -20,
-            -10, 0, 5, 0, 0, 0, 0, -10,
-            -10, 5, 5, 5, 5, 5, 0, -10,
-            0, 0, 5, 5, 5, 5, 0, -5,
-            -5, 0, 5, 5, 5, 5, 0, -5,
-            -10, 0, 5, 5, 5, 5, 0, -10,
-            -10, 0, 0, 0, 0, 0, 0, -10,
-            -20, -10, -10, -5, -5, -10, -10, -20,
-        ];
-        MIDGAME_PIECE_SQUARE_TABLE[WHITE_ROOK as usize] = [
-            0, 0, 0, 5, 5, 0, 0, 0,
-            -5, 0, 0, 0, 0, 0, 0, -5,
-            -5, 0, 0, 0, 0, 0, 0, -5,
-            -5, 0, 0, 0, 0, 0, 0, -5,
-            -5, 0, 0, 0, 0, 0, 0, -5,
-            -5, 0, 0, 0, 0, 0, 0, -5,
-            5, 10, 10, 10, 10, 10, 10, 5,
-            0, 0, 0, 0, 0, 0, 0, 0,
-        ];
-        ENDGAME_PIECE_SQUARE_TABLE[WHITE_ROOK as usize] = [
-            0, 0, 0, 5, 5, 0, 0, 0,
-            -5, 0, 0, 0, 0, 0, 0, -5,
-            -5, 0, 0, 0, 0, 0, 0, -5,
-            -5, 0, 0, 0, 0, 0, 0, -5,
-            -5, 0, 0, 0, 0, 0, 0, -5,
-            -5, 0, 0, 0, 0, 0, 0, -5,
-            5, 10, 10, 10, 10, 10, 10, 5,
-            0, 0, 0, 0, 0, 0, 0, 0,
-        ];
-        MIDGAME_PIECE_SQUARE_TABLE[WHITE_BISHOP as usize] = [
-            -20, -10, -10, -10, -10, -10, -10, -20,
-            -10, 5, 0, 0, 0, 0, 5, -10,
-            -10, 10, 10, 10, 10, 10, 10, -10,
-            -10, 0, 10, 10, 10, 10, 0, -10,
-            -10, 5, 5, 10, 10, 5, 5, -10,
-            -10, 0, 5, 10, 10, 5, 0, -10,
-            -10, 0, 0, 0, 0, 0, 0, -10,
-            -20, -10, -10, -10, -10, -10, -10, -20,
-        ];
-        ENDGAME_PIECE_SQUARE_TABLE[WHITE_BISHOP as usize] = [
-            -20, -10, -10, -10, -10, -10, -10, -20,
-            -10, 5, 0, 0, 0, 0, 5, -10,
-            -10, 10, 10, 10, 10, 10, 10, -10,
-            -10, 0, 10, 10, 10, 10, 0, -10,
-            -10, 5, 5, 10, 10, 5, 5, -10,
-            -10, 0, 5, 10, 10, 5, 0, -10,
-            -10, 0, 0, 0, 0, 0, 0, -10,
-            -20, -10, -10, -10, -10, -10, -10, -20,
-        ];
-        MIDGAME_PIECE_SQUARE_TABLE[WHITE_KNIGHT as usize] = [
-            -50, -40, -30, -30, -30, -30, -40, -50,
-            -40, -20, 0, 5, 5, 0, -20, -40,
-            -30, 5, 10, 15, 15, 10, 5, -30,
-            -30, 0, 15, 20, 20, 15, 0, -30,
-            -30, 5, 15, 20, 20, 15, 5, -30,
-            -30, 0, 10, 15, 15, 10, 0, -30,
-            -40, -20, 0, 0, 0, 0, -20, -40,
-            -50, -40, -30, -30, -30, -30, -40, -50,
-        ];
-        ENDGAME_PIECE_SQUARE_TABLE[WHITE_KNIGHT as usize] = [
-            -50, -40, -30, -30, -30, -30, -40, -50,
-            -40, -20, 0, 5, 5, 0, -20, -40,
-            -30, 5, 10, 15, 15, 10, 5, -30,
-            -30, 0, 15, 20, 20, 15, 0, -30,
-            -30, 5, 15, 20, 20, 15, 5, -30,
-            -30, 0, 10, 15, 15, 10, 0, -30,
-            -40, -20, 0, 0, 0, 0, -20, -40,
-            -50, -40, -30, -30, -30, -30, -40, -50,
-        ];
-        MIDGAME_PIECE_SQUARE_TABLE[WHITE_PAWN as usize] = [
-            0, 0, 0, 0, 0, 0, 0, 0,
-            5, 10, 10, -20, -20, 10, 10, 5,
-            5, -5, -10, 0, 0, -10, -5, 5,
-            0, 0, 0, 20, 20, 0, 0, 0,
-            5, 5, 10, 25, 25, 10, 5, 5,
-            10, 10, 20, 30, 30, 20, 10, 10,
-            50, 50, 50, 50, 50, 50, 50, 50,
-            0, 0, 0, 0, 0, 0, 0, 0,
-        ];
-        ENDGAME_PIECE_SQUARE_TABLE[WHITE_PAWN as usize] = [
-            0, 0, 0, 0, 0, 0, 0, 0,
-            -30, -30, -30, -30, -30, -30, -30, -30,
-            -10, -10, -10, -10, -10, -10, -10, -10,
-            0, 0, 0, 0, 0, 0, 0, 0,
-            20, 20, 20, 20, 20, 20, 20, 20,
-            40, 40, 40, 40, 40, 40, 40, 40,
-            60, 60, 60, 60, 60, 60, 60, 60,
-            0, 0, 0, 0, 0, 0, 0, 0,
-        ];
-        for piece_type in 0..6 {
-            for square in 0..64 {
-                MIDGAME_PIECE_SQUARE_TABLE[piece_type][square] *= CENTI_PAWN;
-                ENDGAME_PIECE_SQUARE_TABLE[piece_type][square] *= CENTI_PAWN;
-            }
-        }
-        for piece_type in 0..6 {
-            for square in 0..64 {
-                MIDGAME_PIECE_SQUARE_TABLE[piece_type + 6][square ^ 56] = MIDGAME_PIECE_SQUARE_TABLE[piece_type][square];
-                ENDGAME_PIECE_SQUARE_TABLE[piece_type + 6][square ^ 56] = ENDGAME_PIECE_SQUARE_TABLE[piece_type][square];
+pub fn piece_square_table_init() {
+    let params = eval_params();
+    for piece_type in 0..6 {
+        for square in 0..64 {
+            let score = Score::new(params.midgame_tables[piece_type][square], params.endgame_tables[piece_type][square]);
+            unsafe {
+                PIECE_SQUARE_TABLE[piece_type][square] = score;
+                PIECE_SQUARE_TABLE[piece_type + 6][square ^ 56] = score;
             }
         }
     }
 }
 
-const fn king_safety_table() -> [Evaluation; 100] {
-    [0,  0,   1,   2,   3,   5,   7,   9,  12,  15,
-    18,  22,  26,  30,  35,  39,  44,  50,  56,  62,
-    68,  75,  82,  85,  89,  97, 105, 113, 122, 131,
-    140, 150, 169, 180, 191, 202, 213, 225, 237, 248,
-    260, 272, 283, 295, 307, 319, 330, 342, 354, 366,
-    377, 389, 401, 412, 424, 436, 448, 459, 471, 483,
-    494, 500, 500, 500, 500, 500, 500, 500, 500, 500,
-    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
-    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
-    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,]
-}
-
 #[inline(always)]
 pub fn unchecked_eval_clamp(val: Evaluation, min: Evaluation, max: Evaluation) -> Evaluation {
     debug_assert!(min <= max);
@@ -1,6 +1,6 @@
 use rand::{rng, RngCore};
 
-use crate::{bitboard::{file, pop_lsb, Color, NULL_SQUARE}, state::{CastleAvailability, State}};
+use crate::{bitboard::{file, pop_lsb, Color, COLORS, NULL_SQUARE}, piece_info::PieceType, state::{CastleAvailability, State}};
 
 
 pub static mut SQUARE_HASHES: [[u64; 64]; 12] = [[0; 64]; 12];
@@ -52,4 +52,17 @@ impl State {
         }
         result
     }
-}
\ No newline at end of file
+
+    // Key of the pawns alone, used for the pawn structure table.
+    pub fn get_pawn_hash(&self) -> u64 {
+        let mut result = 0;
+        for color in COLORS {
+            let piece_index = PieceType::Pawn.colored_value(color) as usize;
+            let mut board = self.board[piece_index];
+            while board != 0 {
+                result ^= unsafe { SQUARE_HASHES[piece_index][pop_lsb(&mut board) as usize] };
+            }
+        }
+        result
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{bitboard::{Bitboard, Square, EMPTY_BITBOARD, NULL_SQUARE}, piece_info::PieceType, state::CastleAvailability};
+use crate::{bitboard::{Bitboard, Square, EMPTY_BITBOARD, NULL_SQUARE}, evaluation::IncrementalEval, piece_info::PieceType, state::CastleAvailability};
 
 pub trait HistoryEntry : Copy {
     type Value: Sized + Copy;
@@ -77,6 +77,14 @@ impl HistoryEntry for bool {
     fn new(val: Self::Value) -> Self { val }
 }
 
+impl HistoryEntry for IncrementalEval {
+    type Value = IncrementalEval;
+
+    fn value(self) -> Self::Value { self }
+    fn empty() -> Self { IncrementalEval::empty() }
+    fn new(val: Self::Value) -> Self { val }
+}
+
 pub struct History<T: HistoryEntry> {
     vector: Vec<T>,
     current_index: usize,
@@ -0,0 +1,186 @@
+use crate::{bitboard::{bit_count, board_from_square, get_lsb, pop_lsb, Bitboard, Color, Square, EMPTY_BITBOARD, NULL_SQUARE}, move_list::LegalMoveList, r#move::{build_move, build_simple_move, Move, BISHOP_PROMOTION, CASTLE_SPECIAL_MOVE, EN_PASSANT_SPECIAL_MOVE, KNIGHT_PROMOTION, PROMOTION_SPECIAL_MOVE, QUEEN_PROMOTION, ROOK_PROMOTION}, piece_info::{make_step, move_bitboard, PieceType, Step, BETWEEN_BOARDS, LINE_BOARDS, PAWN_ATTACK_BOARDS}, state::{CastleAvailability, State}};
+
+// Unlike gen_all_moves every move generated here can be made without leaving the king in check, so it doesn't touch
+// the move stack and is meant for tooling and the UI rather than the search.
+impl State {
+    pub fn legal_moves(&self) -> LegalMoveList {
+        let mut moves = LegalMoveList::new();
+        self.legal_moves_into(&mut moves);
+        moves
+    }
+
+    // Reuses the caller's list, which is cleared first.
+    pub fn legal_moves_into(&self, moves: &mut LegalMoveList) {
+        moves.clear();
+        match self.turn {
+            Color::White => self.gen_legal_moves::<{Color::White}>(moves),
+            Color::Black => self.gen_legal_moves::<{Color::Black}>(moves),
+        }
+    }
+
+    pub fn is_legal(&self, m: Move) -> bool {
+        self.legal_moves().contains(&m)
+    }
+
+    pub fn gen_legal_moves<const C: Color>(&self, moves: &mut LegalMoveList) {
+        let king_square = get_lsb(self.get_piece_board(C, PieceType::King));
+        let own_board = self.side_occupied[C as usize];
+        let checkers = self.attackers_to::<C>(king_square, self.occupied);
+
+        let mut king_board = move_bitboard::<{PieceType::King}>(king_square, self.occupied) & !own_board;
+        while king_board != EMPTY_BITBOARD {
+            let des_square = pop_lsb(&mut king_board);
+            if self.is_square_safe::<C, true>(des_square, king_square) {
+                moves.push(build_simple_move(king_square, des_square));
+            }
+        }
+        // Only the king can get out of a double check
+        if bit_count(checkers) > 1 {
+            return;
+        }
+
+        // Out of check a move has to capture the checker or block it
+        let target = if checkers != EMPTY_BITBOARD {
+            let checker_square = get_lsb(checkers);
+            checkers | unsafe { BETWEEN_BOARDS[king_square as usize][checker_square as usize] }
+        } else {
+            !own_board
+        };
+        let pinned = self.pinned::<C>(king_square);
+
+        self.gen_legal_piece_moves::<C, {PieceType::Queen}>(moves, king_square, target, pinned);
+        self.gen_legal_piece_moves::<C, {PieceType::Rook}>(moves, king_square, target, pinned);
+        self.gen_legal_piece_moves::<C, {PieceType::Bishop}>(moves, king_square, target, pinned);
+        self.gen_legal_piece_moves::<C, {PieceType::Knight}>(moves, king_square, target, pinned);
+        self.gen_legal_pawn_moves::<C>(moves, king_square, target, pinned, checkers);
+
+        if checkers == EMPTY_BITBOARD {
+            match self.castle_availability[C as usize] {
+                CastleAvailability::Both => {
+                    self.gen_legal_castle::<C, {CastleAvailability::King}>(moves, king_square);
+                    self.gen_legal_castle::<C, {CastleAvailability::Queen}>(moves, king_square);
+                },
+                CastleAvailability::King => self.gen_legal_castle::<C, {CastleAvailability::King}>(moves, king_square),
+                CastleAvailability::Queen => self.gen_legal_castle::<C, {CastleAvailability::Queen}>(moves, king_square),
+                CastleAvailability::None => (),
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn gen_legal_piece_moves<const C: Color, const P: PieceType>(&self, moves: &mut LegalMoveList, king_square: Square, target: Bitboard, pinned: Bitboard) {
+        let mut piece_board = self.get_piece_board(C, P);
+        while piece_board != EMPTY_BITBOARD {
+            let src_square = pop_lsb(&mut piece_board);
+            let mut move_board = move_bitboard::<P>(src_square, self.occupied) & target;
+            if pinned & board_from_square(src_square) != EMPTY_BITBOARD {
+                move_board &= unsafe { LINE_BOARDS[king_square as usize][src_square as usize] };
+            }
+            while move_board != EMPTY_BITBOARD {
+                let des_square = pop_lsb(&mut move_board);
+                moves.push(build_simple_move(src_square, des_square));
+            }
+        }
+    }
+
+    fn gen_legal_pawn_moves<const C: Color>(&self, moves: &mut LegalMoveList, king_square: Square, target: Bitboard, pinned: Bitboard, checkers: Bitboard) {
+        let enemy_board = self.side_occupied[C.other() as usize];
+        let second_rank_mask = C.color_rel_rank_mask::<1>();
+        let up = C.up() as Step;
+        let mut pawn_board = self.get_piece_board(C, PieceType::Pawn);
+        while pawn_board != EMPTY_BITBOARD {
+            let src_square = pop_lsb(&mut pawn_board);
+            let mut allowed = target;
+            if pinned & board_from_square(src_square) != EMPTY_BITBOARD {
+                allowed &= unsafe { LINE_BOARDS[king_square as usize][src_square as usize] };
+            }
+
+            let push_square = make_step(src_square, up);
+            if board_from_square(push_square) & self.not_occupied != EMPTY_BITBOARD {
+                if board_from_square(push_square) & allowed != EMPTY_BITBOARD {
+                    push_pawn_move::<C>(moves, src_square, push_square);
+                }
+                if board_from_square(src_square) & second_rank_mask != EMPTY_BITBOARD {
+                    let double_push_square = make_step(push_square, up);
+                    if board_from_square(double_push_square) & self.not_occupied & allowed != EMPTY_BITBOARD {
+                        moves.push(build_simple_move(src_square, double_push_square));
+                    }
+                }
+            }
+
+            let attack_board = unsafe { PAWN_ATTACK_BOARDS[C as usize][src_square as usize] };
+            let mut capture_board = attack_board & enemy_board & allowed;
+            while capture_board != EMPTY_BITBOARD {
+                let des_square = pop_lsb(&mut capture_board);
+                push_pawn_move::<C>(moves, src_square, des_square);
+            }
+
+            if self.en_passant_square != NULL_SQUARE && attack_board & board_from_square(self.en_passant_square) != EMPTY_BITBOARD
+                && self.is_en_passant_legal::<C>(src_square, king_square, checkers) {
+                moves.push(build_move(src_square, self.en_passant_square, 0, EN_PASSANT_SPECIAL_MOVE));
+            }
+        }
+    }
+
+    // Two pawns leave the same rank at once so pins are checked against the board after the capture.
+    fn is_en_passant_legal<const C: Color>(&self, src_square: Square, king_square: Square, checkers: Bitboard) -> bool {
+        let captured_square = make_step(self.en_passant_square, C.down() as Step);
+        let captured_board = board_from_square(captured_square);
+        // A knight or pawn check can only be answered by taking the pawn
+        let stepper_checkers = checkers & (self.get_piece_board(C.other(), PieceType::Knight) | self.get_piece_board(C.other(), PieceType::Pawn));
+        if stepper_checkers & !captured_board != EMPTY_BITBOARD {
+            return false;
+        }
+        let occupied = (self.occupied & !board_from_square(src_square) & !captured_board) | board_from_square(self.en_passant_square);
+        self.slider_attackers_to::<C>(king_square, occupied) == EMPTY_BITBOARD
+    }
+
+    // can_castle also checks the king's destination, which gen_all_moves leaves to make_move.
+    fn gen_legal_castle<const C: Color, const A: CastleAvailability>(&self, moves: &mut LegalMoveList, king_square: Square) {
+        if self.can_castle::<C, A>() {
+            moves.push(build_move(king_square, self.castle_squares.rook_squares[C as usize][A.side_index()], A as u8, CASTLE_SPECIAL_MOVE));
+        }
+    }
+
+    // Enemy pieces of C attacking the square with the given occupancy.
+    pub fn attackers_to<const C: Color>(&self, square: Square, occupied: Bitboard) -> Bitboard {
+        self.slider_attackers_to::<C>(square, occupied)
+            | (move_bitboard::<{PieceType::Knight}>(square, occupied) & self.get_piece_board(C.other(), PieceType::Knight))
+            | (move_bitboard::<{PieceType::King}>(square, occupied) & self.get_piece_board(C.other(), PieceType::King))
+            | (unsafe { PAWN_ATTACK_BOARDS[C as usize][square as usize] } & self.get_piece_board(C.other(), PieceType::Pawn))
+    }
+
+    #[inline(always)]
+    fn slider_attackers_to<const C: Color>(&self, square: Square, occupied: Bitboard) -> Bitboard {
+        let queens = self.get_piece_board(C.other(), PieceType::Queen);
+        let rooks = self.get_piece_board(C.other(), PieceType::Rook) | queens;
+        let bishops = self.get_piece_board(C.other(), PieceType::Bishop) | queens;
+        (move_bitboard::<{PieceType::Rook}>(square, occupied) & rooks) | (move_bitboard::<{PieceType::Bishop}>(square, occupied) & bishops)
+    }
+
+    // Pieces of C that are the only thing between their king and an enemy slider.
+    pub fn pinned<const C: Color>(&self, king_square: Square) -> Bitboard {
+        let mut pinned = EMPTY_BITBOARD;
+        let mut snipers = self.slider_attackers_to::<C>(king_square, self.side_occupied[C.other() as usize]);
+        while snipers != EMPTY_BITBOARD {
+            let sniper_square = pop_lsb(&mut snipers);
+            let blockers = unsafe { BETWEEN_BOARDS[king_square as usize][sniper_square as usize] } & self.occupied;
+            if bit_count(blockers) == 1 {
+                pinned |= blockers & self.side_occupied[C as usize];
+            }
+        }
+        pinned
+    }
+}
+
+#[inline(always)]
+fn push_pawn_move<const C: Color>(moves: &mut LegalMoveList, src_square: Square, des_square: Square) {
+    if board_from_square(des_square) & C.color_rel_rank_mask::<7>() != EMPTY_BITBOARD {
+        moves.push(build_move(src_square, des_square, QUEEN_PROMOTION, PROMOTION_SPECIAL_MOVE));
+        moves.push(build_move(src_square, des_square, ROOK_PROMOTION, PROMOTION_SPECIAL_MOVE));
+        moves.push(build_move(src_square, des_square, BISHOP_PROMOTION, PROMOTION_SPECIAL_MOVE));
+        moves.push(build_move(src_square, des_square, KNIGHT_PROMOTION, PROMOTION_SPECIAL_MOVE));
+    } else {
+        moves.push(build_simple_move(src_square, des_square));
+    }
+}
@@ -1,36 +1,98 @@
 #![feature(adt_const_params)]
 #![allow(static_mut_refs)]
 
+pub mod bitbase;
 pub mod bitboard;
 pub mod debugging;
+pub mod endgame;
+pub mod epd;
+pub mod eval_params;
+pub mod eval_trace;
 pub mod evaluation;
 pub mod hash;
 pub mod histories;
+pub mod legal_move_gen;
 pub mod magic;
+pub mod mate_search;
 pub mod move_gen;
 pub mod move_list;
 pub mod move_pick;
+pub mod nnue;
 pub mod r#move;
 pub mod parsing;
+pub mod pawn_table;
+pub mod pgn;
 pub mod piece_info;
+pub mod san;
+pub mod score;
 pub mod search;
+pub mod self_play;
 pub mod state;
 pub mod transposition;
+pub mod tuner;
 pub mod uci;
 pub mod worker;
 
 pub mod tests;
 
-use std::{io::{stdin, stdout, Write}, time::Duration};
+use std::{env, io::{stdin, stdout, Write}, time::Duration};
 
-use crate::{bitboard::Color, evaluation::eval_info_init, r#move::{BISHOP_PROMOTION, KNIGHT_PROMOTION, PROMOTION_SPECIAL_MOVE, QUEEN_PROMOTION, ROOK_PROMOTION, build_move, debug_same_src_des, move_destination_square, move_origin_square, move_special_type}, parsing::{simple_move_from_string, starting_fen}, piece_info::move_gen_init, search::search_init, transposition::{free_ttable, ttable_init}, worker::Worker};
+use crate::{bitboard::Color, epd::run_epd_suite, eval_params::load_eval_params, evaluation::eval_info_init, r#move::{BISHOP_PROMOTION, KNIGHT_PROMOTION, PROMOTION_SPECIAL_MOVE, QUEEN_PROMOTION, ROOK_PROMOTION, build_move, move_destination_square, move_origin_square, move_special_type, uci_string_move}, parsing::starting_fen, pgn::{GameResult, PgnGame, PgnMove}, piece_info::move_gen_init, san::san_move_from_string, search::search_init, self_play::run_match, transposition::{free_ttable, ttable_init}, tuner::run_tuner, uci::{parse_run_mode, uci_loop, IORunMode}, worker::Worker};
+
+const DEFAULT_HASH_SIZE: usize = 2048;
 
 fn main() {
     move_gen_init();
     eval_info_init();
     search_init();
-    unsafe { ttable_init(2048) };
-    ui_game();
+    let args: Vec<String> = env::args().collect();
+    // The table size in megabytes can be passed as --hash <size>, engines run by the match command use a small one.
+    let hash_size = match args.iter().position(|arg| arg == "--hash") {
+        Some(index) => match args.get(index + 1).and_then(|size| size.parse().ok()) {
+            Some(size) => size,
+            None => {
+                eprintln!("Expected a size in megabytes after --hash");
+                return;
+            },
+        },
+        None => DEFAULT_HASH_SIZE,
+    };
+    unsafe { ttable_init(hash_size) };
+    // Parameter files can be passed as --eval-params <path> so A/B tests don't depend on the UCI option.
+    if let Some(index) = args.iter().position(|arg| arg == "--eval-params") {
+        match args.get(index + 1) {
+            Some(path) => if let Err(err) = load_eval_params(path) {
+                eprintln!("{}", err);
+                return;
+            },
+            None => {
+                eprintln!("Expected a parameter file after --eval-params");
+                return;
+            },
+        }
+    }
+    let mut input = String::new();
+    stdin().read_line(&mut input).expect("Failed to read run mode");
+    match parse_run_mode(input.trim()) {
+        Ok(IORunMode::UCI) => uci_loop(),
+        Ok(IORunMode::UserGame) => ui_game(),
+        Ok(IORunMode::Tune(config)) => {
+            if let Err(err) = run_tuner(config) {
+                eprintln!("{}", err);
+            }
+        },
+        Ok(IORunMode::Epd(config)) => {
+            if let Err(err) = run_epd_suite(config) {
+                eprintln!("{}", err);
+            }
+        },
+        Ok(IORunMode::Match(config)) => {
+            if let Err(err) = run_match(config) {
+                eprintln!("{}", err);
+            }
+        },
+        Err(err) => eprintln!("{}", err),
+    }
     unsafe { free_ttable() };
 }
 
@@ -43,30 +105,28 @@ fn ui_game() {
         else { None }
     });
     let mut state = starting_fen();
+    let mut game = PgnGame::new(None);
+    game.set_tag("Event", "User game");
+    let (white, black) = if player_side == Color::White { ("User", "Rhobos") } else { ("Rhobos", "User") };
+    game.set_tag("White", white);
+    game.set_tag("Black", black);
     let mut worker = Worker::new();
     let mut game_over = false;
     let mut player_turn = player_side == state.turn;
     while !game_over {
         println!("{}", state);
         if player_turn {
-            state.debug_quick_gen_moves();
-            let non_validated_moves = state.debug_move_vec();
-            state.current_move_list().reset();
-            let valid_moves = state.debug_validate_moves(&non_validated_moves);
-            let mut user_move = prompt_until("Enter a move: ", |str| {
-                if str.len() != 4 { return None }
-                let m = match simple_move_from_string(str.to_string()) {
-                    Some(val) => val,
-                    None => return None
-                };
-                for valid_m in valid_moves.iter() {
-                    if debug_same_src_des(m, *valid_m) {
-                        return Some(*valid_m)
-                    }
+            let valid_moves = state.legal_moves();
+            // Either SAN, which names the promotion piece itself, or the origin and destination squares
+            let (mut user_move, is_san) = prompt_until("Enter a move: ", |str| {
+                if let Some(m) = san_move_from_string(&state, str) {
+                    return Some((m, true));
                 }
-                None
+                if str.len() != 4 { return None }
+                // Promotions are asked for separately so only the squares are compared
+                valid_moves.iter().copied().find(|valid_m| uci_string_move(*valid_m)[..4] == *str).map(|m| (m, false))
             });
-            if move_special_type(user_move) == PROMOTION_SPECIAL_MOVE {
+            if move_special_type(user_move) == PROMOTION_SPECIAL_MOVE && !is_san {
                 let promotion = prompt_until("What piece do you want to promote to (queen/rook/bishop/knight): ", |str| {
                     match str.to_lowercase() {
                         val if val == "queen" => Some(QUEEN_PROMOTION),
@@ -78,6 +138,7 @@ fn ui_game() {
                 });
                 user_move = build_move(move_origin_square(user_move), move_destination_square(user_move), promotion, PROMOTION_SPECIAL_MOVE)
             }
+            game.moves.push(PgnMove::new(user_move));
             assert!(state.non_reversible_move(user_move))
         } else {
             let search_time = prompt_until("How long would you like to search: ", |str| {
@@ -87,13 +148,11 @@ fn ui_game() {
                 }
             });
             let best_move = worker.iterative_deepening_search(&mut state, Duration::from_secs_f64(search_time), true);
+            println!("Rhobos plays {}", state.san_string_move(best_move));
+            game.moves.push(PgnMove::new(best_move));
             state.non_reversible_move(best_move);
         }
-        state.debug_quick_gen_moves();
-        let mut moves = state.debug_move_vec();
-        moves = state.debug_validate_moves(&moves);
-        state.current_move_list().reset();
-        if moves.is_empty() {
+        if state.legal_moves().is_empty() {
             println!("{}", state);
             if state.check {
                 if player_turn {
@@ -101,17 +160,24 @@ fn ui_game() {
                 } else {
                     println!("Rhobos Wins");
                 }
+                game.set_result(if state.turn == Color::White { GameResult::BlackWin } else { GameResult::WhiteWin });
             } else {
                 println!("Stalemate");
+                game.set_result(GameResult::Draw);
             }
             game_over = true
         } else if state.half_move_clock >= 100 {
             println!("{}", state);
             println!("Draw by 50 move rule");
+            game.set_result(GameResult::Draw);
             game_over = true
         }
         player_turn = !player_turn
     }
+    match game.to_pgn() {
+        Ok(pgn) => print!("{}", pgn),
+        Err(err) => eprintln!("{}", err),
+    }
 }
 
 #[allow(dead_code)]
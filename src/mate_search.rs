@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use crate::{bitboard::Color, r#move::Move, search::Depth, state::State, worker::Worker};
+
+// Result of a mate search. Unlike the regular search nothing is pruned or reduced so both results are proofs.
+pub enum MateSearchResult {
+    // The mating line from the root, the defender always playing the reply that delays mate the longest.
+    Mate(Vec<Move>),
+    // The side to move can't force mate within the requested number of moves.
+    NoMate,
+}
+
+// Proven results for positions where the attacker is to move, keyed by hash and measured in plies.
+struct MateProofTable {
+    // Mate is forced within at most this many plies.
+    mates: HashMap<u64, Depth>,
+    // Mate can't be forced within this many plies.
+    no_mates: HashMap<u64, Depth>,
+}
+
+impl Worker {
+    // Searches for a forced mate in at most `mate_in_moves` moves for the side to move, shortest mates first.
+    pub fn mate_search(&mut self, state: &mut State, mate_in_moves: Depth) -> MateSearchResult {
+        let mut table = MateProofTable { mates: HashMap::new(), no_mates: HashMap::new() };
+        for moves in 1..=mate_in_moves {
+            let plies = moves * 2 - 1;
+            let found = match state.turn {
+                Color::White => self.mate_attack::<{Color::White}>(state, plies, &mut table),
+                Color::Black => self.mate_attack::<{Color::Black}>(state, plies, &mut table),
+            };
+            if found {
+                let mut line = Vec::new();
+                match state.turn {
+                    Color::White => self.mate_attack_line::<{Color::White}>(state, plies, &mut table, &mut line),
+                    Color::Black => self.mate_attack_line::<{Color::Black}>(state, plies, &mut table, &mut line),
+                }
+                state.current_move_list().reset();
+                return MateSearchResult::Mate(line);
+            }
+        }
+        state.current_move_list().reset();
+        MateSearchResult::NoMate
+    }
+
+    // C is the attacking side. Returns true if C can force mate within `plies` plies.
+    fn mate_attack<const C: Color>(&mut self, state: &mut State, plies: Depth, table: &mut MateProofTable) -> bool {
+        debug_assert_eq!(C, state.turn);
+        self.nodes_searched += 1;
+        if table.mates.get(&state.hashcode).is_some_and(|proven| *proven <= plies) {
+            return true;
+        }
+        if table.no_mates.get(&state.hashcode).is_some_and(|refuted| *refuted >= plies) {
+            return false;
+        }
+
+        let mut found = false;
+        for (m, gives_check) in legal_moves_with_checks::<C>(state) {
+            // Only a checking move can mate, so on the last move the quiet moves never need to be searched.
+            // Checks are sorted first so the rest of the moves can be skipped.
+            if plies == 1 && !gives_check {
+                break;
+            }
+            state.make_move::<C>(m);
+            let result = match C {
+                Color::White => self.mate_defend::<{Color::Black}>(state, plies - 1, table),
+                Color::Black => self.mate_defend::<{Color::White}>(state, plies - 1, table),
+            };
+            state.unmake_move::<C>(m);
+            if result {
+                found = true;
+                break;
+            }
+        }
+
+        if found {
+            table.mates.insert(state.hashcode, plies);
+        } else {
+            table.no_mates.insert(state.hashcode, plies);
+        }
+        found
+    }
+
+    // C is the defending side. Returns true if every defence leads to mate within `plies` plies.
+    fn mate_defend<const C: Color>(&mut self, state: &mut State, plies: Depth, table: &mut MateProofTable) -> bool {
+        debug_assert_eq!(C, state.turn);
+        self.nodes_searched += 1;
+        let moves = legal_moves_with_checks::<C>(state);
+        if moves.is_empty() {
+            // Checkmate or stalemate
+            return state.check;
+        }
+        if plies == 0 {
+            return false;
+        }
+        for (m, _) in moves {
+            state.make_move::<C>(m);
+            let result = match C {
+                Color::White => self.mate_attack::<{Color::Black}>(state, plies - 1, table),
+                Color::Black => self.mate_attack::<{Color::White}>(state, plies - 1, table),
+            };
+            state.unmake_move::<C>(m);
+            if !result {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Appends the mating line from an attacker node already proven to mate within `plies` plies.
+    fn mate_attack_line<const C: Color>(&mut self, state: &mut State, plies: Depth, table: &mut MateProofTable, line: &mut Vec<Move>) {
+        for (m, _) in legal_moves_with_checks::<C>(state) {
+            state.make_move::<C>(m);
+            let result = match C {
+                Color::White => self.mate_defend::<{Color::Black}>(state, plies - 1, table),
+                Color::Black => self.mate_defend::<{Color::White}>(state, plies - 1, table),
+            };
+            if result {
+                line.push(m);
+                match C {
+                    Color::White => self.mate_defend_line::<{Color::Black}>(state, plies - 1, table, line),
+                    Color::Black => self.mate_defend_line::<{Color::White}>(state, plies - 1, table, line),
+                }
+                state.unmake_move::<C>(m);
+                return;
+            }
+            state.unmake_move::<C>(m);
+        }
+        debug_assert!(false, "mate line requested for a position without a proven mate");
+    }
+
+    // Appends the longest defence and the rest of the line from a defender node proven to be mated within `plies` plies.
+    fn mate_defend_line<const C: Color>(&mut self, state: &mut State, plies: Depth, table: &mut MateProofTable, line: &mut Vec<Move>) {
+        let mut best_defence = None;
+        let mut longest_mate = 0;
+        for (m, _) in legal_moves_with_checks::<C>(state) {
+            state.make_move::<C>(m);
+            let mut mate_plies = 1;
+            while mate_plies < plies - 1 && !match C {
+                Color::White => self.mate_attack::<{Color::Black}>(state, mate_plies, table),
+                Color::Black => self.mate_attack::<{Color::White}>(state, mate_plies, table),
+            } {
+                mate_plies += 2;
+            }
+            state.unmake_move::<C>(m);
+            if best_defence.is_none() || mate_plies > longest_mate {
+                best_defence = Some(m);
+                longest_mate = mate_plies;
+            }
+        }
+        // No defence means the line is already mate.
+        if let Some(m) = best_defence {
+            line.push(m);
+            state.make_move::<C>(m);
+            match C {
+                Color::White => self.mate_attack_line::<{Color::Black}>(state, longest_mate, table, line),
+                Color::Black => self.mate_attack_line::<{Color::White}>(state, longest_mate, table, line),
+            }
+            state.unmake_move::<C>(m);
+        }
+    }
+}
+
+// All legal moves paired with whether they give check, checking moves first.
+fn legal_moves_with_checks<const C: Color>(state: &mut State) -> Vec<(Move, bool)> {
+    let legal_moves = state.legal_moves();
+    let mut result = Vec::with_capacity(legal_moves.len());
+    for m in legal_moves.iter().copied() {
+        state.make_move::<C>(m);
+        result.push((m, state.check));
+        state.unmake_move::<C>(m);
+    }
+    result.sort_by_key(|(_, gives_check)| !gives_check);
+    result
+}
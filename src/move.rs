@@ -22,6 +22,18 @@ pub const KNIGHT_PROMOTION:  u8 = 3;
 pub const KING_CASTLE: u8 = 0b01;
 pub const QUEEN_CASTLE: u8 = 0b10;
 
+// Set by the UCI_Chess960 option, castles are then written as the king taking its rook.
+static mut CHESS960: bool = false;
+
+pub fn set_chess960(chess960: bool) {
+    unsafe { CHESS960 = chess960 };
+}
+
+#[inline(always)]
+pub fn chess960() -> bool {
+    unsafe { CHESS960 }
+}
+
 #[inline(always)]
 pub const fn move_origin_square(m: Move) -> Square {
     (m & BIT_MASK_6) as u8
@@ -61,21 +73,52 @@ pub fn pretty_string_move(m: Move) -> String {
         return "Null Move".to_string();
     }
     if move_special_type(m) == CASTLE_SPECIAL_MOVE {
-        if move_destination_square(m) == 2 || move_destination_square(m) == 58 {
+        if move_special_info(m) == QUEEN_CASTLE {
             return "O-O-O".to_string();
         } else {
             return "O-O".to_string();
         }
     }
-    let promotion_string = if move_special_type(m) == PROMOTION_SPECIAL_MOVE {
-        match move_special_info(m) {
-            QUEEN_PROMOTION => "=Q",
-            ROOK_PROMOTION => "=R",
-            BISHOP_PROMOTION => "=B",
-            KNIGHT_PROMOTION => "=N",
-            _ => unreachable!(),
-        }} else {""};
-        pretty_string_square(move_origin_square(m)) +
-            &pretty_string_square(move_destination_square(m)) +
-            promotion_string
-}
\ No newline at end of file
+    let mut move_string = coordinate_string_move(m);
+    if let Some(promotion) = promotion_char(m) {
+        move_string.push('=');
+        move_string.push(promotion);
+    }
+    move_string
+}
+
+// Long algebraic notation as used by the UCI protocol, for example e2e4, e1g1 or e7e8q. In Chess960 mode castles are
+// written as the king taking its rook, e1h1.
+pub fn uci_string_move(m: Move) -> String {
+    if m == NULL_MOVE {
+        return "0000".to_string();
+    }
+    if move_special_type(m) == CASTLE_SPECIAL_MOVE && !chess960() {
+        let origin = move_origin_square(m);
+        let king_des_file = if move_special_info(m) == KING_CASTLE { 6 } else { 2 };
+        return pretty_string_square(origin) + &pretty_string_square(origin - origin % 8 + king_des_file);
+    }
+    let mut move_string = coordinate_string_move(m);
+    if let Some(promotion) = promotion_char(m) {
+        move_string.push(promotion.to_ascii_lowercase());
+    }
+    move_string
+}
+
+// Origin square followed by destination square, shared by the pretty and UCI formats.
+fn coordinate_string_move(m: Move) -> String {
+    pretty_string_square(move_origin_square(m)) + &pretty_string_square(move_destination_square(m))
+}
+
+fn promotion_char(m: Move) -> Option<char> {
+    if move_special_type(m) != PROMOTION_SPECIAL_MOVE {
+        return None;
+    }
+    match move_special_info(m) {
+        QUEEN_PROMOTION => Some('Q'),
+        ROOK_PROMOTION => Some('R'),
+        BISHOP_PROMOTION => Some('B'),
+        KNIGHT_PROMOTION => Some('N'),
+        _ => unreachable!(),
+    }
+}
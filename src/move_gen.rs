@@ -1,5 +1,5 @@
 use std::marker::ConstParamTy;
-use crate::{bitboard::{board_from_square, get_lsb, pop_lsb, shift_bitboard, Bitboard, Color, Square, EMPTY_BITBOARD, NULL_SQUARE}, r#move::{build_move, build_simple_move, BISHOP_PROMOTION, CASTLE_SPECIAL_MOVE, EN_PASSANT_SPECIAL_MOVE, KNIGHT_PROMOTION, PROMOTION_SPECIAL_MOVE, QUEEN_PROMOTION, ROOK_PROMOTION}, piece_info::{make_step, move_bitboard, Direction, PieceType, Step, PAWN_ATTACK_BOARDS}, state::{CastleAvailability, State}};
+use crate::{bitboard::{board_from_square, get_lsb, pop_lsb, shift_bitboard, Bitboard, Color, Square, EMPTY_BITBOARD, NULL_SQUARE}, r#move::{build_move, build_simple_move, move_destination_square, move_origin_square, move_special_info, move_special_type, Move, BISHOP_PROMOTION, CASTLE_SPECIAL_MOVE, EN_PASSANT_SPECIAL_MOVE, KING_CASTLE, KNIGHT_PROMOTION, NULL_MOVE, PASSING_MOVE, PROMOTION_SPECIAL_MOVE, QUEEN_CASTLE, QUEEN_PROMOTION, ROOK_PROMOTION}, piece_info::{make_step, move_bitboard, Direction, PieceType, Step, PAWN_ATTACK_BOARDS}, state::{CastleAvailability, State}};
 
 #[repr(u8)]
 #[derive(Clone, Copy, ConstParamTy, PartialEq, Eq)]
@@ -48,33 +48,28 @@ impl State {
                 self.move_stack.push_current(build_simple_move(king_square, des_square));
             }
         }
-        if G.should_gen_quiets() && king_square == State::king_square::<C>() && !self.check {
+        // Castle availability is lost as soon as the king moves so the king is still on its starting square
+        if G.should_gen_quiets() && !self.check {
             match self.castle_availability[C as usize] {
                 CastleAvailability::Both => {
-                    self.gen_king_castle::<C, { CastleAvailability::King }>();
-                    self.gen_king_castle::<C, { CastleAvailability::Queen }>();
+                    self.gen_king_castle::<C, { CastleAvailability::King }>(king_square);
+                    self.gen_king_castle::<C, { CastleAvailability::Queen }>(king_square);
                 },
-                CastleAvailability::King => self.gen_king_castle::<C, { CastleAvailability::King }>(),
-                CastleAvailability::Queen => self.gen_king_castle::<C, { CastleAvailability::Queen }>(),
+                CastleAvailability::King => self.gen_king_castle::<C, { CastleAvailability::King }>(king_square),
+                CastleAvailability::Queen => self.gen_king_castle::<C, { CastleAvailability::Queen }>(king_square),
                 CastleAvailability::None => (),
             }
         }
     }
 
     #[inline(always)]
-    pub fn gen_king_castle<const C: Color, const A: CastleAvailability>(&mut self) {
+    pub fn gen_king_castle<const C: Color, const A: CastleAvailability>(&mut self, king_square: Square) {
         debug_assert!(A != CastleAvailability::Both && A != CastleAvailability::None);
-        let color_shift = C.castle_shift();
-        let king_square = 4 + color_shift;
-        if board_from_square(CastleAvailability::rook_square::<C, A>()) & self.get_piece_board(C, PieceType::Rook) != EMPTY_BITBOARD &&
-            // Check if squares in between king and rook are free
-            CastleAvailability::through_squares::<C, A>() & self.occupied == EMPTY_BITBOARD &&
-            // Check if all squares in between king and rook are not attacked by another piece
-            self.are_castle_through_squares_safe::<C, A>()
-        {
+        if self.can_castle::<C, A>() {
+            // Encoded as the king taking its own rook so Chess960 castles stay unambiguous
             self.move_stack.push_current(build_move(
                 king_square,
-                CastleAvailability::des_square::<C, A>(),
+                self.castle_squares.rook_squares[C as usize][A.side_index()],
                 A as u8,
                 CASTLE_SPECIAL_MOVE)
             );
@@ -202,34 +197,99 @@ impl State {
     }
 }
 
-impl CastleAvailability {
-    #[inline(always)]
-    pub const fn through_squares<const C: Color, const A: CastleAvailability>() -> Bitboard {
-        (match A {
-            CastleAvailability::King => 0x60,
-            CastleAvailability::Queen => 0xE,
-            _ => EMPTY_BITBOARD,
-        }) << C.castle_shift()
+impl State {
+    // Whether gen_all_moves could have produced the move in this position, so moves from the transposition table or
+    // killer slots can be played without generating first. The king may still be left in check, make_move catches that.
+    pub fn is_pseudo_legal(&self, m: Move) -> bool {
+        if m == NULL_MOVE || m == PASSING_MOVE {
+            return false;
+        }
+        match self.turn {
+            Color::White => self.is_pseudo_legal_for::<{Color::White}>(m),
+            Color::Black => self.is_pseudo_legal_for::<{Color::Black}>(m),
+        }
     }
 
+    fn is_pseudo_legal_for<const C: Color>(&self, m: Move) -> bool {
+        let src_square = move_origin_square(m);
+        let des_square = move_destination_square(m);
+        let des_board = board_from_square(des_square);
+        let Some(piece_type) = self.get_colored_piece_at_square::<C>(src_square) else { return false };
+        let special_info = move_special_info(m);
+        // Castles are the only moves onto a friendly piece
+        if move_special_type(m) == CASTLE_SPECIAL_MOVE {
+            if piece_type != PieceType::King || self.check {
+                return false;
+            }
+            let availability = self.castle_availability[C as usize] as u8;
+            let rook_squares = self.castle_squares.rook_squares[C as usize];
+            return match special_info {
+                KING_CASTLE if availability & KING_CASTLE != 0 => des_square == rook_squares[CastleAvailability::King.side_index()]
+                    && self.can_castle::<C, {CastleAvailability::King}>(),
+                QUEEN_CASTLE if availability & QUEEN_CASTLE != 0 => des_square == rook_squares[CastleAvailability::Queen.side_index()]
+                    && self.can_castle::<C, {CastleAvailability::Queen}>(),
+                _ => false,
+            };
+        }
+        if des_board & self.side_occupied[C as usize] != EMPTY_BITBOARD {
+            return false;
+        }
+        match move_special_type(m) {
+            PROMOTION_SPECIAL_MOVE => {
+                piece_type == PieceType::Pawn && board_from_square(src_square) & C.color_rel_rank_mask::<6>() != EMPTY_BITBOARD
+                    && self.is_pawn_move::<C>(src_square, des_board)
+            },
+            EN_PASSANT_SPECIAL_MOVE => {
+                piece_type == PieceType::Pawn && special_info == 0 && des_square == self.en_passant_square
+                    && unsafe { PAWN_ATTACK_BOARDS[C as usize][src_square as usize] } & des_board != EMPTY_BITBOARD
+            },
+            _ => {
+                if special_info != 0 {
+                    return false;
+                }
+                let move_board = match piece_type {
+                    PieceType::King => move_bitboard::<{PieceType::King}>(src_square, self.occupied),
+                    PieceType::Queen => move_bitboard::<{PieceType::Queen}>(src_square, self.occupied),
+                    PieceType::Rook => move_bitboard::<{PieceType::Rook}>(src_square, self.occupied),
+                    PieceType::Bishop => move_bitboard::<{PieceType::Bishop}>(src_square, self.occupied),
+                    PieceType::Knight => move_bitboard::<{PieceType::Knight}>(src_square, self.occupied),
+                    PieceType::Pawn => {
+                        return board_from_square(src_square) & C.color_rel_rank_mask::<6>() == EMPTY_BITBOARD
+                            && self.is_pawn_move::<C>(src_square, des_board);
+                    },
+                };
+                move_board & des_board != EMPTY_BITBOARD
+            },
+        }
+    }
+
+    // Single or double push onto empty squares, or a capture of an enemy piece.
     #[inline(always)]
-    pub const fn rook_square<const C: Color, const A: CastleAvailability>() -> Square {
-        (match A {
-            CastleAvailability::King => 7,
-            CastleAvailability::Queen => 0,
-            _ => NULL_SQUARE,
-        }) + C.castle_shift()
+    fn is_pawn_move<const C: Color>(&self, src_square: Square, des_board: Bitboard) -> bool {
+        let enemy_board = self.side_occupied[C.other() as usize];
+        if unsafe { PAWN_ATTACK_BOARDS[C as usize][src_square as usize] } & des_board & enemy_board != EMPTY_BITBOARD {
+            return true;
+        }
+        let push_board = board_from_square(make_step(src_square, C.up() as Step));
+        if push_board & self.occupied != EMPTY_BITBOARD {
+            return false;
+        }
+        push_board == des_board || (board_from_square(src_square) & C.color_rel_rank_mask::<1>() != EMPTY_BITBOARD
+            && board_from_square(make_step(make_step(src_square, C.up() as Step), C.up() as Step)) == des_board
+            && des_board & self.occupied == EMPTY_BITBOARD)
     }
 
     #[inline(always)]
-    pub const fn des_square<const C: Color, const A: CastleAvailability>() -> Square {
-        (match A {
-            CastleAvailability::King => 6,
-            CastleAvailability::Queen => 2,
-            _ => NULL_SQUARE,
-        }) + C.castle_shift()
+    pub fn can_castle<const C: Color, const A: CastleAvailability>(&self) -> bool {
+        // The rook is home, nothing else is in the way and the king doesn't pass through or land in check
+        let side = A.side_index();
+        board_from_square(self.castle_squares.rook_squares[C as usize][side]) & self.get_piece_board(C, PieceType::Rook) != EMPTY_BITBOARD &&
+            self.castle_squares.empty_masks[C as usize][side] & self.occupied == EMPTY_BITBOARD &&
+            self.are_castle_through_squares_safe::<C, A>()
     }
+}
 
+impl CastleAvailability {
     #[inline(always)]
     pub fn bit_mask<const A: CastleAvailability>() -> u8 {
         match A {
@@ -1,10 +1,12 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, ops::{Deref, DerefMut}};
 
 use crate::{evaluation::Evaluation, r#move::{pretty_string_move, Move, NULL_MOVE}, move_pick::MovePickStage, state::State};
 
 pub const MAX_QUIET_MOVES: usize = 50;
 pub const MAX_CAPTURE_MOVES: usize = 40;
 pub const NUM_KILLERS: usize = 2;
+// No legal position has more than 218 moves.
+pub const MAX_LEGAL_MOVES: usize = 256;
 
 #[derive(Clone)]
 pub struct MoveStack {
@@ -15,7 +17,14 @@ pub struct MoveStack {
 #[derive(Clone, Copy)]
 pub union MoveValue {
     pub attack_val: Evaluation,
-    pub quiet_val: u64,
+    pub quiet_val: i32,
+}
+
+// Fixed capacity list of legal moves that lives on the stack and derefs to a slice.
+#[derive(Clone, Copy)]
+pub struct LegalMoveList {
+    moves: [Move; MAX_LEGAL_MOVES],
+    len: usize,
 }
 
 #[derive(Clone)]
@@ -23,6 +32,7 @@ pub struct MoveList {
     pub move_vec: Vec<Move>,
     pub value_vec: Vec<MoveValue>,
     pub current: Move,
+    pub current_piece: u8,
     pub last: usize,
     pub move_pick_stage: MovePickStage,
     pub tt_move: Move,
@@ -61,6 +71,16 @@ impl MoveStack {
         &mut self.vec[self.current]
     }
 
+    // Returns the move list `plies` plies above the current one, if the stack is that deep.
+    #[inline(always)]
+    pub fn get_previous(&self, plies: usize) -> Option<&MoveList> {
+        if plies > self.current {
+            None
+        } else {
+            Some(&self.vec[self.current - plies])
+        }
+    }
+
     #[inline(always)]
     pub fn push_current(&mut self, m: Move) {
         self.get_current().push(m);
@@ -74,6 +94,7 @@ impl MoveList {
             move_vec: vec![0; MAX_CAPTURE_MOVES + MAX_QUIET_MOVES],
             value_vec: vec![MoveValue {attack_val: 0}; MAX_CAPTURE_MOVES + MAX_QUIET_MOVES],
             current: NULL_MOVE,
+            current_piece: 0,
             last: 0,
             move_pick_stage: MovePickStage::Start,
             tt_move: NULL_MOVE,
@@ -125,6 +146,59 @@ impl MoveList {
 
 }
 
+impl LegalMoveList {
+    pub const fn new() -> LegalMoveList {
+        LegalMoveList { moves: [NULL_MOVE; MAX_LEGAL_MOVES], len: 0 }
+    }
+
+    #[inline(always)]
+    pub fn push(&mut self, m: Move) {
+        debug_assert!(self.len < MAX_LEGAL_MOVES);
+        self.moves[self.len] = m;
+        self.len += 1;
+    }
+
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl Default for LegalMoveList {
+    fn default() -> Self {
+        LegalMoveList::new()
+    }
+}
+
+impl Deref for LegalMoveList {
+    type Target = [Move];
+
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+}
+
+impl DerefMut for LegalMoveList {
+    fn deref_mut(&mut self) -> &mut [Move] {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl<'a> IntoIterator for &'a LegalMoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Debug for LegalMoveList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter().map(|m| pretty_string_move(*m))).finish()
+    }
+}
+
 impl State {
     #[inline(always)]
     pub fn current_move_list(&mut self) -> &mut MoveList {
@@ -1,8 +1,26 @@
 use std::{marker::ConstParamTy, mem::transmute};
-use crate::{bitboard::Color, evaluation::{PAWN_EVAL, PIECE_EVAL_TABLE}, r#move::{move_destination_square, move_origin_square, move_special_info, move_special_type, Move, EN_PASSANT_SPECIAL_MOVE, NULL_MOVE, PROMOTION_SPECIAL_MOVE}, move_gen::MoveGenType, move_list::NUM_KILLERS, piece_info::PieceType, state::State};
+use crate::{bitboard::{board_from_square, Color, Square, EMPTY_BITBOARD}, evaluation::{Evaluation, CENTI_PAWN, PIECE_EVAL_TABLE}, r#move::{move_destination_square, move_origin_square, move_special_info, move_special_type, Move, EN_PASSANT_SPECIAL_MOVE, NULL_MOVE, PASSING_MOVE, PROMOTION_SPECIAL_MOVE}, move_gen::MoveGenType, move_list::NUM_KILLERS, piece_info::PieceType, search::Depth, state::State};
 
-// Set to 1 so that there will always be a move better than null move for quiet move ordering.
-pub static mut HISTORY_TABLE: [[u64; 64]; 12] = [[1; 64]; 12];
+// All history values are kept within [-HISTORY_MAX, HISTORY_MAX] by the gravity update in apply_history_bonus.
+pub const HISTORY_MAX: i32 = 16384;
+const HISTORY_BONUS_MAX: i32 = 1536;
+const COUNTER_MOVE_BONUS: i32 = 8192;
+
+// Indexed by [colored piece][destination square].
+pub static mut HISTORY_TABLE: [[i32; 64]; 12] = [[0; 64]; 12];
+// Indexed by [colored piece of previous move][destination of previous move], stores the quiet move that refuted it.
+pub static mut COUNTER_MOVE_TABLE: [[Move; 64]; 12] = [[NULL_MOVE; 64]; 12];
+// Indexed by [colored piece][destination square][captured piece type]. Blended with the victim value so a strong
+// history can swap neighbouring victim classes, a knight and a bishop or a rook and a minor piece, but never lifts a
+// pawn capture above a queen capture.
+pub static mut CAPTURE_HISTORY_TABLE: [[[i32; 6]; 64]; 12] = [[[0; 6]; 64]; 12];
+const CAPTURE_MVV_MULTIPLIER: i32 = 32;
+const CAPTURE_HISTORY_DIVISOR: i32 = 4;
+// Small enough to only matter between captures of equal victims with similar history.
+const CAPTURE_LVA_MULTIPLIER: i32 = 64;
+// Indexed by [previous colored piece][previous destination][colored piece][destination]. Used for both the
+// move one ply back (counter move history) and two plies back (follow up history).
+pub static mut CONTINUATION_HISTORY_TABLE: [[[[i32; 64]; 12]; 64]; 12] = [[[[0; 64]; 12]; 64]; 12];
 
 #[derive(Clone, Copy, PartialEq, Eq, ConstParamTy)]
 #[repr(u8)]
@@ -22,6 +40,12 @@ pub enum MovePickStage {
     Done,
 }
 
+pub struct QuietHistoryContext {
+    pub counter_move: Move,
+    // The (colored piece, destination) of the moves one and two plies ago, if there were any.
+    pub continuations: [Option<(u8, Square)>; 2],
+}
+
 impl State {
     pub fn pick_next_move<const T: MovePickType>(&mut self) -> bool {
         match self.current_move_list().move_pick_stage {
@@ -30,7 +54,9 @@ impl State {
                 self.pick_next_move::<T>()
             },
             MovePickStage::TTMove => {
-                if self.current_move_list().tt_move != NULL_MOVE {
+                // The hash move is played before generating, so a key collision could hand over a move for another position.
+                let tt_move = self.current_move_list().tt_move;
+                if tt_move != NULL_MOVE && self.is_pseudo_legal(tt_move) {
                     self.current_move_list().current = self.current_move_list().tt_move;
                     for i in 0..self.current_move_list().last {
                         if self.current_move_list().move_vec[i] == self.current_move_list().tt_move {
@@ -71,6 +97,7 @@ impl State {
                 }
             },
             MovePickStage::KillerMoves => {
+                // Killers are only played when they match a generated quiet move so they can't be stale.
                 for i in 0..self.current_move_list().last {
                     let m = self.current_move_list().move_vec[i];
                     for j in 0..NUM_KILLERS {
@@ -87,7 +114,7 @@ impl State {
             MovePickStage::QuietMoves => {
                 let mut best_move = NULL_MOVE;
                 let mut best_index = 0;
-                let mut best_move_score = u64::MIN;
+                let mut best_move_score = i32::MIN;
                 for i in 0..self.current_move_list().last {
                     let contending_move = self.current_move_list().move_vec[i];
                     if contending_move == NULL_MOVE {
@@ -155,7 +182,21 @@ impl State {
                         self.assign_capture_scores();
                         MovePickStage::CaptureMoves
                     },
-                    MovePickStage::CaptureMoves => MovePickStage::Done,
+                    MovePickStage::CaptureMoves => {
+                        // When in check every evasion has to be searched, not just the captures.
+                        if self.check {
+                            self.current_move_list().last = 0;
+                            match self.turn {
+                                Color::White => self.gen_all_moves::<{Color::White}, {MoveGenType::Quiet}>(),
+                                Color::Black => self.gen_all_moves::<{Color::Black}, {MoveGenType::Quiet}>(),
+                            }
+                            self.assign_quiet_scores();
+                            MovePickStage::QuietMoves
+                        } else {
+                            MovePickStage::Done
+                        }
+                    },
+                    MovePickStage::QuietMoves => MovePickStage::Done,
                     MovePickStage::Done => MovePickStage::Done,
                     _ => unreachable!(),
                 }
@@ -165,9 +206,10 @@ impl State {
 
     #[inline(always)]
     pub fn assign_quiet_scores(&mut self) {
+        let context = self.quiet_history_context();
         for i in 0..self.current_move_list().last {
             let m = self.current_move_list().move_vec[i];
-            self.current_move_list().value_vec[i].quiet_val = self.move_quiet_score(m)
+            self.current_move_list().value_vec[i].quiet_val = self.move_quiet_score(m, &context)
         }
     }
 
@@ -175,55 +217,197 @@ impl State {
     pub fn assign_capture_scores(&mut self) {
         for i in 0..self.current_move_list().last {
             let m = self.current_move_list().move_vec[i];
-            let (lva_mvv, futility_score) = self.move_capture_score(m);
+            let (capture_score, futility_score) = self.move_capture_score(m);
             if self.current_move_list().is_futile && futility_score > self.current_move_list().futility_margin {
                 self.current_move_list().move_vec[i] = NULL_MOVE;
             } else {
-                self.current_move_list().value_vec[i].attack_val = lva_mvv;
+                self.current_move_list().value_vec[i].attack_val = capture_score;
             }
         }
     }
 
     #[inline(always)]
-    pub fn move_quiet_score(&self, m: Move) -> u64 {
-        let src_piece_type = match self.turn {
-            Color::White => self.force_get_colored_piece_at_square::<{Color::White}>(move_origin_square(m)),
-            Color::Black => self.force_get_colored_piece_at_square::<{Color::Black}>(move_origin_square(m)),
+    pub fn move_quiet_score(&self, m: Move, context: &QuietHistoryContext) -> i32 {
+        let piece = self.moving_colored_piece(m) as usize;
+        let des_square = move_destination_square(m) as usize;
+        let mut score = unsafe { HISTORY_TABLE[piece][des_square] };
+        for (previous_piece, previous_square) in context.continuations.iter().flatten() {
+            score += unsafe { CONTINUATION_HISTORY_TABLE[*previous_piece as usize][*previous_square as usize][piece][des_square] };
+        }
+        if m == context.counter_move {
+            score += COUNTER_MOVE_BONUS;
+        }
+        score
+    }
+
+    // Gathers the per node information needed to score quiet moves so it is not recomputed for every move.
+    #[inline(always)]
+    pub fn quiet_history_context(&self) -> QuietHistoryContext {
+        let continuations = [self.previous_move(1), self.previous_move(2)];
+        let counter_move = match continuations[0] {
+            Some((piece, square)) => unsafe { COUNTER_MOVE_TABLE[piece as usize][square as usize] },
+            None => NULL_MOVE,
         };
-        unsafe { HISTORY_TABLE[src_piece_type as usize][move_destination_square(m) as usize] }
+        QuietHistoryContext { counter_move, continuations }
+    }
+
+    // Returns the colored piece and destination square of the move played `plies` plies ago in the current line.
+    #[inline(always)]
+    pub fn previous_move(&self, plies: usize) -> Option<(u8, Square)> {
+        let move_list = self.move_stack.get_previous(plies)?;
+        if move_list.current == NULL_MOVE || move_list.current == PASSING_MOVE {
+            None
+        } else {
+            Some((move_list.current_piece, move_destination_square(move_list.current)))
+        }
+    }
+
+    #[inline(always)]
+    pub fn moving_colored_piece(&self, m: Move) -> u8 {
+        match self.turn {
+            Color::White => self.force_get_colored_piece_at_square::<{Color::White}>(move_origin_square(m)).colored_value(Color::White),
+            Color::Black => self.force_get_colored_piece_at_square::<{Color::Black}>(move_origin_square(m)).colored_value(Color::Black),
+        }
     }
 
-    // First return is lva-mva second is victim value/pawn_promotion value
+    // Promotions and en passant are ordered with the captures so they are not treated as quiet moves.
+    #[inline(always)]
+    pub fn is_quiet(&self, m: Move) -> bool {
+        let special_type = move_special_type(m);
+        special_type != PROMOTION_SPECIAL_MOVE && special_type != EN_PASSANT_SPECIAL_MOVE &&
+            self.side_occupied[self.turn.other() as usize] & board_from_square(move_destination_square(m)) == EMPTY_BITBOARD
+    }
+
+    // Rewards the quiet move that caused a beta cutoff and penalizes the quiet moves searched before it.
+    pub fn update_quiet_histories(&mut self, best_move: Move, depth: Depth, failed_quiets: &[Move]) {
+        let bonus = history_bonus(depth);
+        let context = self.quiet_history_context();
+        self.update_quiet_history(best_move, bonus, &context);
+        for m in failed_quiets {
+            self.update_quiet_history(*m, -bonus, &context);
+        }
+        if let Some((piece, square)) = context.continuations[0] {
+            unsafe { COUNTER_MOVE_TABLE[piece as usize][square as usize] = best_move };
+        }
+    }
+
+    #[inline(always)]
+    fn update_quiet_history(&self, m: Move, bonus: i32, context: &QuietHistoryContext) {
+        let piece = self.moving_colored_piece(m) as usize;
+        let des_square = move_destination_square(m) as usize;
+        unsafe { apply_history_bonus(&mut HISTORY_TABLE[piece][des_square], bonus) };
+        for (previous_piece, previous_square) in context.continuations.iter().flatten() {
+            unsafe { apply_history_bonus(&mut CONTINUATION_HISTORY_TABLE[*previous_piece as usize][*previous_square as usize][piece][des_square], bonus) };
+        }
+    }
+
+    // First return is the mvv + capture history + lva ordering score, second is victim value/pawn_promotion value
     #[inline(always)]
     pub fn move_capture_score(&self, m: Move) -> (i32, i32) {
+        let victim_value = self.move_victim_value(m);
+        // Piece types go from king to pawn so a higher index is a less valuable attacker.
+        let attacker = match self.turn {
+            Color::White => self.force_get_colored_piece_at_square::<{Color::White}>(move_origin_square(m)),
+            Color::Black => self.force_get_colored_piece_at_square::<{Color::Black}>(move_origin_square(m)),
+        } as i32;
+        let (piece, des_square, captured) = self.capture_history_index(m);
+        let capture_history = unsafe { CAPTURE_HISTORY_TABLE[piece][des_square][captured] };
+        (victim_value / CENTI_PAWN * CAPTURE_MVV_MULTIPLIER + capture_history / CAPTURE_HISTORY_DIVISOR + attacker * CAPTURE_LVA_MULTIPLIER, victim_value)
+    }
+
+    // Value of the captured piece plus the value of the promoted piece for promotions.
+    #[inline(always)]
+    pub fn move_victim_value(&self, m: Move) -> Evaluation {
+        let captured_value = match self.captured_piece(m) {
+            Some(piece_type) => PIECE_EVAL_TABLE[piece_type as usize],
+            None => 0,
+        };
         if move_special_type(m) == PROMOTION_SPECIAL_MOVE {
             let promotion_type: PieceType = unsafe {transmute(move_special_info(m) + 1)};
-            if let Some(des) = match self.turn {
-                Color::White => self.get_colored_piece_at_square::<{Color::Black}>(move_destination_square(m)),
-                Color::Black => self.get_colored_piece_at_square::<{Color::White}>(move_destination_square(m)),
-            } {
-                return (PIECE_EVAL_TABLE[promotion_type as usize], PIECE_EVAL_TABLE[promotion_type as usize] + PIECE_EVAL_TABLE[des as usize])
-            } else {
-                return (PIECE_EVAL_TABLE[promotion_type as usize], PIECE_EVAL_TABLE[promotion_type as usize]);
-            }
+            PIECE_EVAL_TABLE[promotion_type as usize] + captured_value
+        } else {
+            captured_value
         }
-        // Currently this check is technically not needed since force_get_colored_piece_at_square returns PieceType::Pawn
-        // if there is no piece present, but it is currently set to panic in debug if no piece is present. So this
-        // may want to be changed in future, but since relying on that is asking for a subtle bug in the future it may want
-        // to be kept this way for now.
+    }
+
+    #[inline(always)]
+    pub fn captured_piece(&self, m: Move) -> Option<PieceType> {
+        // En passant is handled separately since there is no piece on the destination square.
         if move_special_type(m) == EN_PASSANT_SPECIAL_MOVE {
-            return (0, PAWN_EVAL);
+            return Some(PieceType::Pawn);
         }
-        let (src, des) = match self.turn {
-            Color::White => {
-                (self.force_get_colored_piece_at_square::<{Color::White}>(move_origin_square(m)),
-                self.force_get_colored_piece_at_square::<{Color::Black}>(move_destination_square(m)))
-            },
-            Color::Black => {
-                (self.force_get_colored_piece_at_square::<{Color::Black}>(move_origin_square(m)),
-                self.force_get_colored_piece_at_square::<{Color::White}>(move_destination_square(m)))
+        match self.turn {
+            Color::White => self.get_colored_piece_at_square::<{Color::Black}>(move_destination_square(m)),
+            Color::Black => self.get_colored_piece_at_square::<{Color::White}>(move_destination_square(m)),
+        }
+    }
+
+    // Non capturing promotions use the king slot for the captured piece since a king can never be captured.
+    #[inline(always)]
+    fn capture_history_index(&self, m: Move) -> (usize, usize, usize) {
+        let captured = self.captured_piece(m).unwrap_or(PieceType::King);
+        (self.moving_colored_piece(m) as usize, move_destination_square(m) as usize, captured as usize)
+    }
+
+    // Rewards the capture that caused a beta cutoff and penalizes the captures searched before the cutoff move.
+    pub fn update_capture_histories(&self, best_move: Move, depth: Depth, failed_captures: &[Move]) {
+        let bonus = history_bonus(depth);
+        if !self.is_quiet(best_move) {
+            let (piece, des_square, captured) = self.capture_history_index(best_move);
+            unsafe { apply_history_bonus(&mut CAPTURE_HISTORY_TABLE[piece][des_square][captured], bonus) };
+        }
+        for m in failed_captures {
+            let (piece, des_square, captured) = self.capture_history_index(*m);
+            unsafe { apply_history_bonus(&mut CAPTURE_HISTORY_TABLE[piece][des_square][captured], -bonus) };
+        }
+    }
+}
+
+#[inline(always)]
+pub fn history_bonus(depth: Depth) -> i32 {
+    (32 * depth * depth).min(HISTORY_BONUS_MAX)
+}
+
+// Gravity style update, the closer an entry is to HISTORY_MAX the less a bonus of the same sign moves it.
+#[inline(always)]
+pub fn apply_history_bonus(entry: &mut i32, bonus: i32) {
+    *entry += bonus - *entry * bonus.abs() / HISTORY_MAX;
+}
+
+// Called between searches so that results from old positions slowly lose their influence.
+pub fn age_history_tables() {
+    unsafe {
+        for piece_table in HISTORY_TABLE.iter_mut() {
+            for entry in piece_table.iter_mut() {
+                *entry /= 2;
             }
-        };
-        (PIECE_EVAL_TABLE[des as usize] - PIECE_EVAL_TABLE[src as usize], PIECE_EVAL_TABLE[des as usize])
+        }
+        for piece_table in CAPTURE_HISTORY_TABLE.iter_mut() {
+            for square_table in piece_table.iter_mut() {
+                for entry in square_table.iter_mut() {
+                    *entry /= 2;
+                }
+            }
+        }
+        for previous_piece_table in CONTINUATION_HISTORY_TABLE.iter_mut() {
+            for previous_square_table in previous_piece_table.iter_mut() {
+                for piece_table in previous_square_table.iter_mut() {
+                    for entry in piece_table.iter_mut() {
+                        *entry /= 2;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Forgets everything learned so far, used when searches have to be independent of each other.
+#[cold]
+pub fn clear_history_tables() {
+    unsafe {
+        HISTORY_TABLE = [[0; 64]; 12];
+        COUNTER_MOVE_TABLE = [[NULL_MOVE; 64]; 12];
+        CAPTURE_HISTORY_TABLE = [[[0; 6]; 64]; 12];
+        CONTINUATION_HISTORY_TABLE.iter_mut().flatten().flatten().for_each(|piece_table| *piece_table = [0; 64]);
     }
 }
@@ -0,0 +1,311 @@
+use std::fs;
+
+use crate::{bitboard::{pop_lsb, Color, Square, COLORS, EMPTY_BITBOARD}, evaluation::{Evaluation, CENTI_PAWN, MATE_VALUE_CUTOFF}, piece_info::PieceType, state::State};
+
+// Network layout is (768 -> NNUE_HIDDEN) x 2 perspectives -> NNUE_HIDDEN_2 -> 1 with clipped ReLU activations.
+//
+// The weights file is little endian values in this order:
+//   feature weights  i16 [768][NNUE_HIDDEN]
+//   feature bias     i16 [NNUE_HIDDEN]
+//   hidden weights   i8  [NNUE_HIDDEN_2][2 * NNUE_HIDDEN] (side to move accumulator first)
+//   hidden bias      i32 [NNUE_HIDDEN_2] (quantized by NNUE_QA * NNUE_QH)
+//   output weights   i16 [NNUE_HIDDEN_2]
+//   output bias      i32 (quantized by NNUE_QA * NNUE_QB)
+// Anything after that, such as alignment padding, is ignored.
+//
+// Feature index is color * 384 + piece * 64 + square where everything is relative to the perspective, color 0 is the
+// perspective's own pieces, pieces are ordered pawn, knight, bishop, rook, queen, king and squares are flipped
+// vertically for black.
+pub const NNUE_INPUTS: usize = 768;
+pub const NNUE_HIDDEN: usize = 256;
+pub const NNUE_HIDDEN_2: usize = 32;
+// Activations are clipped to QA = 127 so a pair of u8 * i8 products always fits the i16 lanes of maddubs.
+const NNUE_QA: i32 = 127;
+const NNUE_QH: i32 = 64;
+const NNUE_QB: i32 = 64;
+const NNUE_SCALE: i64 = 400;
+// Network scores are kept below the mate cutoff so they can never be mistaken for a mate.
+const NNUE_MAX_CENTI_PAWNS: i64 = (MATE_VALUE_CUTOFF / CENTI_PAWN) as i64 - 1;
+
+const NETWORK_BYTE_COUNT: usize = 2 * (NNUE_INPUTS * NNUE_HIDDEN + NNUE_HIDDEN) + NNUE_HIDDEN_2 * 2 * NNUE_HIDDEN
+    + 4 * NNUE_HIDDEN_2 + 2 * NNUE_HIDDEN_2 + 4;
+
+static mut NETWORK: Option<Box<Network>> = None;
+static mut USE_NNUE: bool = false;
+
+#[repr(C, align(64))]
+pub struct Network {
+    feature_weights: [[i16; NNUE_HIDDEN]; NNUE_INPUTS],
+    feature_bias: [i16; NNUE_HIDDEN],
+    hidden_weights: [[i8; 2 * NNUE_HIDDEN]; NNUE_HIDDEN_2],
+    hidden_bias: [i32; NNUE_HIDDEN_2],
+    output_weights: [i16; NNUE_HIDDEN_2],
+    output_bias: i32,
+}
+
+// Hidden layer values before activation, one half per perspective, indexed by color.
+#[repr(C, align(64))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct Accumulator {
+    pub values: [[i16; NNUE_HIDDEN]; 2],
+}
+
+#[cold]
+pub fn load_network(path: &str) -> Result<(), String> {
+    let bytes = fs::read(path).map_err(|err| format!("Unable to read network file {}: {}", path, err))?;
+    load_network_bytes(&bytes).map_err(|err| format!("Network file {}: {}", path, err))
+}
+
+#[cold]
+pub fn load_network_bytes(bytes: &[u8]) -> Result<(), String> {
+    if bytes.len() < NETWORK_BYTE_COUNT {
+        return Err(format!("{} bytes, expected at least {}", bytes.len(), NETWORK_BYTE_COUNT));
+    }
+    let mut reader = NetworkReader { bytes, offset: 0 };
+    // Boxed zeroed to avoid building the roughly 400kb network on the stack.
+    let mut network: Box<Network> = unsafe { Box::new_zeroed().assume_init() };
+    for feature in network.feature_weights.iter_mut() {
+        feature.iter_mut().for_each(|weight| *weight = i16::from_le_bytes(reader.next()));
+    }
+    network.feature_bias.iter_mut().for_each(|bias| *bias = i16::from_le_bytes(reader.next()));
+    for neuron in network.hidden_weights.iter_mut() {
+        neuron.iter_mut().for_each(|weight| *weight = i8::from_le_bytes(reader.next()));
+    }
+    network.hidden_bias.iter_mut().for_each(|bias| *bias = i32::from_le_bytes(reader.next()));
+    network.output_weights.iter_mut().for_each(|weight| *weight = i16::from_le_bytes(reader.next()));
+    network.output_bias = i32::from_le_bytes(reader.next());
+    unsafe { NETWORK = Some(network) };
+    Ok(())
+}
+
+struct NetworkReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl NetworkReader<'_> {
+    // The length is checked up front so this never reads past the end.
+    fn next<const N: usize>(&mut self) -> [u8; N] {
+        let value = self.bytes[self.offset..self.offset + N].try_into().unwrap();
+        self.offset += N;
+        value
+    }
+}
+
+pub fn set_use_nnue(use_nnue: bool) -> Result<(), String> {
+    if use_nnue && !has_network() {
+        return Err("No network loaded, set EvalFile first".to_string());
+    }
+    unsafe { USE_NNUE = use_nnue };
+    Ok(())
+}
+
+#[inline(always)]
+pub fn use_nnue() -> bool {
+    unsafe { USE_NNUE }
+}
+
+#[inline(always)]
+pub fn has_network() -> bool {
+    unsafe { NETWORK.is_some() }
+}
+
+#[inline(always)]
+fn network() -> &'static Network {
+    debug_assert!(unsafe { NETWORK.is_some() });
+    unsafe { NETWORK.as_deref().unwrap_unchecked() }
+}
+
+#[inline(always)]
+const fn feature_index(perspective: Color, color: Color, piece_type: u8, square: Square) -> usize {
+    // Our piece types run king to pawn, the network's run pawn to king.
+    let network_piece = 5 - piece_type as usize;
+    match perspective {
+        Color::White => (color as usize) * 384 + network_piece * 64 + square as usize,
+        Color::Black => (color.other() as usize) * 384 + network_piece * 64 + (square ^ 56) as usize,
+    }
+}
+
+impl Accumulator {
+    pub const fn new() -> Accumulator {
+        Accumulator { values: [[0; NNUE_HIDDEN]; 2] }
+    }
+
+    #[inline(always)]
+    pub fn add_feature(&mut self, color: Color, piece_type: u8, square: Square) {
+        let network = network();
+        for perspective in COLORS {
+            let weights = &network.feature_weights[feature_index(perspective, color, piece_type, square)];
+            for (value, weight) in self.values[perspective as usize].iter_mut().zip(weights) {
+                *value += *weight;
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub fn remove_feature(&mut self, color: Color, piece_type: u8, square: Square) {
+        let network = network();
+        for perspective in COLORS {
+            let weights = &network.feature_weights[feature_index(perspective, color, piece_type, square)];
+            for (value, weight) in self.values[perspective as usize].iter_mut().zip(weights) {
+                *value -= *weight;
+            }
+        }
+    }
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    // Rebuilds the accumulator from scratch. Needed whenever a state is created or the network changes.
+    pub fn refresh_accumulator(&mut self) {
+        if use_nnue() {
+            self.enable_accumulator();
+        } else {
+            self.accumulator_enabled = false;
+        }
+    }
+
+    // Keeps the accumulator up to date through make_move and unmake_move even while the classical eval is used.
+    pub fn enable_accumulator(&mut self) {
+        debug_assert!(has_network());
+        self.accumulator_enabled = true;
+        self.accumulator = self.computed_accumulator();
+    }
+
+    pub fn computed_accumulator(&self) -> Accumulator {
+        let network = network();
+        let mut accumulator = Accumulator { values: [network.feature_bias; 2] };
+        for color in COLORS {
+            for piece_type in 0..6 {
+                let mut piece_board = self.get_piece_board(color, unsafe { std::mem::transmute::<u8, PieceType>(piece_type) });
+                while piece_board != EMPTY_BITBOARD {
+                    accumulator.add_feature(color, piece_type, pop_lsb(&mut piece_board));
+                }
+            }
+        }
+        accumulator
+    }
+
+    pub fn nnue_eval(&self, perspective: Color) -> Evaluation {
+        debug_assert!(self.accumulator == self.computed_accumulator());
+        let network = network();
+        let mut activations = [0; 2 * NNUE_HIDDEN];
+        let (own, other) = activations.split_at_mut(NNUE_HIDDEN);
+        crelu_pack(&self.accumulator.values[perspective as usize], own);
+        crelu_pack(&self.accumulator.values[perspective.other() as usize], other);
+        // The int8 dot products fit in i32, everything after is widened so large weights can't overflow.
+        let mut output = network.output_bias as i64;
+        for (weights, (bias, output_weight)) in network.hidden_weights.iter().zip(network.hidden_bias.iter().zip(network.output_weights)) {
+            let hidden = (dot_u8_i8(&activations, weights) as i64 + *bias as i64) / NNUE_QH as i64;
+            output += hidden.clamp(0, NNUE_QA as i64) * output_weight as i64;
+        }
+        // Output is quantized by QA * QB, the bias is stored already quantized that way.
+        let centi_pawns = (output * NNUE_SCALE / (NNUE_QA * NNUE_QB) as i64).clamp(-NNUE_MAX_CENTI_PAWNS, NNUE_MAX_CENTI_PAWNS);
+        centi_pawns as Evaluation * CENTI_PAWN
+    }
+}
+
+// Clamps the accumulator to [0, QA] and narrows it to the u8 inputs of the int8 hidden layer.
+#[inline(always)]
+fn crelu_pack(accumulator: &[i16; NNUE_HIDDEN], activations: &mut [u8]) {
+    debug_assert_eq!(activations.len(), NNUE_HIDDEN);
+    #[cfg(target_arch = "x86_64")]
+    {
+        // SSE2 is part of the x86_64 baseline so this is always available.
+        unsafe { crelu_pack_sse2(accumulator, activations) }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        crelu_pack_scalar(accumulator, activations)
+    }
+}
+
+#[allow(dead_code)]
+fn crelu_pack_scalar(accumulator: &[i16; NNUE_HIDDEN], activations: &mut [u8]) {
+    for (activation, value) in activations.iter_mut().zip(accumulator) {
+        *activation = (*value as i32).clamp(0, NNUE_QA) as u8;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn crelu_pack_sse2(accumulator: &[i16; NNUE_HIDDEN], activations: &mut [u8]) {
+    use std::arch::x86_64::*;
+    const LANES: usize = 16;
+    let max = _mm_set1_epi8(NNUE_QA as i8);
+    for i in (0..NNUE_HIDDEN).step_by(LANES) {
+        let low = unsafe { _mm_loadu_si128(accumulator.as_ptr().add(i) as *const __m128i) };
+        let high = unsafe { _mm_loadu_si128(accumulator.as_ptr().add(i + LANES / 2) as *const __m128i) };
+        // Packing saturates negative values to 0, the unsigned min then clips to QA.
+        let packed = _mm_min_epu8(_mm_packus_epi16(low, high), max);
+        unsafe { _mm_storeu_si128(activations.as_mut_ptr().add(i) as *mut __m128i, packed) };
+    }
+}
+
+// Sum of activations * weights using the widest integer SIMD the cpu supports.
+#[inline(always)]
+fn dot_u8_i8(activations: &[u8; 2 * NNUE_HIDDEN], weights: &[i8; 2 * NNUE_HIDDEN]) -> i32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if std::is_x86_feature_detected!("avx2") {
+            unsafe { dot_u8_i8_avx2(activations, weights) }
+        } else if std::is_x86_feature_detected!("ssse3") {
+            unsafe { dot_u8_i8_ssse3(activations, weights) }
+        } else {
+            dot_u8_i8_scalar(activations, weights)
+        }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        dot_u8_i8_scalar(activations, weights)
+    }
+}
+
+fn dot_u8_i8_scalar(activations: &[u8; 2 * NNUE_HIDDEN], weights: &[i8; 2 * NNUE_HIDDEN]) -> i32 {
+    activations.iter().zip(weights).map(|(activation, weight)| *activation as i32 * *weight as i32).sum()
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn dot_u8_i8_ssse3(activations: &[u8; 2 * NNUE_HIDDEN], weights: &[i8; 2 * NNUE_HIDDEN]) -> i32 {
+    use std::arch::x86_64::*;
+    const LANES: usize = 16;
+    let ones = _mm_set1_epi16(1);
+    let mut sum = _mm_setzero_si128();
+    for i in (0..2 * NNUE_HIDDEN).step_by(LANES) {
+        let values = unsafe { _mm_loadu_si128(activations.as_ptr().add(i) as *const __m128i) };
+        let weights = unsafe { _mm_loadu_si128(weights.as_ptr().add(i) as *const __m128i) };
+        // Pairs of u8 * i8 products are at most 2 * 127 * 128 so the saturating i16 sums never saturate.
+        let products = _mm_maddubs_epi16(values, weights);
+        sum = _mm_add_epi32(sum, _mm_madd_epi16(products, ones));
+    }
+    let high = _mm_unpackhi_epi64(sum, sum);
+    let sum = _mm_add_epi32(sum, high);
+    let high = _mm_shuffle_epi32::<0b01>(sum);
+    _mm_cvtsi128_si32(_mm_add_epi32(sum, high))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn dot_u8_i8_avx2(activations: &[u8; 2 * NNUE_HIDDEN], weights: &[i8; 2 * NNUE_HIDDEN]) -> i32 {
+    use std::arch::x86_64::*;
+    const LANES: usize = 32;
+    let ones = _mm256_set1_epi16(1);
+    let mut sum = _mm256_setzero_si256();
+    for i in (0..2 * NNUE_HIDDEN).step_by(LANES) {
+        let values = unsafe { _mm256_loadu_si256(activations.as_ptr().add(i) as *const __m256i) };
+        let weights = unsafe { _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i) };
+        let products = _mm256_maddubs_epi16(values, weights);
+        sum = _mm256_add_epi32(sum, _mm256_madd_epi16(products, ones));
+    }
+    let sum = _mm_add_epi32(_mm256_castsi256_si128(sum), _mm256_extracti128_si256::<1>(sum));
+    let high = _mm_unpackhi_epi64(sum, sum);
+    let sum = _mm_add_epi32(sum, high);
+    let high = _mm_shuffle_epi32::<0b01>(sum);
+    _mm_cvtsi128_si32(_mm_add_epi32(sum, high))
+}
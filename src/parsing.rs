@@ -1,11 +1,11 @@
-use std::collections::HashMap;
+use std::{fmt::Display, mem::transmute};
 
-use crate::{bitboard::{get_lsb, Color, Square, EMPTY_BITBOARD, FILE_MAP, NULL_SQUARE}, histories::History, r#move::{build_simple_move, Move}, move_list::MoveStack, piece_info::PieceType, state::{CastleAvailability, State}};
+use crate::{bitboard::{bit_count, board_from_square, file, get_lsb, pretty_string_square, rank, Board, Color, Square, COLORS, EMPTY_BITBOARD, FILE_MAP, NULL_SQUARE, RANKS}, evaluation::IncrementalEval, histories::History, r#move::{build_simple_move, uci_string_move, Move}, move_list::MoveStack, nnue::Accumulator, piece_info::{make_step, PieceType, Step}, state::{CastleAvailability, CastleSquares, State}};
 
 pub fn square_from_string(string: String) -> Option<Square> {
-    let rank = match string[1..].parse::<Square>() { 
-        Ok(val) => val - 1,
-        Err(_) => return None,
+    let rank = match string.get(1..)?.parse::<Square>() {
+        Ok(val @ 1..=8) => val - 1,
+        _ => return None,
     };
     let mut file = None;
     for (i, r) in FILE_MAP.iter().enumerate() {
@@ -28,111 +28,328 @@ pub fn simple_move_from_string(move_string: String) -> Option<Move> {
     }
 }
 
-pub fn parse_fen_string(fen_string: String) -> Result<State, String> {
-    let mut piece_map = HashMap::new();
-    const PIECE_CHARS: [char; 12] = ['K', 'Q', 'R', 'B', 'N', 'P', 'k', 'q', 'r', 'b', 'n', 'p'];
-    for (i, c) in PIECE_CHARS.iter().enumerate() { piece_map.insert(*c, i); }
-    let split_fen_string: Vec<&str> = fen_string.split(' ').collect();
+// Resolves a UCI move string such as e2e4 or e7e8q against the legal moves of the state.
+pub fn uci_move_from_string(state: &State, move_string: &str) -> Option<Move> {
+    if move_string.len() != 4 && move_string.len() != 5 {
+        return None;
+    }
+    // How castles are written depends on the Chess960 option, so each legal move is written out and compared
+    state.legal_moves().iter().copied().find(|m| uci_string_move(*m) == move_string)
+}
 
-    // Board section
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    MissingField(&'static str),
+    TooManyFields(usize),
+    WrongRankCount(usize),
+    // Rank number from 1 to 8
+    WrongRankLength(usize),
+    InvalidPiece(char),
+    InvalidSideToMove(String),
+    InvalidCastleCharacter(char),
+    CastleWithoutKing(char),
+    CastleWithoutRook(char),
+    DuplicateCastleSide(char),
+    InvalidEnPassant(String),
+    InvalidHalfMoveClock(String),
+    InvalidFullMoveNumber(String),
+    KingCount(Color, u32),
+    TooManyPieces(Color),
+    TooManyPawns(Color),
+    PawnOnBackRank,
+    // The side that just moved can't have left its king in check
+    OpponentInCheck,
+}
+
+impl Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "Missing {} field", field),
+            FenError::TooManyFields(count) => write!(f, "Expected at most 6 fields, found {}", count),
+            FenError::WrongRankCount(count) => write!(f, "Expected 8 ranks, found {}", count),
+            FenError::WrongRankLength(rank) => write!(f, "Rank {} doesn't have 8 squares", rank),
+            FenError::InvalidPiece(c) => write!(f, "Invalid piece character {}", c),
+            FenError::InvalidSideToMove(side) => write!(f, "Invalid side to move {}", side),
+            FenError::InvalidCastleCharacter(c) => write!(f, "Invalid castle availability character {}", c),
+            FenError::CastleWithoutKing(c) => write!(f, "Castle availability {} without a king on the back rank", c),
+            FenError::CastleWithoutRook(c) => write!(f, "No rook to castle with for {}", c),
+            FenError::DuplicateCastleSide(c) => write!(f, "Castle availability {} repeats a side", c),
+            FenError::InvalidEnPassant(square) => write!(f, "Invalid en passant square {}", square),
+            FenError::InvalidHalfMoveClock(clock) => write!(f, "Invalid half move clock {}", clock),
+            FenError::InvalidFullMoveNumber(number) => write!(f, "Invalid full move number {}", number),
+            FenError::KingCount(color, count) => write!(f, "{:?} has {} kings", color, count),
+            FenError::TooManyPieces(color) => write!(f, "{:?} has more than 16 pieces", color),
+            FenError::TooManyPawns(color) => write!(f, "{:?} has more than 8 pawns", color),
+            FenError::PawnOnBackRank => write!(f, "Pawn on the first or last rank"),
+            FenError::OpponentInCheck => write!(f, "The side not to move is in check"),
+        }
+    }
+}
+
+// Accepts standard KQkq, X-FEN where K and Q mean the outermost rook on that side of the king, and Shredder-FEN which
+// gives the rook files as letters, e.g. HAha.
+fn parse_castle_string(castle_string: &str, board: &Board) -> Result<([CastleAvailability; 2], CastleSquares), FenError> {
+    let standard = CastleSquares::standard();
+    let mut availability = [0u8; 2];
+    let mut rook_squares = standard.rook_squares;
+    let mut king_squares = [4, 60];
+    if castle_string != "-" {
+        for c in castle_string.chars() {
+            if !matches!(c.to_ascii_lowercase(), 'k' | 'q' | 'a'..='h') {
+                return Err(FenError::InvalidCastleCharacter(c));
+            }
+            let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+            let back_rank = color.castle_shift();
+            let kings = board[PieceType::King.colored_value(color) as usize] & RANKS[back_rank as usize / 8];
+            if kings == EMPTY_BITBOARD {
+                return Err(FenError::CastleWithoutKing(c));
+            }
+            let king_square = get_lsb(kings);
+            let rooks = board[PieceType::Rook.colored_value(color) as usize];
+            let is_rook = |file: u8| rooks & board_from_square(back_rank + file) != EMPTY_BITBOARD;
+            let rook_file = match c.to_ascii_lowercase() {
+                'k' => (file(king_square) + 1..8).rev().find(|file| is_rook(*file)),
+                'q' => (0..file(king_square)).find(|file| is_rook(*file)),
+                file_char => Some(file_char as u8 - b'a').filter(|rook_file| is_rook(*rook_file) && *rook_file != file(king_square)),
+            }.ok_or(FenError::CastleWithoutRook(c))?;
+            let side = if rook_file > file(king_square) { CastleAvailability::King } else { CastleAvailability::Queen };
+            if availability[color as usize] & side as u8 != 0 {
+                return Err(FenError::DuplicateCastleSide(c));
+            }
+            availability[color as usize] |= side as u8;
+            rook_squares[color as usize][side.side_index()] = back_rank + rook_file;
+            king_squares[color as usize] = king_square;
+        }
+    }
+    let castle_availability = availability.map(|bits| unsafe { transmute::<u8, CastleAvailability>(bits) });
+    Ok((castle_availability, CastleSquares::new(king_squares, rook_squares)))
+}
+
+fn parse_board_string(board_string: &str) -> Result<Board, FenError> {
+    const PIECE_CHARS: [char; 12] = ['K', 'Q', 'R', 'B', 'N', 'P', 'k', 'q', 'r', 'b', 'n', 'p'];
     let mut board = [EMPTY_BITBOARD; 12];
-    let board_string: Vec<&str> = split_fen_string[0].split('/').collect();
-    for i in 0..8 {
-        let rank= board_string[7-i];
+    let ranks: Vec<&str> = board_string.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenError::WrongRankCount(ranks.len()));
+    }
+    for (i, rank_string) in ranks.iter().rev().enumerate() {
         let mut column = 0;
-        for c in rank.chars() {
-            match piece_map.get(&c) {
-                Some(index) => board[*index] |= 1 << (i*8 + column),
-                None => {
-                    match c.to_digit(10) {
-                        Some(num) => column += num as usize - 1,
-                        None => return Err("Unable to parse Fen String. Bad board layout".into()),
-                    }
+        for c in rank_string.chars() {
+            if let Some(index) = PIECE_CHARS.iter().position(|piece| *piece == c) {
+                if column >= 8 {
+                    return Err(FenError::WrongRankLength(i + 1));
+                }
+                board[index] |= board_from_square((i * 8 + column) as Square);
+                column += 1;
+            } else {
+                match c.to_digit(10) {
+                    Some(num @ 1..=8) => column += num as usize,
+                    _ => return Err(FenError::InvalidPiece(c)),
                 }
             }
-            column += 1;
+        }
+        if column != 8 {
+            return Err(FenError::WrongRankLength(i + 1));
         }
     }
-    let mut side_occupied = [EMPTY_BITBOARD; 2];
-    for i in 0..6 {
-        side_occupied[0] |= board[i];
-        side_occupied[1] |= board[6+i];
+    Ok(board)
+}
+
+// Only set when the pawn that just moved two squares is there to be taken, with both squares it passed over empty.
+fn parse_en_passant_string(en_passant_string: &str, board: &Board, turn: Color) -> Result<Square, FenError> {
+    if en_passant_string == "-" {
+        return Ok(NULL_SQUARE);
+    }
+    let error = || FenError::InvalidEnPassant(en_passant_string.to_string());
+    let square = match en_passant_string.as_bytes() {
+        [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => (rank - b'1') * 8 + (file - b'a'),
+        _ => return Err(error()),
+    };
+    let mover = turn.other();
+    if rank(square) != if mover == Color::White { 2 } else { 5 } {
+        return Err(error());
+    }
+    let occupied = board.iter().fold(EMPTY_BITBOARD, |occupied, piece_board| occupied | piece_board);
+    let pawn_square = make_step(square, mover.up() as Step);
+    let start_square = make_step(square, mover.down() as Step);
+    if board[PieceType::Pawn.colored_value(mover) as usize] & board_from_square(pawn_square) == EMPTY_BITBOARD
+        || occupied & (board_from_square(square) | board_from_square(start_square)) != EMPTY_BITBOARD {
+        return Err(error());
     }
+    Ok(square)
+}
 
-    // Castle Section
-    let castle_string = split_fen_string[2];
-    let mut castle_availability = [CastleAvailability::None; 2];
-    if castle_string != "-" {
-        if castle_string.contains("K") {
-            castle_availability[0] = CastleAvailability::King;
+// Piece counts that can't come from a real game. Promotions are allowed for so only the totals are limited.
+fn validate_material(board: &Board) -> Result<(), FenError> {
+    for color in COLORS {
+        let kings = bit_count(board[PieceType::King.colored_value(color) as usize]);
+        if kings != 1 {
+            return Err(FenError::KingCount(color, kings));
         }
-        if castle_string.contains("Q") {
-            castle_availability[0] = if castle_availability[0] == CastleAvailability::King {CastleAvailability::Both} else {CastleAvailability::Queen};
+        if bit_count(board[PieceType::Pawn.colored_value(color) as usize]) > 8 {
+            return Err(FenError::TooManyPawns(color));
         }
-        if castle_string.contains("k") {
-            castle_availability[1] = CastleAvailability::King;
+        let pieces: u32 = (0..6).map(|piece_type| bit_count(board[color as usize * 6 + piece_type])).sum();
+        if pieces > 16 {
+            return Err(FenError::TooManyPieces(color));
         }
-        if castle_string.contains("q") {
-            castle_availability[1] = if castle_availability[1] == CastleAvailability::King {CastleAvailability::Both} else {CastleAvailability::Queen};
+        if board[PieceType::Pawn.colored_value(color) as usize] & (RANKS[0] | RANKS[7]) != EMPTY_BITBOARD {
+            return Err(FenError::PawnOnBackRank);
         }
     }
+    Ok(())
+}
 
-    // En Passant Section
-    const RANKS_CHARS: [char; 8] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
-    let en_passant_string = split_fen_string[3];
-    let mut en_passant_square = NULL_SQUARE;
-    if en_passant_string != "-" {
-        let mut rank_map = HashMap::new();
-        for (i, c) in RANKS_CHARS.iter().enumerate() {
-            rank_map.insert(*c, i);
-        }
-        let file_char = en_passant_string.chars().nth(0).unwrap();
-        match rank_map.get(&file_char) {
-            Some(c) => en_passant_square = *c as u8,
-            None => return Err("Invalid en passant substring".into()),
-        }
-        let rank_char = en_passant_string.chars().nth(1).unwrap();
-        match rank_char.to_digit(10) {
-            Some(d) => en_passant_square += ((d-1) * 8) as u8,
-            None => return Err("Invalid en passant substring".into()),
-        }
+// The move counters are optional and default to 0 and 1.
+pub fn parse_fen_string(fen_string: String) -> Result<State, FenError> {
+    let split_fen_string: Vec<&str> = fen_string.split_whitespace().collect();
+    const FIELD_NAMES: [&str; 4] = ["board", "side to move", "castle availability", "en passant"];
+    if let Some(missing) = FIELD_NAMES.get(split_fen_string.len()) {
+        return Err(FenError::MissingField(missing));
+    }
+    if split_fen_string.len() > 6 {
+        return Err(FenError::TooManyFields(split_fen_string.len()));
     }
 
-    // Ply Section
-    let ply = match split_fen_string[5].parse::<u16>() {
-        Ok(val) => val,
-        Err(err) => return Err(err.to_string()),
+    // Board section
+    let board = parse_board_string(split_fen_string[0])?;
+    validate_material(&board)?;
+    let mut side_occupied = [EMPTY_BITBOARD; 2];
+    for i in 0..6 {
+        side_occupied[0] |= board[i];
+        side_occupied[1] |= board[6+i];
+    }
+
+    let turn = match split_fen_string[1] {
+        "w" => Color::White,
+        "b" => Color::Black,
+        other => return Err(FenError::InvalidSideToMove(other.to_string())),
+    };
+
+    // Castle Section
+    let (castle_availability, castle_squares) = parse_castle_string(split_fen_string[2], &board)?;
+
+    // En Passant Section
+    let en_passant_square = parse_en_passant_string(split_fen_string[3], &board, turn)?;
+
+    // Ply Section, counted from 0 at the start of the game while the full move number starts at 1
+    let half_move_clock = match split_fen_string.get(4) {
+        Some(clock) => clock.parse::<u8>().map_err(|_| FenError::InvalidHalfMoveClock(clock.to_string()))?,
+        None => 0,
+    };
+    let full_move = match split_fen_string.get(5) {
+        Some(number) => number.parse::<u16>().map_err(|_| FenError::InvalidFullMoveNumber(number.to_string()))?,
+        None => 1,
     };
+    let ply = full_move.saturating_sub(1).saturating_mul(2).saturating_add(turn as u16);
     let mut state = State {
         board,
         side_occupied,
         occupied: side_occupied[0] | side_occupied[1],
         not_occupied: !(side_occupied[0] | side_occupied[1]),
-        turn: if split_fen_string[1] == "w" {Color::White} else {Color::Black},
+        turn,
         ply,
         en_passant_square,
         check: false,
         hashcode: 0,
-        half_move_clock: split_fen_string[4].parse().unwrap(),
+        pawn_hashcode: 0,
+        incremental_eval: IncrementalEval::empty(),
+        half_move_clock,
         move_stack: MoveStack::new(25),
         castle_availability,
+        castle_squares,
         capture_history: History::new(5),
         en_passant_history: History::new(5),
         castle_history: History::new(5),
         fifty_move_history: History::new(5),
         hash_history: History::new(5),
+        pawn_hash_history: History::new(5),
         check_history: History::new(5),
+        incremental_eval_history: History::new(5),
+        accumulator: Accumulator::new(),
+        accumulator_enabled: false,
+    };
+    let white_in_check = !state.is_square_safe::<{ Color::White }, false>(get_lsb(state.get_piece_board(Color::White, PieceType::King)), NULL_SQUARE);
+    let black_in_check = !state.is_square_safe::<{ Color::Black }, false>(get_lsb(state.get_piece_board(Color::Black, PieceType::King)), NULL_SQUARE);
+    let (check, opponent_in_check) = match turn {
+        Color::White => (white_in_check, black_in_check),
+        Color::Black => (black_in_check, white_in_check),
     };
-    if state.turn == Color::White {
-        state.check = !state.is_square_safe::<{ Color::White }, false>(get_lsb(state.get_piece_board(Color::White, PieceType::King)), NULL_SQUARE);
-    } else {
-        state.check = !state.is_square_safe::<{ Color::Black }, false>(get_lsb(state.get_piece_board(Color::Black, PieceType::King)), NULL_SQUARE);
-        state.ply += 1;
+    if opponent_in_check {
+        return Err(FenError::OpponentInCheck);
     }
+    state.check = check;
     state.hashcode = state.get_hash();
+    state.pawn_hashcode = state.get_pawn_hash();
+    state.incremental_eval = state.computed_incremental_eval();
+    state.refresh_accumulator();
     Ok(state)
 }
 
+impl State {
+    pub fn to_fen(&self) -> String {
+        const PIECE_CHARS: [char; 12] = ['K', 'Q', 'R', 'B', 'N', 'P', 'k', 'q', 'r', 'b', 'n', 'p'];
+        let mut ranks = Vec::with_capacity(8);
+        for rank in (0..8).rev() {
+            let mut rank_string = String::new();
+            let mut empty_count = 0;
+            for file in 0..8 {
+                let square_board = board_from_square(rank * 8 + file);
+                match (0..12).find(|i| self.board[*i] & square_board != EMPTY_BITBOARD) {
+                    Some(i) => {
+                        if empty_count > 0 {
+                            rank_string.push_str(&empty_count.to_string());
+                            empty_count = 0;
+                        }
+                        rank_string.push(PIECE_CHARS[i]);
+                    },
+                    None => empty_count += 1,
+                }
+            }
+            if empty_count > 0 {
+                rank_string.push_str(&empty_count.to_string());
+            }
+            ranks.push(rank_string);
+        }
+
+        let en_passant = if self.en_passant_square == NULL_SQUARE { "-".to_string() } else { pretty_string_square(self.en_passant_square) };
+        format!("{} {} {} {} {} {}",
+            ranks.join("/"),
+            if self.turn == Color::White { "w" } else { "b" },
+            self.castle_string(),
+            en_passant,
+            self.half_move_clock,
+            self.ply / 2 + 1,
+        )
+    }
+
+    // X-FEN, K and Q while the castling rook is the outermost one on its side and the rook's file otherwise. Standard
+    // positions come out as plain KQkq.
+    fn castle_string(&self) -> String {
+        let mut result = String::new();
+        for color in COLORS {
+            let king_square = get_lsb(self.get_piece_board(color, PieceType::King));
+            let rooks = self.get_piece_board(color, PieceType::Rook) & RANKS[rank(king_square) as usize];
+            for (side, side_char) in [(CastleAvailability::King, 'k'), (CastleAvailability::Queen, 'q')] {
+                if self.castle_availability[color as usize] as u8 & side as u8 == 0 {
+                    continue;
+                }
+                let rook_square = self.castle_squares.rook_squares[color as usize][side.side_index()];
+                let below_rook = board_from_square(rook_square) - 1;
+                let outer_rooks = match side {
+                    CastleAvailability::King => rooks & !below_rook & !board_from_square(rook_square),
+                    _ => rooks & below_rook,
+                };
+                let castle_char = if outer_rooks == EMPTY_BITBOARD { side_char } else { FILE_MAP[file(rook_square) as usize] };
+                result.push(if color == Color::White { castle_char.to_ascii_uppercase() } else { castle_char });
+            }
+        }
+        if result.is_empty() { "-".to_string() } else { result }
+    }
+}
+
+pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
 pub fn starting_fen() -> State {
-    parse_fen_string("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string()).unwrap()
+    parse_fen_string(STARTING_FEN.to_string()).unwrap()
 }
\ No newline at end of file
@@ -0,0 +1,112 @@
+use crate::{bitboard::{board_from_square, file, pop_lsb, rank, Bitboard, Color, COLORS, EMPTY_BITBOARD, FILES, RANKS}, eval_params::eval_params, evaluation::{relative_rank, PAWN_EVAL_LOOKUP_BOARDS}, piece_info::{PieceType, PAWN_ATTACK_BOARDS}, score::Score, state::State};
+
+// Power of two so the index is a mask of the pawn hash.
+const PAWN_TABLE_ENTRIES: usize = 1 << 16;
+
+// A fixed size array so probing before eval_info_init is still sound, empty entries have key 0.
+static mut PAWN_TABLE: [PawnEntry; PAWN_TABLE_ENTRIES] = [PawnEntry::EMPTY; PAWN_TABLE_ENTRIES];
+
+// Everything about a pawn structure that doesn't depend on the other pieces. Per side arrays are indexed by color and
+// scores are positive for that side.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct PawnEntry {
+    pub key: u64,
+    pub score: [Score; 2],
+    pub isolated_count: [u8; 2],
+    pub doubled_count: [u8; 2],
+    pub backward_count: [u8; 2],
+    pub connected_count: [u8; 2],
+    pub passed_count: [u8; 2],
+    pub passed_pawns: [Bitboard; 2],
+    // Squares the pawns attack now.
+    pub attacks: [Bitboard; 2],
+    // Squares the pawns could ever attack by advancing.
+    pub attack_spans: [Bitboard; 2],
+}
+
+impl PawnEntry {
+    pub const EMPTY: PawnEntry = PawnEntry {
+        key: 0,
+        score: [Score::ZERO; 2],
+        isolated_count: [0; 2],
+        doubled_count: [0; 2],
+        backward_count: [0; 2],
+        connected_count: [0; 2],
+        passed_count: [0; 2],
+        passed_pawns: [EMPTY_BITBOARD; 2],
+        attacks: [EMPTY_BITBOARD; 2],
+        attack_spans: [EMPTY_BITBOARD; 2],
+    };
+}
+
+// Needed whenever the eval parameters change. An empty entry is the correct entry for a board without pawns, whose
+// key is 0, so clearing never leaves a wrong result behind.
+#[cold]
+pub fn clear_pawn_table() {
+    unsafe { PAWN_TABLE.iter_mut().for_each(|entry| *entry = PawnEntry::EMPTY) };
+}
+
+impl State {
+    #[inline(always)]
+    pub fn pawn_entry(&self) -> PawnEntry {
+        let index = self.pawn_hashcode as usize & (PAWN_TABLE_ENTRIES - 1);
+        let entry = unsafe { &mut PAWN_TABLE[index] };
+        if entry.key != self.pawn_hashcode {
+            *entry = self.computed_pawn_entry();
+        }
+        *entry
+    }
+
+    pub fn computed_pawn_entry(&self) -> PawnEntry {
+        let params = eval_params();
+        let mut entry = PawnEntry { key: self.pawn_hashcode, ..Default::default() };
+        for color in COLORS {
+            let mut pawn_board = self.get_piece_board(color, PieceType::Pawn);
+            while pawn_board != EMPTY_BITBOARD {
+                entry.attacks[color as usize] |= unsafe { PAWN_ATTACK_BOARDS[color as usize][pop_lsb(&mut pawn_board) as usize] };
+            }
+        }
+        for color in COLORS {
+            let side = color as usize;
+            let mut mut_friend_pawn_board = self.get_piece_board(color, PieceType::Pawn);
+            let friend_pawn_board = self.get_piece_board(color, PieceType::Pawn);
+            let enemy_pawn_board = self.get_piece_board(color.other(), PieceType::Pawn);
+            while mut_friend_pawn_board != EMPTY_BITBOARD {
+                let pawn_square = pop_lsb(&mut mut_friend_pawn_board);
+                let (front_span, adjacent_files) = unsafe { (PAWN_EVAL_LOOKUP_BOARDS[pawn_square as usize][side], PAWN_EVAL_LOOKUP_BOARDS[pawn_square as usize][2]) };
+                let stop_square = match color { Color::White => pawn_square + 8, Color::Black => pawn_square - 8 };
+                entry.attack_spans[side] |= front_span & adjacent_files;
+                let isolated = adjacent_files & friend_pawn_board == EMPTY_BITBOARD;
+                if isolated {
+                    // Isolated Pawn
+                    entry.score[side] += params.pawn_isolated;
+                    entry.isolated_count[side] += 1;
+                }
+                if FILES[file(pawn_square) as usize] & friend_pawn_board != board_from_square(pawn_square) {
+                    // Doubled pawn
+                    entry.score[side] += params.pawn_doubled;
+                    entry.doubled_count[side] += 1;
+                }
+                if !isolated && adjacent_files & !front_span & friend_pawn_board == EMPTY_BITBOARD
+                    && board_from_square(stop_square) & entry.attacks[color.other() as usize] != EMPTY_BITBOARD {
+                    // Backward pawn, no neighbour can come to its defence and it can't advance safely.
+                    entry.score[side] += params.pawn_backward;
+                    entry.backward_count[side] += 1;
+                }
+                let phalanx = adjacent_files & RANKS[rank(pawn_square) as usize] & friend_pawn_board;
+                if board_from_square(pawn_square) & entry.attacks[side] != EMPTY_BITBOARD || phalanx != EMPTY_BITBOARD {
+                    // Connected pawn
+                    entry.score[side] += params.pawn_connected;
+                    entry.connected_count[side] += 1;
+                }
+                if front_span & enemy_pawn_board == EMPTY_BITBOARD {
+                    // Passed pawn
+                    entry.score[side] += params.passed_rank[relative_rank(color, pawn_square) as usize];
+                    entry.passed_count[side] += 1;
+                    entry.passed_pawns[side] |= board_from_square(pawn_square);
+                }
+            }
+        }
+        entry
+    }
+}
@@ -0,0 +1,388 @@
+use std::{fmt::Display, fs::{self, OpenOptions}, io::Write, time::Duration};
+
+use crate::{bitboard::Color, evaluation::{pretty_string_eval, Evaluation}, parsing::{parse_fen_string, starting_fen, STARTING_FEN}, r#move::Move, san::san_move_from_string, search::Depth, state::State};
+
+const LINE_LENGTH: usize = 80;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWin,
+    BlackWin,
+    Draw,
+    Unknown,
+}
+
+impl GameResult {
+    pub fn parse(result: &str) -> Option<GameResult> {
+        match result {
+            "1-0" => Some(GameResult::WhiteWin),
+            "0-1" => Some(GameResult::BlackWin),
+            "1/2-1/2" => Some(GameResult::Draw),
+            "*" => Some(GameResult::Unknown),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GameResult::WhiteWin => "1-0",
+            GameResult::BlackWin => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unknown => "*",
+        }
+    }
+}
+
+impl Display for GameResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// A move in the game tree. Variations are alternatives to this move, played from the position before it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgnMove {
+    pub m: Move,
+    pub nags: Vec<u8>,
+    pub comment_before: Option<String>,
+    pub comment: Option<String>,
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(m: Move) -> PgnMove {
+        PgnMove { m, nags: Vec::new(), comment_before: None, comment: None, variations: Vec::new() }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgnGame {
+    // In file order, new games start with the seven tag roster
+    pub tags: Vec<(String, String)>,
+    pub comment: Option<String>,
+    pub moves: Vec<PgnMove>,
+    pub result: GameResult,
+}
+
+impl PgnGame {
+    // Games from a position other than the standard start get the SetUp and FEN tags.
+    pub fn new(start_fen: Option<&str>) -> PgnGame {
+        let mut tags: Vec<(String, String)> = ["Event", "Site", "Date", "Round", "White", "Black"].iter()
+            .map(|name| (name.to_string(), "?".to_string()))
+            .collect();
+        tags.push(("Result".to_string(), GameResult::Unknown.as_str().to_string()));
+        if let Some(fen) = start_fen.filter(|fen| *fen != STARTING_FEN) {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), fen.to_string()));
+        }
+        PgnGame { tags, comment: None, moves: Vec::new(), result: GameResult::Unknown }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag_name, _)| tag_name == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag_name, _)| tag_name == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn set_result(&mut self, result: GameResult) {
+        self.result = result;
+        self.set_tag("Result", result.as_str());
+    }
+
+    pub fn start_state(&self) -> Result<State, String> {
+        match self.tag("FEN") {
+            Some(fen) => parse_fen_string(fen.to_string()).map_err(|err| format!("Invalid FEN tag: {}", err)),
+            None => Ok(starting_fen()),
+        }
+    }
+
+    // The position after the main line.
+    pub fn final_state(&self) -> Result<State, String> {
+        let mut state = self.start_state()?;
+        for pgn_move in &self.moves {
+            state.non_reversible_move(pgn_move.m);
+        }
+        Ok(state)
+    }
+
+    pub fn to_pgn(&self) -> Result<String, String> {
+        let mut output = String::new();
+        for (name, value) in &self.tags {
+            output += &format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\""));
+        }
+        output.push('\n');
+
+        let mut tokens = Vec::new();
+        if let Some(comment) = &self.comment {
+            tokens.push(format!("{{{}}}", comment));
+        }
+        let mut state = self.start_state()?;
+        write_line(&mut state, &self.moves, &mut tokens);
+        tokens.push(self.result.as_str().to_string());
+
+        let mut line_length = 0;
+        let mut after_variation_start = false;
+        for token in tokens {
+            if line_length > 0 && line_length + 1 + token.len() > LINE_LENGTH {
+                output.push('\n');
+                line_length = 0;
+            } else if line_length > 0 && !after_variation_start && token != ")" {
+                output.push(' ');
+                line_length += 1;
+            }
+            after_variation_start = token == "(";
+            line_length += token.len();
+            output += &token;
+        }
+        output += "\n\n";
+        Ok(output)
+    }
+}
+
+// The usual engine game comment, for example {+0.35/12 0.512s}.
+pub fn search_comment(eval: Evaluation, depth: Depth, time: Duration) -> String {
+    let eval_string = pretty_string_eval(eval);
+    let sign = if eval > 0 { "+" } else { "" };
+    format!("{}{}/{} {:.3}s", sign, eval_string, depth, time.as_secs_f64())
+}
+
+// Leaves the state as it was found.
+fn write_line(state: &mut State, moves: &[PgnMove], tokens: &mut Vec<String>) {
+    let mut needs_number = true;
+    for pgn_move in moves {
+        if let Some(comment) = &pgn_move.comment_before {
+            tokens.push(format!("{{{}}}", comment));
+            needs_number = true;
+        }
+        let move_number = state.ply / 2 + 1;
+        match state.turn {
+            Color::White => tokens.push(format!("{}.", move_number)),
+            Color::Black => if needs_number {
+                tokens.push(format!("{}...", move_number));
+            },
+        }
+        tokens.push(state.san_string_move(pgn_move.m));
+        tokens.extend(pgn_move.nags.iter().map(|nag| format!("${}", nag)));
+        needs_number = false;
+        if let Some(comment) = &pgn_move.comment {
+            tokens.push(format!("{{{}}}", comment));
+            needs_number = true;
+        }
+        for variation in &pgn_move.variations {
+            tokens.push("(".to_string());
+            write_line(state, variation, tokens);
+            tokens.push(")".to_string());
+            needs_number = true;
+        }
+        make_move(state, pgn_move.m);
+    }
+    for pgn_move in moves.iter().rev() {
+        unmake_move(state, pgn_move.m);
+    }
+}
+
+fn make_move(state: &mut State, m: Move) {
+    match state.turn {
+        Color::White => state.make_move::<{Color::White}>(m),
+        Color::Black => state.make_move::<{Color::Black}>(m),
+    };
+}
+
+fn unmake_move(state: &mut State, m: Move) {
+    match state.turn {
+        Color::White => state.unmake_move::<{Color::Black}>(m),
+        Color::Black => state.unmake_move::<{Color::White}>(m),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    VariationStart,
+    VariationEnd,
+    Result(GameResult),
+    // SAN with any move number stripped off
+    Move(String),
+}
+
+// Move suffix annotations are turned into their NAG.
+const SUFFIX_NAGS: [(&str, u8); 6] = [("!!", 3), ("??", 4), ("!?", 5), ("?!", 6), ("!", 1), ("?", 2)];
+
+fn tokenize(pgn: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = pgn.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => { line_start = true; continue },
+            // Escaped lines are for other programs
+            '%' if line_start => while chars.next_if(|c| *c != '\n').is_some() {},
+            ';' => while chars.next_if(|c| *c != '\n').is_some() {},
+            c if c.is_whitespace() => continue,
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => return Err("Unterminated comment".to_string()),
+                    }
+                }
+                tokens.push(Token::Comment(comment.split_whitespace().collect::<Vec<&str>>().join(" ")));
+            },
+            '[' => {
+                let mut tag = String::new();
+                let mut in_string = false;
+                loop {
+                    match chars.next() {
+                        Some('\\') if in_string => tag.extend(chars.next()),
+                        Some('"') => { in_string = !in_string; tag.push('"') },
+                        Some(']') if !in_string => break,
+                        Some(c) => tag.push(c),
+                        None => return Err("Unterminated tag".to_string()),
+                    }
+                }
+                let (name, value) = tag.trim().split_once(char::is_whitespace).ok_or(format!("Invalid tag [{}]", tag))?;
+                let value = value.trim().strip_prefix('"').and_then(|value| value.strip_suffix('"')).ok_or(format!("Invalid tag value in [{}]", tag))?;
+                tokens.push(Token::Tag(name.to_string(), value.to_string()));
+            },
+            '(' => tokens.push(Token::VariationStart),
+            ')' => tokens.push(Token::VariationEnd),
+            '$' => {
+                let mut number = String::new();
+                while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+                    number.push(digit);
+                }
+                tokens.push(Token::Nag(number.parse().map_err(|_| format!("Invalid NAG ${}", number))?));
+            },
+            c => {
+                let mut symbol = c.to_string();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"{}()[];$".contains(*c)) {
+                    symbol.push(c);
+                }
+                if let Some(result) = GameResult::parse(&symbol) {
+                    tokens.push(Token::Result(result));
+                } else {
+                    // Move numbers can be written apart from or joined to the move, 12. e4, 12...e5 or 12.e4. Digits
+                    // are only a move number when a dot follows so castling written as 0-0 is kept.
+                    let after_number = symbol.trim_start_matches(|c: char| c.is_ascii_digit());
+                    let san = match after_number.strip_prefix('.') {
+                        Some(rest) if after_number.len() < symbol.len() => rest.trim_start_matches('.'),
+                        _ => symbol.as_str(),
+                    };
+                    if !san.is_empty() {
+                        let annotation = SUFFIX_NAGS.iter().find(|(suffix, _)| san.ends_with(suffix));
+                        let san = san.trim_end_matches(['!', '?']);
+                        tokens.push(Token::Move(san.to_string()));
+                        if let Some((_, nag)) = annotation {
+                            tokens.push(Token::Nag(*nag));
+                        }
+                    }
+                }
+            },
+        }
+        line_start = false;
+    }
+    Ok(tokens)
+}
+
+// Reads every game in the text, variations are checked against the position they branch from.
+pub fn parse_pgn(pgn: &str) -> Result<Vec<PgnGame>, String> {
+    let tokens = tokenize(pgn)?;
+    let mut games = Vec::new();
+    let mut index = 0;
+    while index < tokens.len() {
+        let game_number = games.len() + 1;
+        let game = parse_game(&tokens, &mut index).map_err(|err| format!("Game {}: {}", game_number, err))?;
+        games.push(game);
+    }
+    Ok(games)
+}
+
+pub fn read_pgn_file(path: &str) -> Result<Vec<PgnGame>, String> {
+    let contents = fs::read_to_string(path).map_err(|err| format!("Unable to read PGN file {}: {}", path, err))?;
+    parse_pgn(&contents)
+}
+
+pub fn append_pgn_file(path: &str, game: &PgnGame) -> Result<(), String> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path).map_err(|err| format!("Unable to open PGN file {}: {}", path, err))?;
+    file.write_all(game.to_pgn()?.as_bytes()).map_err(|err| format!("Unable to write PGN file {}: {}", path, err))
+}
+
+fn parse_game(tokens: &[Token], index: &mut usize) -> Result<PgnGame, String> {
+    let mut game = PgnGame { tags: Vec::new(), comment: None, moves: Vec::new(), result: GameResult::Unknown };
+    while let Some(Token::Tag(name, value)) = tokens.get(*index) {
+        game.tags.push((name.clone(), value.clone()));
+        *index += 1;
+    }
+    if let Some(Token::Comment(comment)) = tokens.get(*index) {
+        game.comment = Some(comment.clone());
+        *index += 1;
+    }
+    let mut state = game.start_state()?;
+    game.moves = parse_line(&mut state, tokens, index)?;
+    match tokens.get(*index) {
+        Some(Token::Result(result)) => {
+            game.result = *result;
+            *index += 1;
+        },
+        // A game without a result marker ends where the next one's tags start
+        None | Some(Token::Tag(_, _)) => game.result = game.tag("Result").and_then(GameResult::parse).unwrap_or(GameResult::Unknown),
+        Some(Token::VariationEnd) => return Err("Unmatched )".to_string()),
+        Some(token) => return Err(format!("Unexpected {:?}", token)),
+    }
+    Ok(game)
+}
+
+// Parses moves until the end of the line, leaving the state as it was found.
+fn parse_line(state: &mut State, tokens: &[Token], index: &mut usize) -> Result<Vec<PgnMove>, String> {
+    let mut moves: Vec<PgnMove> = Vec::new();
+    let mut comment_before = None;
+    loop {
+        match tokens.get(*index) {
+            Some(Token::Move(san)) => {
+                let m = san_move_from_string(state, san).ok_or_else(|| format!("Illegal move {} in {}", san, state.to_fen()))?;
+                let mut pgn_move = PgnMove::new(m);
+                pgn_move.comment_before = comment_before.take();
+                make_move(state, m);
+                moves.push(pgn_move);
+            },
+            Some(Token::Nag(nag)) => moves.last_mut().ok_or("NAG before any move")?.nags.push(*nag),
+            Some(Token::Comment(comment)) => {
+                let target = match moves.last_mut() {
+                    Some(last) if comment_before.is_none() => &mut last.comment,
+                    _ => &mut comment_before,
+                };
+                *target = Some(match target.take() {
+                    Some(existing) => existing + " " + comment,
+                    None => comment.clone(),
+                });
+            },
+            Some(Token::VariationStart) => {
+                let last = moves.last().ok_or("Variation before any move")?.m;
+                // The variation replaces the last move
+                unmake_move(state, last);
+                *index += 1;
+                let variation = parse_line(state, tokens, index)?;
+                if tokens.get(*index) != Some(&Token::VariationEnd) {
+                    return Err("Unterminated variation".to_string());
+                }
+                make_move(state, last);
+                moves.last_mut().unwrap().variations.push(variation);
+            },
+            _ => break,
+        }
+        *index += 1;
+    }
+    for pgn_move in moves.iter().rev() {
+        unmake_move(state, pgn_move.m);
+    }
+    Ok(moves)
+}
@@ -85,6 +85,10 @@ pub const KNIGHT_STEPS: [Step; 8] = [KNIGHT_RIGHT_UP_STEP, KNIGHT_UP_RIGHT_STEP,
 pub static mut CAN_STEP_TABLE: [[bool; 64]; 16] = [[false; 64]; 16];
 pub static mut MOVE_BOARDS: [[Bitboard; 64]; 5] = [[EMPTY_BITBOARD; 64]; 5];
 pub static mut PAWN_ATTACK_BOARDS: [[Bitboard; 64]; 2] = [[EMPTY_BITBOARD; 64]; 2];
+// Squares strictly between two squares on the same rank, file or diagonal, empty otherwise.
+pub static mut BETWEEN_BOARDS: [[Bitboard; 64]; 64] = [[EMPTY_BITBOARD; 64]; 64];
+// The whole rank, file or diagonal through two aligned squares, empty otherwise.
+pub static mut LINE_BOARDS: [[Bitboard; 64]; 64] = [[EMPTY_BITBOARD; 64]; 64];
 
 impl PieceType {
     #[inline(always)]
@@ -139,6 +143,8 @@ pub fn move_gen_init() {
     fill_moves_boards::<{ PieceType::Knight }>();
     fill_moves_boards::<{ PieceType::Pawn }>();
     magic_init();
+    fill_line_boards::<{ PieceType::Rook }>();
+    fill_line_boards::<{ PieceType::Bishop }>();
 }
 
 #[cold]
@@ -211,6 +217,23 @@ fn fill_moves_boards<const P: PieceType>() {
     }
 }
 
+// Needs the magic tables for the between boards.
+#[cold]
+fn fill_line_boards<const P: PieceType>() {
+    let piece_index = P.index() as usize;
+    for a in 0..64 {
+        for b in 0..64 {
+            let (a_board, b_board) = (board_from_square(a), board_from_square(b));
+            if a != b && unsafe { MOVE_BOARDS[piece_index][a as usize] } & b_board != EMPTY_BITBOARD {
+                unsafe {
+                    LINE_BOARDS[a as usize][b as usize] = (MOVE_BOARDS[piece_index][a as usize] & MOVE_BOARDS[piece_index][b as usize]) | a_board | b_board;
+                    BETWEEN_BOARDS[a as usize][b as usize] = move_bitboard::<P>(a, b_board) & move_bitboard::<P>(b, a_board);
+                }
+            }
+        }
+    }
+}
+
 #[inline(always)]
 pub fn move_bitboard<const P: PieceType>(square: Square, occupied: Bitboard) -> Bitboard{
     debug_assert!(is_valid_square(square));
@@ -0,0 +1,170 @@
+use crate::{bitboard::{board_from_square, file, pretty_string_square, rank, Color, Square, EMPTY_BITBOARD, FILE_MAP}, parsing::square_from_string, piece_info::PieceType, r#move::{move_destination_square, move_origin_square, move_special_info, move_special_type, Move, BISHOP_PROMOTION, CASTLE_SPECIAL_MOVE, EN_PASSANT_SPECIAL_MOVE, KING_CASTLE, KNIGHT_PROMOTION, NULL_MOVE, PROMOTION_SPECIAL_MOVE, QUEEN_CASTLE, QUEEN_PROMOTION, ROOK_PROMOTION}, state::State};
+
+// Standard Algebraic Notation as used by PGN and EPD, for example Nbd7, exd6, e8=Q+ or O-O-O#.
+impl State {
+    // The move has to be legal in this position. It is made and unmade again to find the check and mate suffix.
+    pub fn san_string_move(&mut self, m: Move) -> String {
+        if m == NULL_MOVE {
+            return "--".to_string();
+        }
+        let mut san = self.san_string_without_suffix(m);
+        let gives_check = match self.turn {
+            Color::White => self.make_move::<{Color::White}>(m) && self.check,
+            Color::Black => self.make_move::<{Color::Black}>(m) && self.check,
+        };
+        if gives_check {
+            san.push(if self.legal_moves().is_empty() { '#' } else { '+' });
+        }
+        match self.turn {
+            Color::White => self.unmake_move::<{Color::Black}>(m),
+            Color::Black => self.unmake_move::<{Color::White}>(m),
+        }
+        san
+    }
+
+    fn san_string_without_suffix(&self, m: Move) -> String {
+        if move_special_type(m) == CASTLE_SPECIAL_MOVE {
+            return if move_special_info(m) == QUEEN_CASTLE { "O-O-O".to_string() } else { "O-O".to_string() };
+        }
+        let origin = move_origin_square(m);
+        let destination = move_destination_square(m);
+        let piece_type = self.side_to_move_piece_at(origin).expect("No piece to move");
+        let is_capture = move_special_type(m) == EN_PASSANT_SPECIAL_MOVE
+            || self.side_occupied[self.turn.other() as usize] & board_from_square(destination) != EMPTY_BITBOARD;
+
+        let mut san = String::new();
+        if piece_type == PieceType::Pawn {
+            if is_capture {
+                san.push(FILE_MAP[file(origin) as usize]);
+            }
+        } else {
+            san.push(piece_char(piece_type));
+            san += &self.disambiguation(m, piece_type);
+        }
+        if is_capture {
+            san.push('x');
+        }
+        san += &pretty_string_square(destination);
+        if move_special_type(m) == PROMOTION_SPECIAL_MOVE {
+            san.push('=');
+            san.push(piece_char(promotion_piece(move_special_info(m))));
+        }
+        san
+    }
+
+    // The file is preferred, then the rank, and the full square only when neither tells the pieces apart.
+    fn disambiguation(&self, m: Move, piece_type: PieceType) -> String {
+        let origin = move_origin_square(m);
+        let others: Vec<Square> = self.legal_moves().iter()
+            .filter(|other| **other != m && move_special_type(**other) != CASTLE_SPECIAL_MOVE && move_destination_square(**other) == move_destination_square(m))
+            .map(|other| move_origin_square(*other))
+            .filter(|other_origin| self.side_to_move_piece_at(*other_origin) == Some(piece_type))
+            .collect();
+        if others.is_empty() {
+            String::new()
+        } else if others.iter().all(|other| file(*other) != file(origin)) {
+            FILE_MAP[file(origin) as usize].to_string()
+        } else if others.iter().all(|other| rank(*other) != rank(origin)) {
+            (rank(origin) + 1).to_string()
+        } else {
+            pretty_string_square(origin)
+        }
+    }
+
+    fn side_to_move_piece_at(&self, square: Square) -> Option<PieceType> {
+        match self.turn {
+            Color::White => self.get_colored_piece_at_square::<{Color::White}>(square),
+            Color::Black => self.get_colored_piece_at_square::<{Color::Black}>(square),
+        }
+    }
+}
+
+// Resolves the move against the legal moves of the position. Check, mate and annotation suffixes are ignored, as are
+// missing or extra capture marks, and promotions are accepted with or without the equals sign. Ambiguous moves are
+// rejected.
+pub fn san_move_from_string(state: &State, san: &str) -> Option<Move> {
+    let san = san.trim_end_matches(['+', '#', '!', '?']);
+    let legal_moves = state.legal_moves();
+    let castle = match san {
+        "O-O" | "0-0" => Some(KING_CASTLE),
+        "O-O-O" | "0-0-0" => Some(QUEEN_CASTLE),
+        _ => None,
+    };
+    if let Some(castle) = castle {
+        return legal_moves.iter().copied().find(|m| move_special_type(*m) == CASTLE_SPECIAL_MOVE && move_special_info(*m) == castle);
+    }
+    if !san.is_ascii() {
+        return None;
+    }
+
+    let (san, promotion) = match san.as_bytes() {
+        [.., b'=', piece] => (&san[..san.len() - 2], Some(*piece as char)),
+        [.., b'1'..=b'8', piece @ (b'Q' | b'R' | b'B' | b'N')] => (&san[..san.len() - 1], Some(*piece as char)),
+        _ => (san, None),
+    };
+    let promotion = match promotion {
+        Some('Q') => Some(QUEEN_PROMOTION),
+        Some('R') => Some(ROOK_PROMOTION),
+        Some('B') => Some(BISHOP_PROMOTION),
+        Some('N') => Some(KNIGHT_PROMOTION),
+        Some(_) => return None,
+        None => None,
+    };
+    if san.len() < 2 {
+        return None;
+    }
+    let (rest, destination) = san.split_at(san.len() - 2);
+    let destination = square_from_string(destination.to_string())?;
+    let (piece_type, disambiguation) = match rest.chars().next() {
+        Some('K') => (PieceType::King, &rest[1..]),
+        Some('Q') => (PieceType::Queen, &rest[1..]),
+        Some('R') => (PieceType::Rook, &rest[1..]),
+        Some('B') => (PieceType::Bishop, &rest[1..]),
+        Some('N') => (PieceType::Knight, &rest[1..]),
+        _ => (PieceType::Pawn, rest),
+    };
+    let disambiguation = disambiguation.trim_end_matches(['x', ':']);
+
+    let mut candidates = legal_moves.iter().copied().filter(|m| {
+        let origin = move_origin_square(*m);
+        let is_promotion = move_special_type(*m) == PROMOTION_SPECIAL_MOVE;
+        move_special_type(*m) != CASTLE_SPECIAL_MOVE
+            && move_destination_square(*m) == destination
+            && state.side_to_move_piece_at(origin) == Some(piece_type)
+            && promotion == is_promotion.then(|| move_special_info(*m))
+            && disambiguation.chars().all(|c| matches_square(c, origin))
+    });
+    let m = candidates.next()?;
+    if candidates.next().is_some() {
+        return None;
+    }
+    Some(m)
+}
+
+fn matches_square(c: char, square: Square) -> bool {
+    match c {
+        'a'..='h' => file(square) == c as u8 - b'a',
+        '1'..='8' => rank(square) == c as u8 - b'1',
+        _ => false,
+    }
+}
+
+fn piece_char(piece_type: PieceType) -> char {
+    match piece_type {
+        PieceType::King => 'K',
+        PieceType::Queen => 'Q',
+        PieceType::Rook => 'R',
+        PieceType::Bishop => 'B',
+        PieceType::Knight => 'N',
+        PieceType::Pawn => 'P',
+    }
+}
+
+fn promotion_piece(promotion: u8) -> PieceType {
+    match promotion {
+        QUEEN_PROMOTION => PieceType::Queen,
+        ROOK_PROMOTION => PieceType::Rook,
+        BISHOP_PROMOTION => PieceType::Bishop,
+        _ => PieceType::Knight,
+    }
+}
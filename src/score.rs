@@ -0,0 +1,86 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+use crate::evaluation::{Evaluation, TOTAL_PHASE_VALUE};
+
+// A midgame and endgame evaluation packed into one integer so both halves are added together in a single operation.
+// The endgame value is stored in the low 32 bits and the midgame value in the high 32 bits, any borrow from a negative
+// endgame value is undone when unpacking.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Score(i64);
+
+impl Score {
+    pub const ZERO: Score = Score(0);
+
+    #[inline(always)]
+    pub const fn new(midgame: Evaluation, endgame: Evaluation) -> Score {
+        Score(((midgame as i64) << 32) + endgame as i64)
+    }
+
+    #[inline(always)]
+    pub const fn midgame(self) -> Evaluation {
+        ((self.0 + 0x8000_0000) >> 32) as Evaluation
+    }
+
+    #[inline(always)]
+    pub const fn endgame(self) -> Evaluation {
+        self.0 as Evaluation
+    }
+
+    // Interpolates between the two values, phase being the midgame weight out of TOTAL_PHASE_VALUE.
+    #[inline(always)]
+    pub const fn blend(self, phase: u8) -> Evaluation {
+        ((self.midgame() as i64 * phase as i64 + self.endgame() as i64 * (TOTAL_PHASE_VALUE - phase) as i64) / TOTAL_PHASE_VALUE as i64) as Evaluation
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+    #[inline(always)]
+    fn add(self, rhs: Score) -> Score {
+        Score(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Score {
+    #[inline(always)]
+    fn add_assign(&mut self, rhs: Score) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+    #[inline(always)]
+    fn sub(self, rhs: Score) -> Score {
+        Score(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for Score {
+    #[inline(always)]
+    fn sub_assign(&mut self, rhs: Score) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+    #[inline(always)]
+    fn neg(self) -> Score {
+        Score(-self.0)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+    #[inline(always)]
+    fn mul(self, rhs: i32) -> Score {
+        Score(self.0 * rhs as i64)
+    }
+}
+
+impl std::fmt::Debug for Score {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Score({}, {})", self.midgame(), self.endgame())
+    }
+}
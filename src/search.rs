@@ -1,6 +1,6 @@
 use std::{hint::unreachable_unchecked, time::{Duration, Instant}};
 
-use crate::{bitboard::{board_from_square, Color, EMPTY_BITBOARD}, evaluation::{mate_in, pretty_string_eval, unchecked_eval_clamp, Evaluation, CENTI_PAWN, HIGHEST_EVAL, LOWEST_EVAL, MATE_VALUE_CUTOFF, NEGATIVE_MATE_ZERO}, r#move::{move_destination_square, move_origin_square, pretty_string_move, Move, NULL_MOVE}, move_pick::{MovePickType, HISTORY_TABLE}, piece_info::{KING, PAWN}, state::State, transposition::{add_tt_state, eval_convert_precision_low_to_high, parse_packed_depth_and_node, search_tt_state, NodeType}, worker::Worker};
+use crate::{bitboard::Color, evaluation::{mate_in, pretty_string_eval, unchecked_eval_clamp, Evaluation, CENTI_PAWN, HIGHEST_EVAL, LOWEST_EVAL, MATE_VALUE_CUTOFF, NEGATIVE_MATE_ZERO}, r#move::{move_special_type, pretty_string_move, Move, NULL_MOVE, PROMOTION_SPECIAL_MOVE}, move_list::{MAX_CAPTURE_MOVES, MAX_QUIET_MOVES}, move_pick::{age_history_tables, MovePickType}, piece_info::{KING, PAWN}, state::State, transposition::{add_quiescence_tt_state, add_tt_state, eval_convert_precision_low_to_high, parse_packed_depth_and_node, search_tt_state, NodeType}, worker::Worker};
 
 pub type Depth = i32;
 pub type Reduction = i32;
@@ -12,18 +12,58 @@ const ASPIRATION_MATE_CUTOFF: Evaluation = 2000 * CENTI_PAWN;
 
 const INTERNAL_IDS_DEPTH: Depth = 5;
 
+// Exactly scored endgames finish every iteration almost instantly so the depth needs a cap.
+pub const MAX_SEARCH_DEPTH: Depth = 100;
+
 const REDUCTION_FACTOR: Reduction = 1024;
 
 const FUTILITY_MARGIN: Evaluation = CENTI_PAWN * 200;
 
+const DELTA_MARGIN: Evaluation = CENTI_PAWN * 200;
+// Long capture or evasion sequences past the depth cap are cut off at the static eval so mate scores stay encodable.
+const MAX_QUIESCENCE_PLY: Depth = 127;
+
 const NULL_MOVE_REDUCTION: Depth = 2;
+
+const PROBCUT_DEPTH: Depth = 5;
+const PROBCUT_REDUCTION: Depth = 4;
+const PROBCUT_MARGIN: Evaluation = CENTI_PAWN * 100;
 static mut LATE_MOVE_REDUCTION_TABLE: [[Reduction; 64]; 64] = [[0; 64]; 64];
 
+// Limits are checked every this many nodes while searching.
+const LIMIT_CHECK_INTERVAL: u64 = 1024;
+
+// Any combination of limits can be set, the search stops at whichever runs out first. Without any it runs to
+// MAX_SEARCH_DEPTH.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchLimits {
+    pub time: Option<Duration>,
+    pub depth: Option<Depth>,
+    pub nodes: Option<u64>,
+}
+
+impl SearchLimits {
+    pub fn from_time(time: Duration) -> SearchLimits {
+        SearchLimits { time: Some(time), ..SearchLimits::default() }
+    }
+}
+
 impl Worker {
     pub fn iterative_deepening_search(&mut self, state: &mut State, search_time: Duration, info_print: bool) -> Move {
+        self.limited_search(state, SearchLimits::from_time(search_time), info_print)
+    }
+
+    pub fn limited_search(&mut self, state: &mut State, limits: SearchLimits, info_print: bool) -> Move {
         let start = Instant::now();
         let start_node_count = self.nodes_searched;
         self.root_ply = state.ply;
+        self.limits = limits;
+        self.search_start = start;
+        self.start_node_count = start_node_count;
+        self.stopped = false;
+        self.can_stop = false;
+        let max_depth = limits.depth.unwrap_or(MAX_SEARCH_DEPTH).min(MAX_SEARCH_DEPTH);
+        age_history_tables();
 
         let mut eval_guess = self.last_ids_score;
         let mut aspiration_delta = ASPIRATION_OFFSET[0];
@@ -32,7 +72,7 @@ impl Worker {
         let mut best_move = NULL_MOVE;
         let mut current_depth = 1;
 
-        while start.elapsed() < search_time {
+        while current_depth <= max_depth && !self.limit_reached() {
             if aspiration_window_high > ASPIRATION_MATE_CUTOFF {
                 aspiration_window_low = ASPIRATION_MATE_CUTOFF - (200 * CENTI_PAWN);
                 aspiration_window_high = HIGHEST_EVAL;
@@ -41,13 +81,20 @@ impl Worker {
                 aspiration_window_high = -ASPIRATION_MATE_CUTOFF + (200 * CENTI_PAWN);
             }
             if info_print {
-                println!("Search time left: {:?}", search_time - start.elapsed());
+                if let Some(search_time) = limits.time {
+                    println!("Search time left: {:?}", search_time.saturating_sub(start.elapsed()));
+                }
                 println!("Searching next depth with window [{}, {}]", pretty_string_eval(aspiration_window_low), pretty_string_eval(aspiration_window_high));
             }
             let (new_score, new_move) = match state.turn {
                 Color::White => self.negamax::<{Color::White}>(state, current_depth, aspiration_window_low, aspiration_window_high),
                 Color::Black => self.negamax::<{Color::Black}>(state, current_depth, aspiration_window_low, aspiration_window_high),
             };
+            // Whatever the unfinished iteration found can't be trusted
+            if self.stopped {
+                state.current_move_list().reset();
+                break;
+            }
             if info_print {
                 println!("Searched to depth: {}, Best move: {}, Move eval: {}", current_depth, pretty_string_move(new_move), pretty_string_eval(new_score));
             }
@@ -85,6 +132,7 @@ impl Worker {
                 }
                 current_depth += 1;
                 best_move = new_move;
+                self.can_stop = true;
                 eval_guess = new_score;
                 aspiration_delta = ASPIRATION_OFFSET[usize::min(current_depth as usize, MAX_ASPIRATION_OFFSET_INDEX - 1)];
                 if eval_guess.abs() < MATE_VALUE_CUTOFF { 
@@ -96,7 +144,7 @@ impl Worker {
         }
         self.last_ids_score = eval_guess;
         if info_print {
-            println!("Best move: {}", pretty_string_move(best_move));
+            println!("Best move: {}", state.san_string_move(best_move));
             println!("Move evaluation: {}", pretty_string_eval(eval_guess));
             println!("Total moves searched: {}", self.nodes_searched - start_node_count);
             println!("Total search time: {:?}", start.elapsed());
@@ -105,6 +153,19 @@ impl Worker {
         best_move
     }
 
+    fn limit_reached(&self) -> bool {
+        self.limits.time.is_some_and(|time| self.search_start.elapsed() >= time)
+            || self.limits.nodes.is_some_and(|nodes| self.nodes_searched - self.start_node_count >= nodes)
+    }
+
+    #[inline(always)]
+    fn should_stop(&mut self) -> bool {
+        if !self.stopped && self.can_stop && self.nodes_searched.is_multiple_of(LIMIT_CHECK_INTERVAL) && self.limit_reached() {
+            self.stopped = true;
+        }
+        self.stopped
+    }
+
     pub fn negamax<const C: Color>(&mut self, state: &mut State, mut depth: Depth, mut alpha: Evaluation, beta: Evaluation) -> (Evaluation, Move) {
         debug_assert_eq!(C, state.turn);
         debug_assert!(alpha < beta); 
@@ -116,6 +177,9 @@ impl Worker {
         
         depth = depth.max(0);
         self.nodes_searched += 1;
+        if self.should_stop() {
+            return (alpha, NULL_MOVE);
+        }
         let is_root = state.ply == self.root_ply;
         
         if depth == 0 {
@@ -126,6 +190,7 @@ impl Worker {
             return result;
         }
 
+        let mut hash_move = NULL_MOVE;
         let tt_result = search_tt_state(state);
         if let Some(result) = tt_result {
             let tt_eval = eval_convert_precision_low_to_high(result.eval);
@@ -150,17 +215,16 @@ impl Worker {
                     _ => {debug_assert!(false); unsafe {unreachable_unchecked()}},
                 }
             }
-            if tt_best_move != NULL_MOVE {
-                state.current_move_list().add_tt_move(tt_best_move);
-            }
-
-        } else if depth >= INTERNAL_IDS_DEPTH {
+            hash_move = tt_best_move;
+        }
+        // Quiescence entries are stored at depth 0 and don't replace internal iterative deepening.
+        if depth >= INTERNAL_IDS_DEPTH && tt_result.is_none_or(|result| parse_packed_depth_and_node(result.packed_depth_and_node).0 == 0) {
             // Internal iterative deepening search for getting a good first move.
-            let iids_suggested_move = self.negamax::<C>(state, depth / 2, alpha, beta).1;
+            hash_move = self.negamax::<C>(state, depth / 2, alpha, beta).1;
             // Reset the move list as after search it is in a garbage state.
             state.current_move_list().reset();
-            if iids_suggested_move != NULL_MOVE {
-                state.current_move_list().add_tt_move(iids_suggested_move);
+            if self.stopped {
+                return (alpha, NULL_MOVE);
             }
         }
 
@@ -174,12 +238,58 @@ impl Worker {
                     Color::Black => -self.negamax::<{Color::White}>(state, depth-NULL_MOVE_REDUCTION-1, -beta, -beta+1).0,
                 };
                 state.un_passing_move::<C>();
+                if self.stopped {
+                    return (alpha, NULL_MOVE);
+                }
                 if score >= beta {
                     return (beta, NULL_MOVE);
                 }
             } 
         }
 
+        // ProbCut. If a capture beats beta by a clear margin in a shallow search it will very likely beat beta
+        // in the full depth search, so the node can be cut without confirming it.
+        if depth >= PROBCUT_DEPTH && !state.check && !is_root && beta.abs() < ASPIRATION_MATE_CUTOFF {
+            let probcut_beta = beta + PROBCUT_MARGIN;
+            let static_eval = state.eval_state(C);
+            while state.pick_next_move::<{MovePickType::Quiescence}>() {
+                let current_move = state.current_move_list().current;
+                // Captures that can't reach probcut beta even if the captured piece is free are not worth trying.
+                if static_eval + state.move_victim_value(current_move) < probcut_beta {
+                    continue;
+                }
+                if state.make_move::<C>(current_move) {
+                    // Verify with a quiescence search first as it is much cheaper than the reduced search.
+                    let mut score = match C {
+                        Color::White => -self.quiescence_search::<{Color::Black}>(state, -probcut_beta, -probcut_beta + 1).0,
+                        Color::Black => -self.quiescence_search::<{Color::White}>(state, -probcut_beta, -probcut_beta + 1).0,
+                    };
+                    if score >= probcut_beta {
+                        score = match C {
+                            Color::White => -self.negamax::<{Color::Black}>(state, depth - PROBCUT_REDUCTION - 1, -probcut_beta, -probcut_beta + 1).0,
+                            Color::Black => -self.negamax::<{Color::White}>(state, depth - PROBCUT_REDUCTION - 1, -probcut_beta, -probcut_beta + 1).0,
+                        };
+                    }
+                    if self.stopped {
+                        state.unmake_move::<C>(current_move);
+                        return (alpha, NULL_MOVE);
+                    }
+                    if score >= probcut_beta {
+                        state.unmake_move::<C>(current_move);
+                        add_tt_state(state, score, current_move, depth - PROBCUT_REDUCTION, NodeType::CutNode);
+                        return (score, current_move);
+                    }
+                }
+                state.unmake_move::<C>(current_move);
+            }
+            // The capture picking leaves the move list in a garbage state.
+            state.current_move_list().reset();
+        }
+
+        if hash_move != NULL_MOVE {
+            state.current_move_list().add_tt_move(hash_move);
+        }
+
         // Futility pruning
         let mut is_futile = false;
         if depth == 1 && !state.check && alpha > -ASPIRATION_MATE_CUTOFF && beta < ASPIRATION_MATE_CUTOFF {
@@ -193,8 +303,13 @@ impl Worker {
 
         let mut best_move = NULL_MOVE;
         let mut move_count = 0;
+        let mut failed_quiets = [NULL_MOVE; MAX_QUIET_MOVES];
+        let mut failed_quiet_count = 0;
+        let mut failed_captures = [NULL_MOVE; MAX_CAPTURE_MOVES];
+        let mut failed_capture_count = 0;
         while state.pick_next_move::<{MovePickType::Negamax}>() {
             let current_move = state.current_move_list().current;
+            let is_quiet = state.is_quiet(current_move);
             let mut reduction = 0;
             if state.make_move::<C>(current_move) {
                 let mut score;
@@ -230,24 +345,32 @@ impl Worker {
                     };
                 }
 
+                if self.stopped {
+                    state.unmake_move::<C>(current_move);
+                    return (alpha, NULL_MOVE);
+                }
                 if score >= beta {
                     state.unmake_move::<C>(current_move);
                     add_tt_state(state, score, current_move, depth, NodeType::CutNode);
-                    // add to history table if quiet move
-                    if state.side_occupied[C.other() as usize] & board_from_square(move_destination_square(current_move)) == EMPTY_BITBOARD {
-                        let src_piece_type = match C {
-                            Color::White => state.force_get_colored_piece_at_square::<{Color::White}>(move_origin_square(current_move)),
-                            Color::Black => state.force_get_colored_piece_at_square::<{Color::Black}>(move_origin_square(current_move)),
-                        };
-                        unsafe { HISTORY_TABLE [src_piece_type as usize][move_destination_square(current_move) as usize] += (depth * depth) as u64};
+                    // update quiet histories and killers if quiet move
+                    if is_quiet {
+                        state.update_quiet_histories(current_move, depth, &failed_quiets[..failed_quiet_count]);
                         state.current_move_list().add_killer(current_move);
                     }
+                    state.update_capture_histories(current_move, depth, &failed_captures[..failed_capture_count]);
                     return (score, current_move);
                 }
                 if score > alpha {
                     best_move = current_move;
                     alpha = score;
                 }
+                if is_quiet && failed_quiet_count < MAX_QUIET_MOVES {
+                    failed_quiets[failed_quiet_count] = current_move;
+                    failed_quiet_count += 1;
+                } else if !is_quiet && failed_capture_count < MAX_CAPTURE_MOVES {
+                    failed_captures[failed_capture_count] = current_move;
+                    failed_capture_count += 1;
+                }
                 move_count += 1;
             }
             state.unmake_move::<C>(current_move);
@@ -278,24 +401,70 @@ impl Worker {
         debug_assert_eq!(C, state.turn);
         debug_assert!(alpha < beta);
         self.nodes_searched += 1;
-        let current_eval = state.eval_state(C);
-        if current_eval >= beta {
-            return (beta, NULL_MOVE);
+        if self.should_stop() {
+            return (alpha, NULL_MOVE);
+        }
+
+        if self.true_depth(state.ply) >= MAX_QUIESCENCE_PLY {
+            return (state.eval_state(C).clamp(alpha, beta), NULL_MOVE);
+        }
+
+        // Quiescence is below every negamax depth so any stored bound can be used here. Results are stored at depth 0
+        // with add_quiescence_tt_state so they never evict a deeper negamax entry.
+        if let Some(result) = search_tt_state(state) {
+            let tt_eval = eval_convert_precision_low_to_high(result.eval);
+            let (_, tt_node_type) = parse_packed_depth_and_node(result.packed_depth_and_node);
+            match tt_node_type {
+                NodeType::TerminalNode => {
+                    let return_eval = if tt_eval == 0 {
+                        unchecked_eval_clamp(0, alpha, beta)
+                    } else {
+                        unchecked_eval_clamp(mate_in(self.true_depth(state.ply), true), alpha, beta)
+                    };
+                    return (return_eval, NULL_MOVE);
+                },
+                NodeType::PVNode => { if tt_eval >= alpha && tt_eval <= beta { return (tt_eval, result.best_move) } },
+                NodeType::CutNode => { if tt_eval >= beta { return (beta, result.best_move) } },
+                NodeType::AllNode => { if tt_eval <= alpha { return (alpha, result.best_move) } },
+            }
         }
-        if alpha < current_eval {
-            alpha = current_eval;
+
+        // When in check standing pat is not an option, all evasions are searched instead.
+        let in_check = state.check;
+        let mut stand_pat = LOWEST_EVAL;
+        if !in_check {
+            stand_pat = state.eval_state(C);
+            if stand_pat >= beta {
+                add_quiescence_tt_state(state, stand_pat, NULL_MOVE, NodeType::CutNode);
+                return (beta, NULL_MOVE);
+            }
+            if alpha < stand_pat {
+                alpha = stand_pat;
+            }
         }
 
         let mut best_move = NULL_MOVE;
+        let mut move_count = 0;
         while state.pick_next_move::<{MovePickType::Quiescence}>() {
             let current_move = state.current_move_list().current;
+            // Delta pruning. Skip captures that can't raise alpha even if the captured piece is won for free.
+            if !in_check && move_special_type(current_move) != PROMOTION_SPECIAL_MOVE
+                && stand_pat + state.move_victim_value(current_move) + DELTA_MARGIN <= alpha {
+                continue;
+            }
             if state.make_move::<C>(current_move) {
+                move_count += 1;
                 let score = match C {
                     Color::White => -self.quiescence_search::<{Color::Black}>(state, -beta, -alpha).0,
                     Color::Black => -self.quiescence_search::<{Color::White}>(state, -beta, -alpha).0,
                 };
+                if self.stopped {
+                    state.unmake_move::<C>(current_move);
+                    return (alpha, NULL_MOVE);
+                }
                 if score >= beta {
                     state.unmake_move::<C>(current_move);
+                    add_quiescence_tt_state(state, score, current_move, NodeType::CutNode);
                     return (beta, current_move);
                 }
                 if score > alpha {
@@ -305,6 +474,18 @@ impl Worker {
             }
             state.unmake_move::<C>(current_move);
         }
+
+        // All evasions were generated so no legal moves while in check is mate.
+        if in_check && move_count == 0 {
+            add_quiescence_tt_state(state, NEGATIVE_MATE_ZERO, NULL_MOVE, NodeType::TerminalNode);
+            return (mate_in(self.true_depth(state.ply), true).clamp(alpha, beta), NULL_MOVE);
+        }
+
+        if best_move == NULL_MOVE {
+            add_quiescence_tt_state(state, alpha, NULL_MOVE, NodeType::AllNode);
+        } else {
+            add_quiescence_tt_state(state, alpha, best_move, NodeType::PVNode);
+        }
         (alpha, best_move)
     }
 }
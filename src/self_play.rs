@@ -0,0 +1,408 @@
+use std::{env, fs, io::{BufRead, BufReader, Write}, process::{Child, ChildStdin, ChildStdout, Command, Stdio}, time::Duration};
+
+use crate::{bitboard::{bit_count, Color, COLORS, DARK_SQUARES, EMPTY_BITBOARD, LIGHT_SQUARES}, epd::parse_epd_line, parsing::{parse_fen_string, uci_move_from_string, STARTING_FEN}, pgn::{append_pgn_file, read_pgn_file, GameResult, PgnGame, PgnMove}, piece_info::PieceType, r#move::{uci_string_move, Move}, search::{Depth, SearchLimits}, state::State};
+
+const DEFAULT_MOVE_TIME: Duration = Duration::from_millis(100);
+const DEFAULT_GAMES: usize = 100;
+const DEFAULT_HASH_SIZE: usize = 64;
+// Games this long are scored as draws, neither side is making progress.
+const MAX_GAME_PLIES: usize = 600;
+
+// An engine is either the default setup, a parameter file, or UCI options in the form Name=Value,Name=Value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EngineConfig {
+    pub name: String,
+    pub options: Vec<(String, String)>,
+}
+
+impl EngineConfig {
+    pub fn parse(spec: &str) -> Result<EngineConfig, String> {
+        let options = if spec == "default" {
+            Vec::new()
+        } else if !spec.contains('=') {
+            vec![("EvalParams".to_string(), spec.to_string())]
+        } else {
+            spec.split(',').map(|option| {
+                let (name, value) = option.split_once('=').ok_or(format!("Expected Name=Value, found {}", option))?;
+                Ok((name.to_string(), value.to_string()))
+            }).collect::<Result<Vec<(String, String)>, String>>()?
+        };
+        Ok(EngineConfig { name: spec.to_string(), options })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SprtConfig {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+pub struct MatchConfig {
+    pub engines: [EngineConfig; 2],
+    pub games: usize,
+    pub limits: SearchLimits,
+    // Transposition table size of each engine in megabytes
+    pub hash_size: usize,
+    pub openings_path: Option<String>,
+    pub pgn_path: Option<String>,
+    // The match stops as soon as the test concludes
+    pub sprt: Option<SprtConfig>,
+}
+
+// Expects the tokens after match, in the form <engine> <engine> [games <n>] [movetime <ms>] [depth <plies>]
+// [nodes <count>] [hash <mb>] [openings <file>] [pgn <file>] [sprt <elo0> <elo1> [<alpha> <beta>]]
+pub fn parse_match_command(tokens: &[&str]) -> Result<MatchConfig, String> {
+    let first = EngineConfig::parse(tokens.first().ok_or("Expected two engines")?)?;
+    let second = EngineConfig::parse(tokens.get(1).ok_or("Expected two engines")?)?;
+    let mut config = MatchConfig {
+        engines: [first, second],
+        games: DEFAULT_GAMES,
+        limits: SearchLimits::default(),
+        hash_size: DEFAULT_HASH_SIZE,
+        openings_path: None,
+        pgn_path: None,
+        sprt: None,
+    };
+    let mut index = 2;
+    while index < tokens.len() {
+        let name = tokens[index];
+        let value = |offset: usize| tokens.get(index + offset).ok_or(format!("Expected a value after {}", name));
+        let number = |offset: usize| -> Result<f64, String> {
+            let value = value(offset)?;
+            value.parse().map_err(|_| format!("Invalid {} value: {}", name, value))
+        };
+        match name {
+            "games" => config.games = number(1)? as usize,
+            "movetime" => config.limits.time = Some(Duration::from_millis(number(1)? as u64)),
+            "depth" => config.limits.depth = Some(number(1)? as Depth),
+            "nodes" => config.limits.nodes = Some(number(1)? as u64),
+            "hash" => config.hash_size = number(1)? as usize,
+            "openings" => config.openings_path = Some(value(1)?.to_string()),
+            "pgn" => config.pgn_path = Some(value(1)?.to_string()),
+            "sprt" => {
+                let (elo0, elo1) = (number(1)?, number(2)?);
+                // Error rates are optional and default to 5%
+                let rates = tokens.get(index + 3).and_then(|alpha| alpha.parse::<f64>().ok())
+                    .zip(tokens.get(index + 4).and_then(|beta| beta.parse::<f64>().ok()));
+                let (alpha, beta) = rates.unwrap_or((0.05, 0.05));
+                config.sprt = Some(SprtConfig { elo0, elo1, alpha, beta });
+                index += if rates.is_some() { 3 } else { 1 };
+            },
+            other => return Err(format!("Unknown match setting: {}", other)),
+        }
+        index += 2;
+    }
+    if config.limits.time.is_none() && config.limits.depth.is_none() && config.limits.nodes.is_none() {
+        config.limits.time = Some(DEFAULT_MOVE_TIME);
+    }
+    Ok(config)
+}
+
+// One line per opening, either a FEN or an EPD line, or every game in a PGN file played out to its end.
+pub fn load_openings(path: &str) -> Result<Vec<String>, String> {
+    if path.ends_with(".pgn") {
+        return read_pgn_file(path)?.iter().map(|game| Ok(game.final_state()?.to_fen())).collect();
+    }
+    let contents = fs::read_to_string(path).map_err(|err| format!("Unable to read openings file {}: {}", path, err))?;
+    contents.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with('#'))
+        .map(|(line_number, line)| {
+            let state = match parse_fen_string(line.to_string()) {
+                Ok(state) => state,
+                Err(_) => parse_epd_line(line).map_err(|err| format!("Invalid opening on line {}: {}", line_number + 1, err))?.state,
+            };
+            Ok(state.to_fen())
+        })
+        .collect()
+}
+
+struct Engine {
+    process: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+}
+
+// Runs a copy of this binary in UCI mode.
+impl Engine {
+    fn start(config: &EngineConfig, hash_size: usize) -> Result<Engine, String> {
+        let path = env::current_exe().map_err(|err| format!("Unable to find the engine binary: {}", err))?;
+        let mut process = Command::new(path).args(["--hash", &hash_size.to_string()]).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()
+            .map_err(|err| format!("Unable to start engine {}: {}", config.name, err))?;
+        let input = process.stdin.take().ok_or("Engine has no input")?;
+        let output = BufReader::new(process.stdout.take().ok_or("Engine has no output")?);
+        let mut engine = Engine { process, input, output };
+        engine.send("uci")?;
+        engine.read_until("uciok")?;
+        for (name, value) in &config.options {
+            engine.send(&format!("setoption name {} value {}", name, value))?;
+        }
+        engine.send("isready")?;
+        // Failed options are only reported as info strings
+        let errors: Vec<String> = engine.read_until("readyok")?.into_iter().filter(|line| line.starts_with("info string")).collect();
+        if let Some(error) = errors.first() {
+            return Err(format!("Engine {} rejected its options: {}", config.name, error.trim_start_matches("info string ")));
+        }
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.input, "{}", command).and_then(|_| self.input.flush()).map_err(|err| format!("Unable to write to engine: {}", err))
+    }
+
+    // Returns the lines before the one starting with the token.
+    fn read_until(&mut self, token: &str) -> Result<Vec<String>, String> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            match self.output.read_line(&mut line) {
+                Ok(0) => return Err("Engine exited unexpectedly".to_string()),
+                Ok(_) => (),
+                Err(err) => return Err(format!("Unable to read from engine: {}", err)),
+            }
+            if line.starts_with(token) {
+                return Ok(lines);
+            }
+            lines.push(line.trim().to_string());
+        }
+    }
+
+    fn new_game(&mut self) -> Result<(), String> {
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        self.read_until("readyok").map(|_| ())
+    }
+
+    fn best_move(&mut self, start_fen: &str, moves: &[Move], limits: &SearchLimits) -> Result<String, String> {
+        let move_strings: Vec<String> = moves.iter().map(|m| uci_string_move(*m)).collect();
+        let position = if move_strings.is_empty() { String::new() } else { format!(" moves {}", move_strings.join(" ")) };
+        self.send(&format!("position fen {}{}", start_fen, position))?;
+        let mut go = "go".to_string();
+        if let Some(time) = limits.time {
+            go += &format!(" movetime {}", time.as_millis());
+        }
+        if let Some(depth) = limits.depth {
+            go += &format!(" depth {}", depth);
+        }
+        if let Some(nodes) = limits.nodes {
+            go += &format!(" nodes {}", nodes);
+        }
+        self.send(&go)?;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.output.read_line(&mut line) {
+                Ok(0) => return Err("Engine exited unexpectedly".to_string()),
+                Ok(_) => (),
+                Err(err) => return Err(format!("Unable to read from engine: {}", err)),
+            }
+            if let Some(best_move) = line.strip_prefix("bestmove ") {
+                return Ok(best_move.split_whitespace().next().unwrap_or_default().to_string());
+            }
+        }
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.process.wait();
+    }
+}
+
+// Kings only, or a single minor piece, or bishops that all stand on the same colour.
+pub fn is_insufficient_material(state: &State) -> bool {
+    let heavy_or_pawns = COLORS.iter().fold(EMPTY_BITBOARD, |board, color| {
+        board | state.get_piece_board(*color, PieceType::Queen) | state.get_piece_board(*color, PieceType::Rook) | state.get_piece_board(*color, PieceType::Pawn)
+    });
+    if heavy_or_pawns != EMPTY_BITBOARD {
+        return false;
+    }
+    let knights = state.get_piece_board(Color::White, PieceType::Knight) | state.get_piece_board(Color::Black, PieceType::Knight);
+    let bishops = state.get_piece_board(Color::White, PieceType::Bishop) | state.get_piece_board(Color::Black, PieceType::Bishop);
+    let minors = bit_count(knights | bishops);
+    minors <= 1 || (knights == EMPTY_BITBOARD && (bishops & DARK_SQUARES == EMPTY_BITBOARD || bishops & LIGHT_SQUARES == EMPTY_BITBOARD))
+}
+
+// The result if the game is over, hashes holds the hash of every earlier position of the game.
+pub fn adjudicate(state: &State, hashes: &[u64]) -> Option<(GameResult, &'static str)> {
+    if state.legal_moves().is_empty() {
+        return Some(if !state.check {
+            (GameResult::Draw, "Stalemate")
+        } else if state.turn == Color::White {
+            (GameResult::BlackWin, "Black mates")
+        } else {
+            (GameResult::WhiteWin, "White mates")
+        });
+    }
+    if state.half_move_clock >= 100 {
+        return Some((GameResult::Draw, "Fifty move rule"));
+    }
+    // Only positions since the last capture or pawn move can repeat
+    let reversible = hashes.len().min(state.half_move_clock as usize);
+    if hashes[hashes.len() - reversible..].iter().filter(|hash| **hash == state.hashcode).count() >= 2 {
+        return Some((GameResult::Draw, "Threefold repetition"));
+    }
+    if is_insufficient_material(state) {
+        return Some((GameResult::Draw, "Insufficient material"));
+    }
+    None
+}
+
+fn play_game(engines: &mut [Engine; 2], names: [&str; 2], start_fen: &str, limits: &SearchLimits) -> Result<PgnGame, String> {
+    let mut state = parse_fen_string(start_fen.to_string()).map_err(|err| err.to_string())?;
+    let mut game = PgnGame::new(Some(start_fen));
+    game.set_tag("White", names[0]);
+    game.set_tag("Black", names[1]);
+    for engine in engines.iter_mut() {
+        engine.new_game()?;
+    }
+    let mut moves = Vec::new();
+    let mut hashes = Vec::new();
+    loop {
+        let mut ending = adjudicate(&state, &hashes).map(|(result, reason)| (result, reason.to_string()));
+        if ending.is_none() && moves.len() >= MAX_GAME_PLIES {
+            ending = Some((GameResult::Draw, "Move limit".to_string()));
+        }
+        let mover = state.turn;
+        let mut next_move = None;
+        if ending.is_none() {
+            let move_string = engines[mover as usize].best_move(start_fen, &moves, limits)?;
+            next_move = uci_move_from_string(&state, &move_string);
+            if next_move.is_none() {
+                let result = if mover == Color::White { GameResult::BlackWin } else { GameResult::WhiteWin };
+                ending = Some((result, format!("{} played the illegal move {}", names[mover as usize], move_string)));
+            }
+        }
+        // The reason the game ended goes after the last move
+        if let Some((result, reason)) = ending {
+            game.set_result(result);
+            match game.moves.last_mut() {
+                Some(last) => last.comment = Some(reason),
+                None => game.comment = Some(reason),
+            }
+            return Ok(game);
+        }
+        let m = next_move.unwrap();
+        hashes.push(state.hashcode);
+        game.moves.push(PgnMove::new(m));
+        moves.push(m);
+        state.non_reversible_move(m);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MatchScore {
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+impl MatchScore {
+    pub fn games(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    // Variance of a single game's score.
+    fn variance(&self) -> f64 {
+        let score = self.score();
+        let games = self.games() as f64;
+        (self.wins as f64 * (1.0 - score).powi(2) + self.draws as f64 * (0.5 - score).powi(2) + self.losses as f64 * score.powi(2)) / games
+    }
+
+    // Elo difference and the half width of its 95% confidence interval.
+    pub fn elo(&self) -> (f64, f64) {
+        let standard_error = (self.variance() / self.games() as f64).sqrt();
+        let low = score_to_elo(self.score() - 1.96 * standard_error);
+        let high = score_to_elo(self.score() + 1.96 * standard_error);
+        (score_to_elo(self.score()), (high - low) / 2.0)
+    }
+
+    // Log likelihood ratio of elo1 against elo0, using the normal approximation to the trinomial result distribution.
+    pub fn llr(&self, sprt: &SprtConfig) -> f64 {
+        let variance = self.variance();
+        if self.games() == 0 || variance == 0.0 {
+            return 0.0;
+        }
+        let (score0, score1) = (elo_to_score(sprt.elo0), elo_to_score(sprt.elo1));
+        self.games() as f64 * (score1 - score0) * (2.0 * self.score() - score0 - score1) / (2.0 * variance)
+    }
+}
+
+impl SprtConfig {
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+}
+
+pub fn score_to_elo(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1.0 - 1e-6);
+    // Adding zero turns the -0.0 of an even score into 0.0
+    -400.0 * (1.0 / score - 1.0).log10() + 0.0
+}
+
+pub fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+// Each opening is played twice with the engines swapping colours. Scores are from the first engine's point of view.
+pub fn run_match(config: MatchConfig) -> Result<(), String> {
+    let openings = match &config.openings_path {
+        Some(path) => load_openings(path)?,
+        None => vec![STARTING_FEN.to_string()],
+    };
+    if openings.is_empty() {
+        return Err("No openings to play".to_string());
+    }
+    let mut engines = [Engine::start(&config.engines[0], config.hash_size)?, Engine::start(&config.engines[1], config.hash_size)?];
+    let names = [config.engines[0].name.as_str(), config.engines[1].name.as_str()];
+    let mut score = MatchScore::default();
+    for game_index in 0..config.games {
+        let opening = &openings[(game_index / 2) % openings.len()];
+        let first_is_white = game_index % 2 == 0;
+        let mut game = if first_is_white {
+            play_game(&mut engines, names, opening, &config.limits)?
+        } else {
+            let [first, second] = engines;
+            let mut swapped = [second, first];
+            let game = play_game(&mut swapped, [names[1], names[0]], opening, &config.limits);
+            let [second, first] = swapped;
+            engines = [first, second];
+            game?
+        };
+        game.set_tag("Event", "Rhobos match");
+        game.set_tag("Round", &(game_index + 1).to_string());
+        match (game.result, first_is_white) {
+            (GameResult::WhiteWin, true) | (GameResult::BlackWin, false) => score.wins += 1,
+            (GameResult::WhiteWin, false) | (GameResult::BlackWin, true) => score.losses += 1,
+            _ => score.draws += 1,
+        }
+        println!("Game {}: {} vs {} {} ({})", game_index + 1, game.tag("White").unwrap_or("?"), game.tag("Black").unwrap_or("?"), game.result, game.moves.last().and_then(|last| last.comment.as_deref()).or(game.comment.as_deref()).unwrap_or(""));
+        println!("Score of {} vs {}: {} - {} - {} [{:.3}] {}", names[0], names[1], score.wins, score.losses, score.draws, score.score(), score.games());
+        if let Some(path) = &config.pgn_path {
+            append_pgn_file(path, &game)?;
+        }
+        if let Some(sprt) = &config.sprt {
+            let (lower, upper) = sprt.bounds();
+            let llr = score.llr(sprt);
+            if llr <= lower || llr >= upper {
+                break;
+            }
+        }
+    }
+
+    let (elo, error) = score.elo();
+    println!("Elo difference: {:.1} +/- {:.1}", elo, error);
+    if let Some(sprt) = &config.sprt {
+        let (lower, upper) = sprt.bounds();
+        let llr = score.llr(sprt);
+        let verdict = if llr >= upper { "H1 accepted" } else if llr <= lower { "H0 accepted" } else { "inconclusive" };
+        println!("SPRT elo0 {} elo1 {}: LLR {:.2} ({:.2}, {:.2}) {}", sprt.elo0, sprt.elo1, llr, lower, upper, verdict);
+    }
+    Ok(())
+}
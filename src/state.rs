@@ -1,6 +1,6 @@
 use core::fmt;
 use std::{fmt::Display, hint::unreachable_unchecked, marker::ConstParamTy, mem::transmute};
-use crate::{bitboard::{board_from_square, file, get_lsb, is_valid_square, pop_lsb, rank, Bitboard, Board, Color, Square, EMPTY_BITBOARD, NULL_SQUARE}, hash::{BLACK_HASH, CASTLE_HASHES, EN_PASSANT_HASHES, SQUARE_HASHES}, histories::{CaptureEntry, CastleHistoryEntry, EnPassantEntry, FiftyMoveHistory, History, HistoryEntry}, r#move::{move_destination_square, move_origin_square, move_special_info, move_special_type, Move, CASTLE_SPECIAL_MOVE, EN_PASSANT_SPECIAL_MOVE, NOT_SPECIAL_MOVE, NULL_MOVE, PROMOTION_SPECIAL_MOVE}, move_list::MoveStack, piece_info::{make_step, move_bitboard, PieceType, Step, PAWN_ATTACK_BOARDS}};
+use crate::{bitboard::{board_from_square, file, get_lsb, is_valid_square, pop_lsb, rank, Bitboard, Board, Color, Square, COLORS, EMPTY_BITBOARD, NULL_SQUARE}, evaluation::IncrementalEval, hash::{BLACK_HASH, CASTLE_HASHES, EN_PASSANT_HASHES, SQUARE_HASHES}, histories::{CaptureEntry, CastleHistoryEntry, EnPassantEntry, FiftyMoveHistory, History, HistoryEntry}, r#move::{move_destination_square, move_origin_square, move_special_info, move_special_type, Move, CASTLE_SPECIAL_MOVE, KING_CASTLE, EN_PASSANT_SPECIAL_MOVE, NOT_SPECIAL_MOVE, NULL_MOVE, PASSING_MOVE, PROMOTION_SPECIAL_MOVE}, move_list::MoveStack, nnue::Accumulator, piece_info::{make_step, move_bitboard, PieceType, Step, PAWN_ATTACK_BOARDS}};
 
 #[repr(u8)]
 #[derive(Clone, Copy, ConstParamTy, PartialEq, Eq, Debug)]
@@ -11,6 +11,18 @@ pub enum CastleAvailability {
     Both  = 0b11,
 }
 
+// Where the castling rooks start and which squares castling needs, fixed for the whole game. Standard chess has the
+// rooks in the corners, Chess960 can have the king and rooks on any files. Indexed by [color][castle side] with the
+// king side first.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CastleSquares {
+    pub rook_squares: [[Square; 2]; 2],
+    // Squares other than the king and rook that have to be empty
+    pub empty_masks:  [[Bitboard; 2]; 2],
+    // Squares the king moves through and onto, none of them can be attacked
+    pub king_paths:   [[Bitboard; 2]; 2],
+}
+
 pub struct State {
     pub board:               Board,
     pub side_occupied:       [Bitboard; 2],
@@ -21,15 +33,22 @@ pub struct State {
     pub en_passant_square:   Square,
     pub check:               bool,
     pub hashcode:            u64,
+    pub pawn_hashcode:       u64,
+    pub incremental_eval:    IncrementalEval,
     pub half_move_clock:     u8,
     pub move_stack:          MoveStack,
     pub castle_availability: [CastleAvailability; 2],
+    pub castle_squares:      CastleSquares,
     pub capture_history:     History<CaptureEntry>,
     pub en_passant_history:  History<EnPassantEntry>,
     pub castle_history:      History<CastleHistoryEntry>,
     pub fifty_move_history:  History<FiftyMoveHistory>,
     pub hash_history:        History<u64>,
+    pub pawn_hash_history:   History<u64>,
     pub check_history:       History<bool>,
+    pub incremental_eval_history: History<IncrementalEval>,
+    pub accumulator:         Accumulator,
+    pub accumulator_enabled: bool,
 }
 
 impl State {
@@ -46,14 +65,28 @@ impl State {
         self.castle_history.push(self.castle_availability[C as usize]);
         self.fifty_move_history.push(self.half_move_clock);
         self.hash_history.push(self.hashcode);
+        self.pawn_hash_history.push(self.pawn_hashcode);
         self.check_history.push(self.check);
+        self.incremental_eval_history.push(self.incremental_eval);
         let mut capture_entry = CaptureEntry::empty();
+        // Reset below for captures and pawn moves
+        self.half_move_clock = self.half_move_clock.saturating_add(1);
 
         self.clear_en_passant::<true>();
 
         let src_piece_type = self.force_get_colored_piece_at_square::<C>(src_square);
+        self.current_move_list().current_piece = src_piece_type.colored_value(C);
         self.clear_square::<true>(src_square, C, src_piece_type);
-        self.set_square::<true>(des_square, C, src_piece_type);
+        if move_special_type(m) == CASTLE_SPECIAL_MOVE {
+            // Castles are encoded as the king taking its own rook, both are lifted before either lands since in
+            // Chess960 the king and rook can end up on each other's squares.
+            let (king_des_square, rook_des_square) = castle_destinations::<C>(move_special_info(m));
+            self.clear_square::<true>(des_square, C, PieceType::Rook);
+            self.set_square::<true>(king_des_square, C, PieceType::King);
+            self.set_square::<true>(rook_des_square, C, PieceType::Rook);
+        } else {
+            self.set_square::<true>(des_square, C, src_piece_type);
+        }
 
         let des_piece_type_option = match C {
             Color::White => self.get_colored_piece_at_square::<{Color::Black}>(des_square),
@@ -69,20 +102,11 @@ impl State {
         match move_special_type(m) {
             NOT_SPECIAL_MOVE => {
                 match src_piece_type {
-                    PieceType::King => {
-                        if src_square == State::king_square::<C>() {
-                            match self.castle_availability[C as usize] {
-                                CastleAvailability::Both => self.toggle_castle_availability::<C, {CastleAvailability::Both}, true>(),
-                                CastleAvailability::King => self.toggle_castle_availability::<C, {CastleAvailability::King}, true>(),
-                                CastleAvailability::Queen => self.toggle_castle_availability::<C, {CastleAvailability::Queen}, true>(),
-                                CastleAvailability::None => (),
-                            }
-                        }
-                    },
+                    PieceType::King => self.clear_all_castle_availability::<C>(),
                     PieceType::Rook => {
-                        if src_square == CastleAvailability::rook_square::<C, {CastleAvailability::King}>() {
+                        if src_square == self.castle_squares.rook_squares[C as usize][CastleAvailability::King.side_index()] {
                             self.clear_castle_availability::<C, {CastleAvailability::King}, true>();
-                        } else if src_square == CastleAvailability::rook_square::<C, {CastleAvailability::Queen}>() {
+                        } else if src_square == self.castle_squares.rook_squares[C as usize][CastleAvailability::Queen.side_index()] {
                             self.clear_castle_availability::<C, {CastleAvailability::Queen}, true>();
                         }
                     }
@@ -99,32 +123,21 @@ impl State {
             }
             CASTLE_SPECIAL_MOVE => {
                 debug_assert!(move_special_info(m) == 1 || move_special_info(m) == 2);
-                let castle_type: CastleAvailability = unsafe { transmute(move_special_info(m)) };
-                let (old_rook_square, new_rook_square) = match castle_type {
-                    CastleAvailability::King => { 
-                        self.toggle_castle_availability::<C, {CastleAvailability::King}, true>();
-                        (7 + C.castle_shift(), 5 + C.castle_shift())
-                    },
-                    CastleAvailability::Queen => {
-                        self.toggle_castle_availability::<C, {CastleAvailability::Queen}, true>();
-                        (C.castle_shift(), 3 + C.castle_shift())
-                    },
-                    _ => { debug_assert!(false); unsafe { unreachable_unchecked() }; },
-                };
-                self.clear_square::<true>(old_rook_square, C, PieceType::Rook);
-                self.set_square::<true>(new_rook_square, C, PieceType::Rook);
+                self.clear_all_castle_availability::<C>();
             },
             PROMOTION_SPECIAL_MOVE => {
                 let promotion_type = move_special_info(m);
                 debug_assert!(promotion_type < 4);
                 self.clear_square::<true>(des_square, C, PieceType::Pawn);
                 unsafe { self.set_square_raw::<true>(des_square, C as u8, promotion_type + 1) };
+                self.half_move_clock = 0;
             },
             EN_PASSANT_SPECIAL_MOVE => {
                 let down_step = C.down() as Step;
                 let en_passant_square = make_step(des_square, down_step);
                 capture_entry = CaptureEntry { piece: Some(PieceType::Pawn), bitboard: self.get_piece_board(C.other(), PieceType::Pawn) };
                 self.clear_square::<true>(en_passant_square, C.other(), PieceType::Pawn);
+                self.half_move_clock = 0;
             }
             _ => { debug_assert!(false); unsafe { unreachable_unchecked() }; },
         }
@@ -138,6 +151,8 @@ impl State {
         self.ply += 1;
         self.hashcode ^= unsafe { BLACK_HASH };
         debug_assert_eq!(self.hashcode, self.get_hash());
+        debug_assert_eq!(self.pawn_hashcode, self.get_pawn_hash());
+        debug_assert_eq!(self.incremental_eval, self.computed_incremental_eval());
 
         self.move_stack.next();
         match C {
@@ -181,36 +196,37 @@ impl State {
         self.castle_availability[C as usize] = self.castle_history.pop().value();
         self.half_move_clock = self.fifty_move_history.pop().value();
         self.hashcode = self.hash_history.pop().value();
+        self.pawn_hashcode = self.pawn_hash_history.pop().value();
         self.check = self.check_history.pop().value();
+        self.incremental_eval = self.incremental_eval_history.pop().value();
         
-        let src_piece_type = self.force_get_colored_piece_at_square::<C>(des_square);
-        self.clear_square::<false>(des_square, C, src_piece_type);
-        self.set_square::<false>(src_square, C, src_piece_type);
+        if move_special_type(m) == CASTLE_SPECIAL_MOVE {
+            let (king_des_square, rook_des_square) = castle_destinations::<C>(move_special_info(m));
+            self.clear_square::<false>(king_des_square, C, PieceType::King);
+            self.clear_square::<false>(rook_des_square, C, PieceType::Rook);
+            self.set_square::<false>(src_square, C, PieceType::King);
+            self.set_square::<false>(des_square, C, PieceType::Rook);
+        } else {
+            let src_piece_type = self.force_get_colored_piece_at_square::<C>(des_square);
+            self.clear_square::<false>(des_square, C, src_piece_type);
+            self.set_square::<false>(src_square, C, src_piece_type);
+        }
         
         let capture_entry = self.capture_history.pop().value();
         if let Some(piece_type) = capture_entry.0 {
             self.set_piece_board(capture_entry.1, C.other(), piece_type);
+            if self.accumulator_enabled {
+                // Restoring the bitboard directly skips set_square so the accumulator has to be updated here.
+                let capture_square = if move_special_type(m) == EN_PASSANT_SPECIAL_MOVE { make_step(des_square, C.down() as Step) } else { des_square };
+                self.accumulator.add_feature(C.other(), piece_type as u8, capture_square);
+            }
         }
 
-        match move_special_type(m) {
-            CASTLE_SPECIAL_MOVE => {
-                debug_assert!(move_special_info(m) == 1 || move_special_info(m) == 2);
-                let castle_type: CastleAvailability = unsafe { transmute(move_special_info(m)) };
-                let (old_rook_square, new_rook_square) = match castle_type {
-                    CastleAvailability::King => (7 + C.castle_shift(), 5 + C.castle_shift()),
-                    CastleAvailability::Queen => (C.castle_shift(), 3 + C.castle_shift()),
-                    _ => unreachable!(),
-                };
-                self.clear_square::<false>(new_rook_square, C, PieceType::Rook);
-                self.set_square::<false>(old_rook_square, C, PieceType::Rook);
-            },
-            PROMOTION_SPECIAL_MOVE => {
-                let promotion_type = move_special_info(m);
-                debug_assert!(promotion_type < 4);
-                self.set_square::<false>(src_square, C, PieceType::Pawn);
-                unsafe { self.clear_square_raw::<false>(src_square, C as u8, promotion_type + 1) };
-            },
-            _ => (),
+        if move_special_type(m) == PROMOTION_SPECIAL_MOVE {
+            let promotion_type = move_special_info(m);
+            debug_assert!(promotion_type < 4);
+            self.set_square::<false>(src_square, C, PieceType::Pawn);
+            unsafe { self.clear_square_raw::<false>(src_square, C as u8, promotion_type + 1) };
         }
 
         self.turn = C;
@@ -218,6 +234,8 @@ impl State {
         self.update_occupied();
 
         debug_assert_eq!(self.hashcode, self.get_hash());
+        debug_assert_eq!(self.pawn_hashcode, self.get_pawn_hash());
+        debug_assert_eq!(self.incremental_eval, self.computed_incremental_eval());
         
         self.move_stack.previous();
     }
@@ -232,8 +250,10 @@ impl State {
         self.castle_history.pop();
         self.fifty_move_history.pop();
         self.hash_history.pop();
+        self.pawn_hash_history.pop();
         self.check_history.pop();
         self.capture_history.pop();
+        self.incremental_eval_history.pop();
         result
     }
     
@@ -252,6 +272,7 @@ impl State {
         self.clear_en_passant::<true>();
         self.hashcode ^= unsafe { BLACK_HASH };
         self.turn = C.other();
+        self.current_move_list().current = PASSING_MOVE;
         self.move_stack.next();
 
         debug_assert_eq!(self.hashcode, self.get_hash());
@@ -340,12 +361,21 @@ impl State {
         unsafe { *self.board.get_unchecked_mut((color * 6 + piece_type) as usize) = bitboard }
     }
 
+    // H updates the hashes and incremental eval along with the board. Unmaking a move restores both from their
+    // histories so it passes false.
     #[inline(always)]
     pub fn set_square<const H: bool>(&mut self, square: Square, color: Color, piece_type: PieceType) {
         debug_assert!(is_valid_square(square));
         self.board[piece_type.colored_value(color) as usize] |= board_from_square(square);
         if H {
-            self.hashcode ^= unsafe { SQUARE_HASHES[piece_type.colored_value(color) as usize][square as usize] }
+            self.hashcode ^= unsafe { SQUARE_HASHES[piece_type.colored_value(color) as usize][square as usize] };
+            if piece_type == PieceType::Pawn {
+                self.pawn_hashcode ^= unsafe { SQUARE_HASHES[piece_type.colored_value(color) as usize][square as usize] };
+            }
+            self.incremental_eval.add_piece(color, piece_type as u8, square);
+        }
+        if self.accumulator_enabled {
+            self.accumulator.add_feature(color, piece_type as u8, square);
         }
     }
 
@@ -356,7 +386,14 @@ impl State {
         debug_assert!(is_valid_square(square));
         unsafe { *self.board.get_unchecked_mut((color * 6 + piece_type) as usize) |= board_from_square(square) };
         if H {
-            self.hashcode ^= unsafe { SQUARE_HASHES[(color * 6 + piece_type) as usize][square as usize] }
+            self.hashcode ^= unsafe { SQUARE_HASHES[(color * 6 + piece_type) as usize][square as usize] };
+            if piece_type == PieceType::Pawn as u8 {
+                self.pawn_hashcode ^= unsafe { SQUARE_HASHES[(color * 6 + piece_type) as usize][square as usize] };
+            }
+            self.incremental_eval.add_piece(unsafe { transmute::<u8, Color>(color) }, piece_type, square);
+        }
+        if self.accumulator_enabled {
+            self.accumulator.add_feature(unsafe { transmute::<u8, Color>(color) }, piece_type, square);
         }
     }
 
@@ -365,7 +402,14 @@ impl State {
         debug_assert!(is_valid_square(square));
         self.board[piece_type.colored_value(color) as usize] &= !board_from_square(square);
         if H {
-            self.hashcode ^= unsafe { SQUARE_HASHES[piece_type.colored_value(color) as usize][square as usize] }
+            self.hashcode ^= unsafe { SQUARE_HASHES[piece_type.colored_value(color) as usize][square as usize] };
+            if piece_type == PieceType::Pawn {
+                self.pawn_hashcode ^= unsafe { SQUARE_HASHES[piece_type.colored_value(color) as usize][square as usize] };
+            }
+            self.incremental_eval.remove_piece(color, piece_type as u8, square);
+        }
+        if self.accumulator_enabled {
+            self.accumulator.remove_feature(color, piece_type as u8, square);
         }
     }
 
@@ -376,7 +420,14 @@ impl State {
         debug_assert!(is_valid_square(square));
         unsafe { *self.board.get_unchecked_mut((color * 6 + piece_type) as usize) &= !board_from_square(square) };
         if H {
-            self.hashcode ^= unsafe { SQUARE_HASHES[(color * 6 + piece_type) as usize][square as usize] }
+            self.hashcode ^= unsafe { SQUARE_HASHES[(color * 6 + piece_type) as usize][square as usize] };
+            if piece_type == PieceType::Pawn as u8 {
+                self.pawn_hashcode ^= unsafe { SQUARE_HASHES[(color * 6 + piece_type) as usize][square as usize] };
+            }
+            self.incremental_eval.remove_piece(unsafe { transmute::<u8, Color>(color) }, piece_type, square);
+        }
+        if self.accumulator_enabled {
+            self.accumulator.remove_feature(unsafe { transmute::<u8, Color>(color) }, piece_type, square);
         }
     }
 
@@ -410,6 +461,16 @@ impl State {
         }
     }
 
+    #[inline(always)]
+    pub fn clear_all_castle_availability<const C: Color>(&mut self) {
+        match self.castle_availability[C as usize] {
+            CastleAvailability::Both => self.toggle_castle_availability::<C, {CastleAvailability::Both}, true>(),
+            CastleAvailability::King => self.toggle_castle_availability::<C, {CastleAvailability::King}, true>(),
+            CastleAvailability::Queen => self.toggle_castle_availability::<C, {CastleAvailability::Queen}, true>(),
+            CastleAvailability::None => (),
+        }
+    }
+
     #[inline(always)]
     pub fn clear_castle_availability<const C: Color, const A: CastleAvailability, const H: bool>(&mut self) {
         debug_assert!(A != CastleAvailability::Both && A != CastleAvailability::None);
@@ -468,27 +529,70 @@ impl State {
         }
     }
 
+    // The rook is lifted since in Chess960 it can be shielding the king's destination from an attack along the rank.
+    // The king's own square is covered by not being in check.
     #[inline(always)]
-    pub const fn king_square<const C: Color>() -> Square {
-        match C {
-            Color::White => 4,
-            Color::Black => 60,
+    pub fn are_castle_through_squares_safe<const C: Color, const A: CastleAvailability>(&self) -> bool {
+        let rook_square = self.castle_squares.rook_squares[C as usize][A.side_index()];
+        let mut king_path = self.castle_squares.king_paths[C as usize][A.side_index()];
+        while king_path != EMPTY_BITBOARD {
+            if !self.is_square_safe::<C, true>(pop_lsb(&mut king_path), rook_square) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// Squares the king and rook land on, the same as in standard chess whatever files they start on.
+#[inline(always)]
+pub const fn castle_destinations<const C: Color>(castle_type: u8) -> (Square, Square) {
+    if castle_type == KING_CASTLE {
+        (6 + C.castle_shift(), 5 + C.castle_shift())
+    } else {
+        (2 + C.castle_shift(), 3 + C.castle_shift())
+    }
+}
+
+impl CastleSquares {
+    pub fn new(king_squares: [Square; 2], rook_squares: [[Square; 2]; 2]) -> CastleSquares {
+        let mut empty_masks = [[EMPTY_BITBOARD; 2]; 2];
+        let mut king_paths = [[EMPTY_BITBOARD; 2]; 2];
+        for color in COLORS {
+            let shift = color.castle_shift();
+            let color = color as usize;
+            for (side, (king_des_square, rook_des_square)) in [(6, 5), (2, 3)].into_iter().enumerate() {
+                let (king_square, rook_square) = (king_squares[color], rook_squares[color][side]);
+                if rook_square == NULL_SQUARE {
+                    continue;
+                }
+                let king_span = rank_span(king_square, king_des_square + shift);
+                let rook_span = rank_span(rook_square, rook_des_square + shift);
+                empty_masks[color][side] = (king_span | rook_span) & !board_from_square(king_square) & !board_from_square(rook_square);
+                king_paths[color][side] = king_span & !board_from_square(king_square);
+            }
         }
+        CastleSquares { rook_squares, empty_masks, king_paths }
+    }
+
+    pub fn standard() -> CastleSquares {
+        CastleSquares::new([4, 60], [[7, 0], [63, 56]])
     }
+}
+
+// Both squares and everything between them on their rank.
+fn rank_span(a: Square, b: Square) -> Bitboard {
+    let (low, high) = (a.min(b), a.max(b));
+    (low..=high).fold(EMPTY_BITBOARD, |board, square| board | board_from_square(square))
+}
 
+impl CastleAvailability {
+    // Index into the per side castle tables, only meaningful for King and Queen.
     #[inline(always)]
-    pub fn are_castle_through_squares_safe<const C: Color, const A: CastleAvailability>(&self) -> bool {
-        let color_shift = C.castle_shift();
-        match A {
-            CastleAvailability::King => {
-                self.is_square_safe::<C, false>(4 + color_shift, NULL_SQUARE) &&
-                self.is_square_safe::<C, false>(5 + color_shift, NULL_SQUARE)
-            },
-            CastleAvailability::Queen => {
-                self.is_square_safe::<C, false>(2 + color_shift, NULL_SQUARE) &&
-                self.is_square_safe::<C, false>(3 + color_shift, NULL_SQUARE)
-            },
-            _ => false,
+    pub const fn side_index(self) -> usize {
+        match self {
+            CastleAvailability::Queen => 1,
+            _ => 0,
         }
     }
 }
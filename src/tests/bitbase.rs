@@ -0,0 +1,26 @@
+#[allow(unused_imports)]
+use crate::{bitbase::kpk_probe, bitboard::Color, parsing::parse_fen_string, state::State, tests::init};
+
+#[allow(dead_code)]
+fn kpk_result(fen: &str) -> bool {
+    let state = parse_fen_string(fen.to_string()).unwrap();
+    state.eval_trace().endgame.is_some_and(|endgame| endgame.name == "KPK") && state.eval_state(Color::White) != 0
+}
+
+#[test]
+#[allow(dead_code)]
+fn kpk_probe_test() {
+    init();
+    // King on the 6th in front of the pawn wins whoever moves
+    assert!(kpk_result("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"));
+    assert!(kpk_result("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"));
+    // Black to move is stalemated, white to move wins with Kd6
+    assert!(!kpk_result("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"));
+    assert!(kpk_result("4k3/4P3/4K3/8/8/8/8/8 w - - 0 1"));
+    // Rook pawn with the defending king in the corner
+    assert!(!kpk_result("k7/8/8/8/8/1K6/P7/8 w - - 0 1"));
+    // Black pawns and files e to h are mirrored onto the table
+    assert!(kpk_probe(Color::Black, 20, 4, 28, false));
+    assert!(kpk_result("8/8/8/8/3p4/3k4/8/3K4 w - - 0 1"));
+    assert!(!kpk_result("7k/8/8/8/8/6K1/7P/8 b - - 0 1"));
+}
@@ -0,0 +1,59 @@
+#[allow(unused_imports)]
+use crate::{bitboard::Color, endgame::SCALE_FACTOR_NORMAL, parsing::parse_fen_string, state::State, tests::init};
+
+#[allow(dead_code)]
+fn state_from(fen: &str) -> State {
+    parse_fen_string(fen.to_string()).unwrap()
+}
+
+#[test]
+#[allow(dead_code)]
+fn mate_drive_test() {
+    init();
+    let center = state_from("8/8/8/3k4/8/8/8/R3K3 w - - 0 1");
+    let corner = state_from("7k/8/8/8/8/8/8/R3K3 w - - 0 1");
+    assert_eq!(corner.eval_trace().endgame.unwrap().name, "KRK");
+    assert!(corner.eval_state(Color::White) > center.eval_state(Color::White));
+    // Black being the strong side uses the same evaluator
+    let black = state_from("r3k3/8/8/8/8/8/8/7K b - - 0 1");
+    assert_eq!(black.eval_state(Color::Black), corner.eval_state(Color::White));
+}
+
+#[test]
+#[allow(dead_code)]
+fn kbnk_test() {
+    init();
+    // The bishop is on a dark square so only a1 and h8 are mating corners
+    let right_corner = state_from("8/8/8/8/8/3K4/8/k1B2N2 w - - 0 1");
+    let wrong_corner = state_from("8/8/8/8/8/3K4/8/2B2N1k w - - 0 1");
+    assert_eq!(right_corner.eval_trace().endgame.unwrap().name, "KBNK");
+    assert!(right_corner.eval_state(Color::White) > wrong_corner.eval_state(Color::White));
+}
+
+#[test]
+#[allow(dead_code)]
+fn drawn_material_test() {
+    init();
+    for fen in ["8/8/8/3k4/8/8/8/4K3 w - - 0 1", "8/8/8/3k4/8/8/8/2B1K3 w - - 0 1", "8/8/8/3k4/8/8/8/1N2KN2 w - - 0 1"] {
+        assert_eq!(state_from(fen).eval_state(Color::White), 0, "{}", fen);
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn scale_factor_test() {
+    init();
+    // Dark squared bishop can't cover a8
+    let wrong_bishop = state_from("k7/8/8/8/8/8/P7/2B1K3 w - - 0 1").eval_trace();
+    assert_eq!(wrong_bishop.endgame.unwrap().name, "KBPsK");
+    assert_eq!(wrong_bishop.scale_factor, 0);
+    assert_eq!(wrong_bishop.total, 0);
+    let right_bishop = state_from("k7/8/8/8/8/8/P7/1B2K3 w - - 0 1").eval_trace();
+    assert_eq!(right_bishop.scale_factor, SCALE_FACTOR_NORMAL);
+
+    let opposite_bishops = state_from("4k3/8/8/3b4/8/8/PPP5/2B1K3 w - - 0 1").eval_trace();
+    assert!(opposite_bishops.scale_factor < SCALE_FACTOR_NORMAL / 2);
+    let rook_against_bishop = state_from("4k3/8/8/3b4/8/8/8/R3K3 w - - 0 1").eval_trace();
+    assert!(rook_against_bishop.scale_factor < SCALE_FACTOR_NORMAL / 2);
+    assert!(rook_against_bishop.total < rook_against_bishop.term_sum() + 8 && rook_against_bishop.total > rook_against_bishop.term_sum() - 8);
+}
@@ -0,0 +1,45 @@
+#[allow(unused_imports)]
+use crate::{epd::{parse_epd_command, parse_epd_line}, parsing::{parse_fen_string, uci_move_from_string}, search::SearchLimits, tests::init, worker::Worker};
+
+#[test]
+#[allow(dead_code)]
+fn epd_parse_test() {
+    init();
+    let position = parse_epd_line("2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\"; c0 \"mate; in 3\";").unwrap();
+    assert_eq!(position.id.as_deref(), Some("WAC.001"));
+    assert_eq!(position.comment.as_deref(), Some("mate; in 3"));
+    assert_eq!(position.best_moves, vec![uci_move_from_string(&position.state, "g3g6").unwrap()]);
+    assert_eq!(position.operations.len(), 3);
+
+    // Disambiguation, captures, castling and a missing final semicolon
+    let position = parse_epd_line("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - am Rad1 Rxa8+; bm O-O").unwrap();
+    assert_eq!(position.avoid_moves, vec![uci_move_from_string(&position.state, "a1d1").unwrap(), uci_move_from_string(&position.state, "a1a8").unwrap()]);
+    assert_eq!(position.best_moves, vec![uci_move_from_string(&position.state, "e1g1").unwrap()]);
+    assert!(position.is_solved_by(position.best_moves[0]));
+    assert!(!position.is_solved_by(position.avoid_moves[0]));
+
+    assert!(parse_epd_line("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - bm Nf3;").is_err());
+    assert!(parse_epd_line("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - id \"unterminated").is_err());
+    assert!(parse_epd_line("r3k2r/8/8/8 w").is_err());
+    assert!(parse_epd_command(&["suite.epd", "nodes"]).is_err());
+    assert_eq!(parse_epd_command(&["suite.epd", "depth", "4"]).unwrap().limits.depth, Some(4));
+}
+
+#[test]
+#[allow(dead_code)]
+fn search_limits_test() {
+    init();
+    let mut position = parse_epd_line("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - bm Ra8#;").unwrap();
+    let mut worker = Worker::new();
+    let best_move = worker.limited_search(&mut position.state, SearchLimits { depth: Some(3), ..SearchLimits::default() }, false);
+    assert!(position.is_solved_by(best_move));
+
+    // The search stops part way through an iteration once the node limit is reached
+    let mut state = parse_fen_string("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3".to_string()).unwrap();
+    let mut worker = Worker::new();
+    let best_move = worker.limited_search(&mut state, SearchLimits { nodes: Some(20_000), ..SearchLimits::default() }, false);
+    assert!(state.is_legal(best_move));
+    assert!(worker.nodes_searched < 22_000, "Searched {} nodes", worker.nodes_searched);
+    assert!(worker.stopped);
+    assert_eq!(state.to_fen(), "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+}
@@ -0,0 +1,46 @@
+#[allow(unused_imports)]
+use crate::{eval_params::EvalParams, evaluation::CENTI_PAWN, score::Score};
+
+#[test]
+#[allow(dead_code)]
+fn eval_params_round_trip_test() {
+    let params = EvalParams::default_params();
+    assert_eq!(EvalParams::parse(&params.to_string()).unwrap(), params);
+}
+
+#[test]
+#[allow(dead_code)]
+fn eval_params_partial_file_test() {
+    let params = EvalParams::parse("# Only override a few values\nmobility = [3, 5]\nmidgame_knight = [\n    1, 2, 3, 4, 5, 6, 7, 8,\n    0, 0, 0, 0, 0, 0, 0, 0,\n    0, 0, 0, 0, 0, 0, 0, 0,\n    0, 0, 0, 0, 0, 0, 0, 0,\n    0, 0, 0, 0, 0, 0, 0, 0,\n    0, 0, 0, 0, 0, 0, 0, 0,\n    0, 0, 0, 0, 0, 0, 0, 0,\n    0, 0, 0, 0, 0, 0, 0, 0,\n]\n").unwrap();
+    let defaults = EvalParams::default_params();
+    assert_eq!(params.mobility, Score::new(3 * CENTI_PAWN, 5 * CENTI_PAWN));
+    assert_eq!(params.midgame_tables[4][7], 8 * CENTI_PAWN);
+    assert_eq!(params.endgame_tables, defaults.endgame_tables);
+    assert_eq!(params.piece_values, defaults.piece_values);
+
+    assert!(EvalParams::parse("mobilty = [3, 5]").is_err());
+    assert!(EvalParams::parse("mobility = [3, 5, 7]").is_err());
+    assert!(EvalParams::parse("piece_values = [1, 2]").is_err());
+    assert!(EvalParams::parse("king_safety = [1, 2").is_err());
+}
+
+#[test]
+#[allow(dead_code)]
+fn eval_params_scalar_file_test() {
+    // Files from before the eval was tapered give one value per entry, used for both midgame and endgame.
+    let params = EvalParams::parse("mobility = 3\npawn_isolated = -25\npiece_values = [0, 950, 500, 330, 300, 100]\n").unwrap();
+    assert_eq!(params.mobility, Score::new(3 * CENTI_PAWN, 3 * CENTI_PAWN));
+    assert_eq!(params.pawn_isolated, Score::new(-25 * CENTI_PAWN, -25 * CENTI_PAWN));
+    assert_eq!(params.piece_values[1], Score::new(950 * CENTI_PAWN, 950 * CENTI_PAWN));
+    assert_eq!(params.pawn_doubled, EvalParams::default_params().pawn_doubled);
+}
+
+#[test]
+#[allow(dead_code)]
+fn eval_params_out_of_range_test() {
+    assert!(EvalParams::parse("mobility = 40000").is_err());
+    assert!(EvalParams::parse("mobility = [99999, 1]").is_err());
+    assert!(EvalParams::parse("mobility = [-2147483648, 1]").is_err());
+    let params = EvalParams::parse("mobility = [30000, -30000]").unwrap();
+    assert_eq!(params.mobility, Score::new(30000 * CENTI_PAWN, -30000 * CENTI_PAWN));
+}
@@ -0,0 +1,176 @@
+#[allow(unused_imports)]
+use crate::{bitboard::Color, eval_params::eval_params, evaluation::{MATE_VALUE_CUTOFF, PAWN_EVAL}, parsing::{parse_fen_string, uci_move_from_string}, piece_info::PieceType, state::State, tests::{init, perft::PERFT_TEST_CASES}};
+
+#[allow(dead_code)]
+const TRACE_TEST_FENS: [&str; 4] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+];
+
+#[test]
+#[allow(dead_code)]
+fn eval_trace_matches_eval_test() {
+    init();
+    for fen in TRACE_TEST_FENS {
+        let state = parse_fen_string(fen.to_string()).unwrap();
+        let trace = state.eval_trace();
+        assert!((trace.term_sum() - trace.total).abs() <= 8, "Trace terms don't add up for {}", fen);
+        assert_eq!(trace.total.clamp(-MATE_VALUE_CUTOFF, MATE_VALUE_CUTOFF), state.eval_state(Color::White), "Trace total differs from eval for {}", fen);
+        assert_eq!(-state.eval_state(Color::White), state.eval_state(Color::Black));
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn eval_trace_terms_test() {
+    init();
+    // White has four pawns against none, so all four are passed and only the a7 pawn is isolated
+    let state = parse_fen_string("4k3/P7/8/8/8/8/5PPP/4K3 w - - 0 1".to_string()).unwrap();
+    let trace = state.eval_trace();
+    assert_eq!(trace.material[Color::White as usize] - trace.material[Color::Black as usize], 4 * PAWN_EVAL);
+    assert_eq!(trace.isolated_pawns, [1, 0]);
+    assert_eq!(trace.passed_pawns, [4, 0]);
+    assert_eq!(trace.doubled_pawns, [0, 0]);
+    assert_eq!(trace.phase, 0);
+    assert!(trace.mobility_counts[Color::White as usize][0] > 0);
+}
+
+#[test]
+#[allow(dead_code)]
+fn pawn_entry_test() {
+    init();
+    let mut state = parse_fen_string("4k3/p4p2/8/2P5/8/8/P4PP1/4K3 w - - 0 1".to_string()).unwrap();
+    let entry = state.pawn_entry();
+    assert_eq!(entry, state.computed_pawn_entry());
+    assert_eq!(entry.passed_count, [1, 0]);
+    assert_eq!(entry.isolated_count, [2, 2]);
+
+    // King moves leave the pawn key alone, pawn moves change it and unmaking restores it.
+    let pawn_key = state.pawn_hashcode;
+    let king_move = uci_move_from_string(&state, "e1d1").unwrap();
+    state.make_move::<{Color::White}>(king_move);
+    assert_eq!(state.pawn_hashcode, pawn_key);
+    let pawn_move = uci_move_from_string(&state, "a7a5").unwrap();
+    state.make_move::<{Color::Black}>(pawn_move);
+    assert_ne!(state.pawn_hashcode, pawn_key);
+    assert_eq!(state.pawn_hashcode, state.get_pawn_hash());
+    assert_eq!(state.pawn_entry(), state.computed_pawn_entry());
+    state.unmake_move::<{Color::Black}>(pawn_move);
+    state.unmake_move::<{Color::White}>(king_move);
+    assert_eq!(state.pawn_hashcode, pawn_key);
+
+    // Cached entries, including ones filled by earlier probes of the same structure, match a fresh computation.
+    for case in PERFT_TEST_CASES.iter() {
+        let mut state = state_from(case.0);
+        for m in state.legal_moves().iter().copied() {
+            let turn = state.turn;
+            match turn {
+                Color::White => state.make_move::<{Color::White}>(m),
+                Color::Black => state.make_move::<{Color::Black}>(m),
+            };
+            assert_eq!(state.pawn_entry(), state.computed_pawn_entry(), "Cached pawn entry differs for {}", state.to_fen());
+            match turn {
+                Color::White => state.unmake_move::<{Color::White}>(m),
+                Color::Black => state.unmake_move::<{Color::Black}>(m),
+            }
+            assert_eq!(state.pawn_entry(), state.computed_pawn_entry(), "Cached pawn entry differs for {}", state.to_fen());
+        }
+    }
+}
+
+#[allow(dead_code)]
+fn state_from(fen: &str) -> State {
+    parse_fen_string(fen.to_string()).unwrap()
+}
+
+#[test]
+#[allow(dead_code)]
+fn bishop_pair_test() {
+    init();
+    let state = state_from("4kb2/8/8/8/8/8/8/2B1KB2 w - - 0 1");
+    assert_eq!(state.bishop_pair_count(Color::White), 1);
+    assert_eq!(state.bishop_pair_count(Color::Black), 0);
+}
+
+#[test]
+#[allow(dead_code)]
+fn rook_file_test() {
+    init();
+    // a1 semi open, h1 open, d7 open and on the 7th
+    let state = state_from("4k3/p2R4/8/8/8/8/8/R3K2R w - - 0 1");
+    assert_eq!(state.rook_file_counts(Color::White), (2, 1, 1));
+    assert_eq!(state.rook_file_counts(Color::Black), (0, 0, 0));
+}
+
+#[test]
+#[allow(dead_code)]
+fn knight_outpost_test() {
+    init();
+    let state = state_from("4k3/p7/8/4N3/3P4/8/8/4K3 w - - 0 1");
+    assert_eq!(state.knight_outpost_count(Color::White, &state.pawn_entry()), 1);
+    // The f pawn can still chase the knight away
+    let state = state_from("4k3/p4p2/8/4N3/3P4/8/8/4K3 w - - 0 1");
+    assert_eq!(state.knight_outpost_count(Color::White, &state.pawn_entry()), 0);
+}
+
+#[test]
+#[allow(dead_code)]
+fn backward_and_connected_pawn_test() {
+    init();
+    // d3 is backward, c4 and e4 are defended by it
+    let entry = state_from("4k3/8/8/4p3/2P1P3/3P4/8/4K3 w - - 0 1").pawn_entry();
+    assert_eq!(entry.backward_count, [1, 0]);
+    assert_eq!(entry.connected_count, [2, 0]);
+    assert_eq!(entry.isolated_count, [0, 1]);
+}
+
+#[test]
+#[allow(dead_code)]
+fn passed_pawn_test() {
+    init();
+    let state = state_from("kn6/8/4P3/4K3/8/8/8/8 w - - 0 1");
+    let entry = state.pawn_entry();
+    assert_eq!(entry.passed_count, [1, 0]);
+    // The pawn is on the 6th rank so distances to e7 count three times
+    assert_eq!(state.passed_king_distances(Color::White, entry.passed_pawns[Color::White as usize]), (6, 12));
+    assert_eq!(state.eval_trace().passed_ranks[Color::White as usize][5], 1);
+}
+
+#[test]
+#[allow(dead_code)]
+fn king_shelter_test() {
+    init();
+    let state = state_from("4k3/8/8/8/6p1/7P/5PP1/6K1 w - - 0 1");
+    let (shelter, storm) = state.king_shelter_counts(Color::White);
+    assert_eq!(shelter, [0, 2, 1, 0, 0, 0, 0, 0]);
+    assert_eq!(storm, [2, 0, 0, 1, 0, 0, 0, 0]);
+    // No black pawns around the black king, only the f2 pawn far away on its files
+    assert_eq!(state.king_shelter_counts(Color::Black), ([3, 0, 0, 0, 0, 0, 0, 0], [2, 0, 0, 0, 0, 0, 1, 0]));
+}
+
+#[test]
+#[allow(dead_code)]
+fn threat_test() {
+    init();
+    let state = state_from("4r2k/8/8/1Bb1n3/q2P4/8/8/R3K3 w - - 0 1");
+    let trace = state.eval_trace();
+    let white = Color::White as usize;
+    assert_eq!((trace.threats_by_pawn[white], trace.threats_by_minor[white], trace.threats_by_rook[white]), (2, 2, 1));
+}
+
+#[test]
+#[allow(dead_code)]
+fn endgame_phase_test() {
+    init();
+    // Only pawns left so every term uses its endgame value
+    let trace = state_from("8/p7/8/3k4/8/3K4/7P/8 w - - 0 1").eval_trace();
+    let params = eval_params();
+    let white = Color::White as usize;
+    assert_eq!(trace.phase, 0);
+    assert_eq!(trace.king_attack_units[white], 3);
+    assert_eq!(trace.king_safety[white], params.king_safety[3].endgame());
+    assert_eq!(trace.mobility[white], params.mobility.endgame() * trace.mobility_counts[white].iter().sum::<u32>() as i32);
+    assert_eq!(trace.material[white], params.piece_values[PieceType::Pawn as usize].endgame());
+}
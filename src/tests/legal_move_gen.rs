@@ -0,0 +1,84 @@
+#[allow(unused_imports)]
+use crate::{bitboard::Color, move_gen::MoveGenType, parsing::{parse_fen_string, uci_move_from_string}, r#move::{build_move, CASTLE_SPECIAL_MOVE, EN_PASSANT_SPECIAL_MOVE, KING_CASTLE, QUEEN_CASTLE}, state::State, tests::{init, perft::{CHESS960_PERFT_TEST_CASES, PERFT_TEST_CASES}}};
+
+#[test]
+#[allow(dead_code)]
+fn legal_moves_match_pseudo_legal_test() {
+    init();
+    for case in PERFT_TEST_CASES.iter().chain(CHESS960_PERFT_TEST_CASES.iter()) {
+        let mut state = parse_fen_string(case.0.to_string()).unwrap();
+        compare_moves(&mut state, 3);
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn legal_moves_pins_test() {
+    init();
+    // Taking en passant would open the rank to the rook
+    let state = parse_fen_string("8/8/8/KPp4r/8/8/8/7k w - c6 0 2".to_string()).unwrap();
+    assert!(!state.legal_moves().contains(&build_move(33, 42, 0, EN_PASSANT_SPECIAL_MOVE)));
+    // The pinned knight can't block, only e2 and f2 are left
+    let state = parse_fen_string("4k3/8/8/8/1b6/8/3N4/r3K3 w - - 0 1".to_string()).unwrap();
+    assert_eq!(state.legal_moves().len(), 2);
+    // Double check only leaves king moves
+    let state = parse_fen_string("4k3/8/8/8/7b/8/3N4/r3K3 w - - 0 1".to_string()).unwrap();
+    assert_eq!(state.legal_moves().len(), 1);
+}
+
+#[test]
+#[allow(dead_code)]
+fn pseudo_legal_test() {
+    init();
+    for case in PERFT_TEST_CASES.iter().chain(CHESS960_PERFT_TEST_CASES.iter()) {
+        let mut state = parse_fen_string(case.0.to_string()).unwrap();
+        let legal_moves = state.legal_moves();
+        // Every possible move value, anything accepted has to be safe to make and legal if make_move says so
+        for m in 0..=u16::MAX {
+            if !state.is_pseudo_legal(m) {
+                assert!(!legal_moves.contains(&m));
+                continue;
+            }
+            let is_legal = state.debug_quick_make_move(m);
+            state.debug_quick_unmake_move(m);
+            assert_eq!(is_legal, legal_moves.contains(&m));
+            assert_eq!(state.hashcode, state.get_hash());
+        }
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn castle_notation_test() {
+    init();
+    // X-FEN and Shredder-FEN give the same rooks, castles are stored as the king taking its rook
+    let x_fen = parse_fen_string("r3k2r/8/8/8/8/8/8/1R2K1R1 w KQkq - 0 1".to_string()).unwrap();
+    let shredder_fen = parse_fen_string("r3k2r/8/8/8/8/8/8/1R2K1R1 w GBha - 0 1".to_string()).unwrap();
+    assert_eq!(x_fen.castle_squares, shredder_fen.castle_squares);
+    assert_eq!(x_fen.castle_squares.rook_squares[Color::White as usize], [6, 1]);
+    let castle = uci_move_from_string(&x_fen, "e1g1").unwrap();
+    assert_eq!(castle, build_move(4, 6, KING_CASTLE, CASTLE_SPECIAL_MOVE));
+    assert_eq!(uci_move_from_string(&x_fen, "e1c1").unwrap(), build_move(4, 1, QUEEN_CASTLE, CASTLE_SPECIAL_MOVE));
+    assert!(parse_fen_string("4k3/8/8/8/8/8/8/4K3 w K - 0 1".to_string()).is_err());
+}
+
+#[allow(dead_code)]
+fn compare_moves(state: &mut State, depth: i64) {
+    let mut legal_moves = state.legal_moves().to_vec();
+    state.debug_quick_gen_moves();
+    let pseudo_legal_moves = state.debug_move_vec();
+    let mut expected = state.debug_validate_moves(&pseudo_legal_moves);
+    legal_moves.sort();
+    expected.sort();
+    assert_eq!(legal_moves, expected, "Legal moves differ for\n{}", state);
+    for m in pseudo_legal_moves {
+        assert_eq!(state.is_legal(m), expected.contains(&m));
+    }
+    if depth > 1 {
+        for m in legal_moves {
+            state.debug_quick_make_move(m);
+            compare_moves(state, depth - 1);
+            state.debug_quick_unmake_move(m);
+        }
+    }
+}
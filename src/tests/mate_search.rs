@@ -0,0 +1,29 @@
+#[allow(unused_imports)]
+use crate::{mate_search::MateSearchResult, parsing::parse_fen_string, r#move::uci_string_move, tests::init, worker::Worker};
+
+#[allow(dead_code)]
+const MATE_TEST_CASES: [(&str, i32, Option<&str>); 4] = [
+    ("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 1, Some("a1a8")),
+    ("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1", 2, Some("d5f6 g7f6 c4f7")),
+    ("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2", 2, None),
+    // Stalemate is not mate
+    ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 1, None),
+];
+
+#[test]
+#[allow(dead_code)]
+fn mate_search_test() {
+    init();
+    for case in MATE_TEST_CASES.iter() {
+        let mut state = parse_fen_string(case.0.to_string()).unwrap();
+        let mut worker = Worker::new();
+        match (worker.mate_search(&mut state, case.1), case.2) {
+            (MateSearchResult::Mate(line), Some(expected)) => {
+                let line_string: Vec<String> = line.iter().map(|m| uci_string_move(*m)).collect();
+                assert_eq!(line_string.join(" "), expected);
+            },
+            (MateSearchResult::NoMate, None) => (),
+            _ => panic!("Wrong mate search result for {}", case.0),
+        }
+    }
+}
@@ -1,11 +1,31 @@
 use std::sync::Once;
 
-use crate::piece_info::move_gen_init;
+use crate::{evaluation::eval_info_init, piece_info::move_gen_init, transposition::ttable_init};
 
+pub mod bitbase;
+pub mod endgame;
+pub mod epd;
+pub mod eval_params;
+pub mod evaluation;
+pub mod legal_move_gen;
+pub mod mate_search;
+pub mod move_pick;
+pub mod nnue;
+pub mod parsing;
 pub mod perft;
+pub mod pgn;
+pub mod san;
+pub mod self_play;
+pub mod transposition;
+pub mod tuner;
 
 static INIT: Once = Once::new();
 
 pub fn init() {
-    INIT.call_once(move_gen_init);
-}
\ No newline at end of file
+    INIT.call_once(|| {
+        move_gen_init();
+        eval_info_init();
+        // Also sets up the hashes
+        unsafe { ttable_init(16) };
+    });
+}
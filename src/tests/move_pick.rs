@@ -0,0 +1,26 @@
+#[allow(unused_imports)]
+use crate::{parsing::{parse_fen_string, uci_move_from_string}, tests::init};
+
+#[test]
+#[allow(dead_code)]
+fn capture_history_order_test() {
+    init();
+    // Without history the bishop is the bigger victim, a knight capture that keeps causing cutoffs moves ahead of it.
+    let state = parse_fen_string("7k/8/8/2n1b3/8/3N4/8/7K w - - 0 1".to_string()).unwrap();
+    let takes_knight = uci_move_from_string(&state, "d3c5").unwrap();
+    let takes_bishop = uci_move_from_string(&state, "d3e5").unwrap();
+    assert!(state.move_capture_score(takes_bishop).0 > state.move_capture_score(takes_knight).0);
+    for _ in 0..32 {
+        state.update_capture_histories(takes_knight, 10, &[takes_bishop]);
+    }
+    assert!(state.move_capture_score(takes_knight).0 > state.move_capture_score(takes_bishop).0);
+
+    // History is never enough to put a pawn capture ahead of a queen capture.
+    let state = parse_fen_string("7k/8/8/2q1p3/8/3N4/8/7K w - - 0 1".to_string()).unwrap();
+    let takes_queen = uci_move_from_string(&state, "d3c5").unwrap();
+    let takes_pawn = uci_move_from_string(&state, "d3e5").unwrap();
+    for _ in 0..32 {
+        state.update_capture_histories(takes_pawn, 10, &[takes_queen]);
+    }
+    assert!(state.move_capture_score(takes_queen).0 > state.move_capture_score(takes_pawn).0);
+}
@@ -0,0 +1,111 @@
+#[allow(unused_imports)]
+use std::sync::Once;
+
+#[allow(unused_imports)]
+use crate::{bitboard::Color, evaluation::MATE_VALUE_CUTOFF, nnue::{load_network_bytes, NNUE_HIDDEN, NNUE_HIDDEN_2, NNUE_INPUTS}, r#move::uci_string_move, parsing::parse_fen_string, state::State, tests::{init, perft::{CHESS960_PERFT_TEST_CASES, PERFT_TEST_CASES}}};
+
+static NETWORK_INIT: Once = Once::new();
+
+// Loads a small random network once. UseNNUE is left off so other tests keep the classical eval, the states here
+// enable their accumulators directly.
+#[allow(dead_code)]
+fn synthetic_network_init() {
+    init();
+    NETWORK_INIT.call_once(|| {
+        let mut seed: u64 = 0x9E3779B97F4A7C15;
+        let mut next = |range: i64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % (2 * range as u64)) as i64 - range
+        };
+        let mut bytes = Vec::new();
+        for _ in 0..NNUE_INPUTS * NNUE_HIDDEN {
+            bytes.extend((next(24) as i16).to_le_bytes());
+        }
+        for _ in 0..NNUE_HIDDEN {
+            bytes.extend((next(32) as i16 + 32).to_le_bytes());
+        }
+        for _ in 0..NNUE_HIDDEN_2 * 2 * NNUE_HIDDEN {
+            bytes.extend((next(64) as i8).to_le_bytes());
+        }
+        for _ in 0..NNUE_HIDDEN_2 {
+            bytes.extend((next(4096) as i32).to_le_bytes());
+        }
+        for _ in 0..NNUE_HIDDEN_2 {
+            bytes.extend((next(256) as i16).to_le_bytes());
+        }
+        bytes.extend((next(4096) as i32).to_le_bytes());
+        load_network_bytes(&bytes).unwrap();
+    });
+}
+
+// Every move up to the given depth is checked against a full refresh after both make_move and unmake_move.
+#[allow(dead_code)]
+fn check_accumulator(state: &mut State, depth: u8) {
+    if depth == 0 {
+        return;
+    }
+    for m in state.legal_moves().iter().copied() {
+        let before = state.accumulator.clone();
+        let turn = state.turn;
+        match turn {
+            Color::White => assert!(state.make_move::<{Color::White}>(m)),
+            Color::Black => assert!(state.make_move::<{Color::Black}>(m)),
+        };
+        assert!(state.accumulator == state.computed_accumulator(), "Accumulator differs after making {} in {}", uci_string_move(m), state.to_fen());
+        check_accumulator(state, depth - 1);
+        match turn {
+            Color::White => state.unmake_move::<{Color::White}>(m),
+            Color::Black => state.unmake_move::<{Color::Black}>(m),
+        }
+        assert!(state.accumulator == before, "Accumulator differs after unmaking {} in {}", uci_string_move(m), state.to_fen());
+    }
+}
+
+// Flips the board vertically and swaps the colors of every piece, the side to move, castling rights and en passant.
+#[allow(dead_code)]
+fn mirror_fen(fen: &str) -> String {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let swap_case = |field: &str| field.chars().map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() }).collect::<String>();
+    let board = fields[0].split('/').rev().map(swap_case).collect::<Vec<String>>().join("/");
+    let turn = if fields[1] == "w" { "b" } else { "w" };
+    let en_passant = match fields[3].as_bytes() {
+        [file, rank] => format!("{}{}", *file as char, (b'1' + b'8' - rank) as char),
+        _ => fields[3].to_string(),
+    };
+    format!("{} {} {} {} {}", board, turn, swap_case(fields[2]), en_passant, fields[4..].join(" "))
+}
+
+#[test]
+#[allow(dead_code)]
+fn nnue_incremental_accumulator_test() {
+    synthetic_network_init();
+    // The perft positions cover captures, promotions with and without capture, both castles and en passant.
+    for case in PERFT_TEST_CASES.iter().chain(CHESS960_PERFT_TEST_CASES.iter()) {
+        let mut state = parse_fen_string(case.0.to_string()).unwrap();
+        state.enable_accumulator();
+        check_accumulator(&mut state, 2);
+    }
+    let mut en_passant = parse_fen_string("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1".to_string()).unwrap();
+    en_passant.enable_accumulator();
+    check_accumulator(&mut en_passant, 2);
+    let mut promotion = parse_fen_string("1n2k3/P7/8/8/8/8/7p/4K1N1 w - - 0 1".to_string()).unwrap();
+    promotion.enable_accumulator();
+    check_accumulator(&mut promotion, 3);
+}
+
+#[test]
+#[allow(dead_code)]
+fn nnue_symmetry_test() {
+    synthetic_network_init();
+    for case in PERFT_TEST_CASES.iter() {
+        let mut state = parse_fen_string(case.0.to_string()).unwrap();
+        let mut mirrored = parse_fen_string(mirror_fen(case.0)).unwrap();
+        state.enable_accumulator();
+        mirrored.enable_accumulator();
+        let eval = state.nnue_eval(state.turn);
+        assert_eq!(eval, mirrored.nnue_eval(mirrored.turn), "Mirrored eval differs for {}", case.0);
+        assert!(eval.abs() < MATE_VALUE_CUTOFF);
+    }
+}
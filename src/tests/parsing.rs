@@ -0,0 +1,85 @@
+#[allow(unused_imports)]
+use crate::{bitboard::Color, parsing::{parse_fen_string, FenError, starting_fen, uci_move_from_string}, tests::{init, perft::{CHESS960_PERFT_TEST_CASES, PERFT_TEST_CASES}}};
+
+#[test]
+#[allow(dead_code)]
+fn fen_round_trip_test() {
+    init();
+    for case in PERFT_TEST_CASES.iter().chain(CHESS960_PERFT_TEST_CASES.iter()) {
+        let state = parse_fen_string(case.0.to_string()).unwrap();
+        let fen = state.to_fen();
+        // Shredder-FEN castling comes back as X-FEN so compare the reparsed state as well as the string
+        let reparsed = parse_fen_string(fen.clone()).unwrap();
+        assert_eq!(reparsed.to_fen(), fen);
+        assert_eq!(reparsed.hashcode, state.hashcode);
+        assert_eq!(reparsed.castle_squares, state.castle_squares);
+        assert_eq!(reparsed.ply, state.ply);
+    }
+    let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    assert_eq!(parse_fen_string(fen.to_string()).unwrap().to_fen(), fen);
+}
+
+#[test]
+#[allow(dead_code)]
+fn half_move_clock_test() {
+    init();
+    // Quiet moves count up, en passant and promotions without a capture are pawn moves and reset the clock.
+    for (fen, move_string, half_move_clock) in [
+        ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 7 40", "e1d1", 8),
+        ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 7 40", "e5d6", 0),
+        ("4k3/P7/8/8/8/8/8/4K3 w - - 7 40", "a7a8q", 0),
+        ("4k3/P7/8/8/8/8/8/4K3 w - - 7 40", "a7a8n", 0),
+    ] {
+        let mut state = parse_fen_string(fen.to_string()).unwrap();
+        let m = uci_move_from_string(&state, move_string).unwrap();
+        state.make_move::<{Color::White}>(m);
+        assert_eq!(state.half_move_clock, half_move_clock, "Wrong half move clock after {} in {}", move_string, fen);
+        state.unmake_move::<{Color::White}>(m);
+        assert_eq!(state.to_fen(), fen);
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn fen_move_numbers_test() {
+    init();
+    let mut state = starting_fen();
+    assert_eq!(state.ply, 0);
+    for (move_string, fen) in [
+        ("e2e4", "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"),
+        ("g8f6", "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2"),
+        ("e1e2", "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 2 2"),
+    ] {
+        let m = uci_move_from_string(&state, move_string).unwrap();
+        state.non_reversible_move(m);
+        assert_eq!(state.to_fen(), fen);
+        assert_eq!(parse_fen_string(fen.to_string()).unwrap().ply, state.ply);
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn fen_validation_test() {
+    init();
+    let error = |fen: &str| parse_fen_string(fen.to_string()).err();
+    assert_eq!(error(""), Some(FenError::MissingField("board")));
+    assert_eq!(error("4k3/8/8/8/8/8/8/4K3 w"), Some(FenError::MissingField("castle availability")));
+    assert_eq!(error("4k3/8/8/8/8/8/4K3 w - - 0 1"), Some(FenError::WrongRankCount(7)));
+    assert_eq!(error("4k3/8/8/8/8/8/8/4K4 w - - 0 1"), Some(FenError::WrongRankLength(1)));
+    assert_eq!(error("4k3/8/8/8/8/8/8/4X3 w - - 0 1"), Some(FenError::InvalidPiece('X')));
+    assert_eq!(error("4k3/8/8/8/8/8/8/4K3 x - - 0 1"), Some(FenError::InvalidSideToMove("x".to_string())));
+    assert_eq!(error("4k3/8/8/8/8/8/8/8 w - - 0 1"), Some(FenError::KingCount(Color::White, 0)));
+    assert_eq!(error("P3k3/8/8/8/8/8/8/4K3 w - - 0 1"), Some(FenError::PawnOnBackRank));
+    assert_eq!(error("4k3/4Q3/8/8/8/8/8/4K3 w - - 0 1"), Some(FenError::OpponentInCheck));
+    assert_eq!(error("4k3/8/8/8/8/8/8/4K3 w K - 0 1"), Some(FenError::CastleWithoutRook('K')));
+    assert_eq!(error("4k3/8/8/8/8/8/4K3/7R w K - 0 1"), Some(FenError::CastleWithoutKing('K')));
+    assert_eq!(error("4k3/8/8/8/8/8/8/4K2R w KK - 0 1"), Some(FenError::DuplicateCastleSide('K')));
+    assert_eq!(error("4k3/8/8/8/8/8/8/4K3 w - e3 0 1"), Some(FenError::InvalidEnPassant("e3".to_string())));
+    assert_eq!(error("4k3/8/8/8/8/8/8/4K3 w - - x 1"), Some(FenError::InvalidHalfMoveClock("x".to_string())));
+
+    // The move counters can be left out
+    let state = parse_fen_string("4k3/8/8/8/4P3/8/8/4K3 b - e3".to_string()).unwrap();
+    assert_eq!(state.to_fen(), "4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1");
+    // The side to move can be in check
+    assert!(parse_fen_string("4k3/4Q3/8/8/8/8/8/4K3 b - - 0 1".to_string()).unwrap().check);
+}
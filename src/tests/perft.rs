@@ -2,7 +2,7 @@
 use crate::{bitboard::Color, move_gen::MoveGenType, parsing::parse_fen_string, state::State, tests::init};
 
 #[allow(dead_code)]
-const PERFT_TEST_CASES: [(&str, i64, i64); 6] = [
+pub const PERFT_TEST_CASES: [(&str, i64, i64); 6] = [
     ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 6, 119060324),
     ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 5, 193690690),
     ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 7, 178633661),
@@ -11,6 +11,30 @@ const PERFT_TEST_CASES: [(&str, i64, i64); 6] = [
     ("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1", 5, 3605103),
 ];
 
+// Shredder-FEN castling fields, with castles where the king stays put or swaps squares with its rook
+#[allow(dead_code)]
+pub const CHESS960_PERFT_TEST_CASES: [(&str, i64, i64); 4] = [
+    ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", 4, 326672),
+    ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", 4, 667366),
+    ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", 4, 273318),
+    ("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", 4, 382958),
+];
+
+#[test]
+#[allow(dead_code)]
+fn chess960_perft_test() {
+    init();
+    for case in CHESS960_PERFT_TEST_CASES.iter() {
+        let mut move_count = 0;
+        let mut state = parse_fen_string(case.0.to_string()).unwrap();
+        match state.turn {
+            Color::White => perft::<{Color::White}>(&mut state, case.1, &mut move_count),
+            Color::Black => perft::<{Color::Black}>(&mut state, case.1, &mut move_count),
+        }
+        assert_eq!(move_count, case.2, "Perft differs for {}", case.0);
+    }
+}
+
 #[test]
 #[allow(dead_code)]
 fn perft_test() {
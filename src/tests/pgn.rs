@@ -0,0 +1,91 @@
+#[allow(unused_imports)]
+use crate::{evaluation::CENTI_PAWN, parsing::uci_move_from_string, pgn::{parse_pgn, search_comment, GameResult, PgnGame, PgnMove}, tests::init};
+
+#[allow(dead_code)]
+const TEST_PGN: &str = r#"[Event "Test \"quoted\""]
+[Site "?"]
+[Result "1-0"]
+
+{Opening comment} 1. e4 e5 2. Nf3 $1 (2. f4!? exf4 (2... d5) 3. Nf3) 2... Nc6 {Knight out}
+3. Bb5 a6?! ; rest of line comment
+% escaped line
+4.Ba4 Nf6 5. O-O 1-0
+
+[Event "Second"]
+[SetUp "1"]
+[FEN "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1"]
+
+1. b8=Q+ Kd7 *
+"#;
+
+#[test]
+#[allow(dead_code)]
+fn pgn_parse_test() {
+    init();
+    let games = parse_pgn(TEST_PGN).unwrap();
+    assert_eq!(games.len(), 2);
+    let game = &games[0];
+    assert_eq!(game.tag("Event"), Some("Test \"quoted\""));
+    assert_eq!(game.result, GameResult::WhiteWin);
+    assert_eq!(game.comment.as_deref(), Some("Opening comment"));
+    assert_eq!(game.moves.len(), 9);
+    assert_eq!(game.moves[2].nags, vec![1]);
+    assert_eq!(game.moves[3].comment.as_deref(), Some("Knight out"));
+    assert_eq!(game.moves[5].nags, vec![6]);
+    assert_eq!(game.moves[5].comment.as_deref(), None);
+
+    let variation = &game.moves[2].variations[0];
+    assert_eq!(variation.len(), 3);
+    assert_eq!(variation[0].nags, vec![5]);
+    assert_eq!(variation[1].variations[0].len(), 1);
+    assert_eq!(game.final_state().unwrap().to_fen(), "r1bqkb1r/1ppp1ppp/p1n2n2/4p3/B3P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 3 5");
+
+    let second = &games[1];
+    assert_eq!(second.result, GameResult::Unknown);
+    assert_eq!(second.final_state().unwrap().to_fen(), "1Q6/3k4/8/8/8/8/8/4K3 w - - 1 2");
+
+    assert!(parse_pgn("1. e4 e5 2. Ke3 *").is_err());
+    assert!(parse_pgn("1. e4 (1. d4 *").is_err());
+    assert!(parse_pgn("1. e4 {unterminated").is_err());
+}
+
+#[test]
+#[allow(dead_code)]
+fn pgn_castle_test() {
+    init();
+    // Castling written with zeros must not be mistaken for a move number, with or without one in front of it.
+    for pgn in [
+        "[FEN \"r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1\"]\n\n1. 0-0 0-0-0 *",
+        "[FEN \"r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1\"]\n\n1.0-0 0-0-0 *",
+        "[FEN \"r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1\"]\n\n1. O-O 1...O-O-O *",
+    ] {
+        let games = parse_pgn(pgn).unwrap();
+        assert_eq!(games[0].moves.len(), 2, "Wrong move count for {}", pgn);
+        assert_eq!(games[0].final_state().unwrap().to_fen(), "2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 2");
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn pgn_write_test() {
+    init();
+    let games = parse_pgn(TEST_PGN).unwrap();
+    // Writing and reading back gives the same games
+    let written: String = games.iter().map(|game| game.to_pgn().unwrap()).collect();
+    assert_eq!(parse_pgn(&written).unwrap(), games);
+    assert!(written.contains("2. Nf3 $1 (2. f4 $5 exf4 (2... d5) 3. Nf3) 2..."));
+    assert!(written.contains("{Knight out} 3. Bb5 a6 $6 4. Ba4"));
+    assert!(written.lines().all(|line| line.len() <= 80));
+
+    let mut game = PgnGame::new(Some("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1"));
+    let state = game.start_state().unwrap();
+    let mut promotion = PgnMove::new(uci_move_from_string(&state, "b7b8q").unwrap());
+    promotion.comment = Some(search_comment(9 * 100 * CENTI_PAWN, 12, std::time::Duration::from_millis(512)));
+    game.moves.push(promotion);
+    game.set_result(GameResult::Draw);
+    let pgn = game.to_pgn().unwrap();
+    assert!(pgn.contains("[FEN \"4k3/1P6/8/8/8/8/8/4K3 w - - 0 1\"]"));
+    assert!(pgn.contains("[Result \"1/2-1/2\"]"));
+    assert!(pgn.contains("1. b8=Q+ {+9.00/12 0.512s}"));
+    assert!(pgn.trim_end().ends_with("1/2-1/2"));
+}
@@ -0,0 +1,63 @@
+#[allow(unused_imports)]
+use crate::{parsing::{parse_fen_string, uci_move_from_string}, san::san_move_from_string, state::State, tests::{init, perft::PERFT_TEST_CASES}};
+
+#[allow(dead_code)]
+fn san_of(fen: &str, uci: &str) -> String {
+    let mut state = parse_fen_string(fen.to_string()).unwrap();
+    let m = uci_move_from_string(&state, uci).unwrap();
+    state.san_string_move(m)
+}
+
+#[test]
+#[allow(dead_code)]
+fn san_string_test() {
+    init();
+    let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    assert_eq!(san_of(kiwipete, "e1g1"), "O-O");
+    assert_eq!(san_of(kiwipete, "e1c1"), "O-O-O");
+    assert_eq!(san_of(kiwipete, "e5f7"), "Nxf7");
+    assert_eq!(san_of(kiwipete, "d5e6"), "dxe6");
+    assert_eq!(san_of(kiwipete, "a2a4"), "a4");
+    assert_eq!(san_of(kiwipete, "f3f6"), "Qxf6");
+    // Both knights reach b5 so the file is added, and both rooks reach a3 along the same file
+    assert_eq!(san_of("4k3/8/8/8/8/N1N5/8/4K3 w - - 0 1", "c3b5"), "Ncb5");
+    assert_eq!(san_of("4k3/8/8/8/8/N1N5/8/4K3 w - - 0 1", "a3b1"), "Nab1");
+    assert_eq!(san_of("4k3/8/8/8/8/N1N5/8/4K3 w - - 0 1", "c3d5"), "Nd5");
+    assert_eq!(san_of("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3"), "R1a3");
+    assert_eq!(san_of("1k6/8/8/8/Q6Q/8/8/3K3Q w - - 0 1", "h4e4"), "Qh4e4");
+    // Promotions, en passant and mate
+    assert_eq!(san_of("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8n"), "b8=N");
+    assert_eq!(san_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), "exd6");
+    assert_eq!(san_of("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", "a1a8"), "Ra8+");
+    assert_eq!(san_of("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", "a1a8"), "Ra8#");
+}
+
+#[test]
+#[allow(dead_code)]
+fn san_parse_test() {
+    init();
+    let state = parse_fen_string("4k3/1P6/8/8/8/N1N5/8/4K3 w - - 0 1".to_string()).unwrap();
+    assert_eq!(san_move_from_string(&state, "b8=Q+"), uci_move_from_string(&state, "b7b8q"));
+    assert_eq!(san_move_from_string(&state, "b8N"), uci_move_from_string(&state, "b7b8n"));
+    assert_eq!(san_move_from_string(&state, "Ncb5!?"), uci_move_from_string(&state, "c3b5"));
+    assert_eq!(san_move_from_string(&state, "Nc3b5"), uci_move_from_string(&state, "c3b5"));
+    // Ambiguous, illegal or malformed
+    assert_eq!(san_move_from_string(&state, "Nb5"), None);
+    assert_eq!(san_move_from_string(&state, "b8"), None);
+    assert_eq!(san_move_from_string(&state, "O-O"), None);
+    assert_eq!(san_move_from_string(&state, "Nb9"), None);
+    assert_eq!(san_move_from_string(&state, "é"), None);
+}
+
+#[test]
+#[allow(dead_code)]
+fn san_round_trip_test() {
+    init();
+    for (fen, _, _) in PERFT_TEST_CASES {
+        let mut state = parse_fen_string(fen.to_string()).unwrap();
+        for m in &state.legal_moves() {
+            let san = state.san_string_move(*m);
+            assert_eq!(san_move_from_string(&state, &san), Some(*m), "{} in {}", san, fen);
+        }
+    }
+}
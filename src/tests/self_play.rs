@@ -0,0 +1,77 @@
+#[allow(unused_imports)]
+use crate::{parsing::{parse_fen_string, uci_move_from_string}, pgn::GameResult, self_play::{adjudicate, elo_to_score, is_insufficient_material, parse_match_command, score_to_elo, EngineConfig, MatchScore, SprtConfig}, tests::init};
+
+#[test]
+#[allow(dead_code)]
+fn adjudication_test() {
+    init();
+    let result = |fen: &str| adjudicate(&parse_fen_string(fen.to_string()).unwrap(), &[]).map(|(result, _)| result);
+    assert_eq!(result("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1"), Some(GameResult::WhiteWin));
+    assert_eq!(result("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"), Some(GameResult::Draw));
+    assert_eq!(result("4k3/8/8/8/8/8/8/R3K3 b - - 100 80"), Some(GameResult::Draw));
+    assert_eq!(result("4k3/8/8/8/8/8/8/R3K3 b - - 99 80"), None);
+    // Bishops on the same colour can never mate
+    assert_eq!(result("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1"), Some(GameResult::Draw));
+    assert_eq!(result("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1"), None);
+
+    assert!(is_insufficient_material(&parse_fen_string("4k3/8/8/8/8/8/8/4KN2 w - - 0 1".to_string()).unwrap()));
+    assert!(!is_insufficient_material(&parse_fen_string("4k3/8/8/8/8/8/8/3NKN2 w - - 0 1".to_string()).unwrap()));
+    assert!(!is_insufficient_material(&parse_fen_string("4kb2/8/8/8/8/8/8/4KB2 w - - 0 1".to_string()).unwrap()));
+    assert!(!is_insufficient_material(&parse_fen_string("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1".to_string()).unwrap()));
+
+    // Knights shuffling back to the start repeat it for the third time
+    let mut state = parse_fen_string("4k1n1/8/8/8/8/8/8/4K1N1 w - - 0 1".to_string()).unwrap();
+    let mut hashes = Vec::new();
+    for _ in 0..2 {
+        for uci in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            assert_eq!(adjudicate(&state, &hashes), None);
+            hashes.push(state.hashcode);
+            let m = uci_move_from_string(&state, uci).unwrap();
+            state.non_reversible_move(m);
+        }
+    }
+    assert_eq!(adjudicate(&state, &hashes).map(|(result, _)| result), Some(GameResult::Draw));
+}
+
+#[test]
+#[allow(dead_code)]
+fn match_statistics_test() {
+    assert!(score_to_elo(0.5).abs() < 1e-9);
+    assert!((score_to_elo(elo_to_score(100.0)) - 100.0).abs() < 1e-9);
+
+    let score = MatchScore { wins: 60, losses: 40, draws: 100 };
+    assert_eq!(score.games(), 200);
+    assert!((score.score() - 0.55).abs() < 1e-9);
+    let (elo, error) = score.elo();
+    assert!((elo - 34.86).abs() < 0.1, "{}", elo);
+    assert!(error > 20.0 && error < 40.0, "{}", error);
+
+    let sprt = SprtConfig { elo0: 0.0, elo1: 10.0, alpha: 0.05, beta: 0.05 };
+    let (lower, upper) = sprt.bounds();
+    assert!((upper - 2.944).abs() < 0.01 && (lower + 2.944).abs() < 0.01);
+    // A score well above elo1 favours H1 and one well below elo0 favours H0
+    assert!(score.llr(&sprt) > 0.0);
+    assert!(MatchScore { wins: 40, losses: 60, draws: 100 }.llr(&sprt) < 0.0);
+}
+
+#[test]
+#[allow(dead_code)]
+fn match_command_test() {
+    let config = parse_match_command(&["default", "UseNNUE=true,EvalFile=net.bin", "games", "10", "nodes", "5000", "sprt", "0", "5"]).unwrap();
+    assert_eq!(config.engines[0].options, vec![]);
+    assert_eq!(config.engines[1].options, vec![("UseNNUE".to_string(), "true".to_string()), ("EvalFile".to_string(), "net.bin".to_string())]);
+    assert_eq!(config.games, 10);
+    assert_eq!(config.limits.nodes, Some(5000));
+    assert_eq!(config.limits.time, None);
+    assert_eq!(config.sprt, Some(SprtConfig { elo0: 0.0, elo1: 5.0, alpha: 0.05, beta: 0.05 }));
+
+    let config = parse_match_command(&["old.txt", "new.txt", "sprt", "0", "5", "0.1", "0.1", "pgn", "games.pgn"]).unwrap();
+    assert_eq!(config.engines[0], EngineConfig { name: "old.txt".to_string(), options: vec![("EvalParams".to_string(), "old.txt".to_string())] });
+    assert_eq!(config.sprt.map(|sprt| sprt.alpha), Some(0.1));
+    assert_eq!(config.pgn_path.as_deref(), Some("games.pgn"));
+    assert!(config.limits.time.is_some());
+
+    assert!(parse_match_command(&["default"]).is_err());
+    assert!(parse_match_command(&["default", "default", "games"]).is_err());
+    assert!(parse_match_command(&["default", "default", "speed", "1"]).is_err());
+}
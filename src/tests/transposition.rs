@@ -0,0 +1,26 @@
+#[allow(unused_imports)]
+use crate::{bitboard::Color, evaluation::{CENTI_PAWN, HIGHEST_EVAL, LOWEST_EVAL}, parsing::{parse_fen_string, uci_move_from_string}, r#move::NULL_MOVE, transposition::{add_quiescence_tt_state, add_tt_state, parse_packed_depth_and_node, search_tt_state, NodeType}, tests::init, worker::Worker};
+
+#[test]
+#[allow(dead_code)]
+fn quiescence_tt_replacement_test() {
+    init();
+    let mut state = parse_fen_string("4k3/8/2n5/3p4/4P3/8/8/4K2R w K - 0 1".to_string()).unwrap();
+    let capture = uci_move_from_string(&state, "e4d5").unwrap();
+    let quiet = uci_move_from_string(&state, "h1h7").unwrap();
+
+    // A quiescence search of the position keeps the deeper negamax entry in place.
+    add_tt_state(&state, 50 * CENTI_PAWN, quiet, 6, NodeType::CutNode);
+    Worker::new().quiescence_search::<{Color::White}>(&mut state, LOWEST_EVAL, HIGHEST_EVAL);
+    add_quiescence_tt_state(&state, 0, capture, NodeType::PVNode);
+    let entry = search_tt_state(&state).unwrap();
+    let (depth, node_type) = parse_packed_depth_and_node(entry.packed_depth_and_node);
+    assert_eq!((depth, node_type, entry.best_move), (6, NodeType::CutNode, quiet));
+
+    // Depth 0 entries are replaced by newer quiescence results.
+    add_tt_state(&state, 0, NULL_MOVE, 0, NodeType::AllNode);
+    add_quiescence_tt_state(&state, 0, capture, NodeType::PVNode);
+    let entry = search_tt_state(&state).unwrap();
+    let (depth, node_type) = parse_packed_depth_and_node(entry.packed_depth_and_node);
+    assert_eq!((depth, node_type, entry.best_move), (0, NodeType::PVNode, capture));
+}
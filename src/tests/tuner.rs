@@ -0,0 +1,57 @@
+#[allow(unused_imports)]
+use std::{env, fs};
+
+#[allow(unused_imports)]
+use crate::{eval_params::MAX_PARAMETER_CENTI_PAWNS, evaluation::CENTI_PAWN, parsing::parse_fen_string, score::Score, tests::init, tuner::{coefficients, current_parameters, eval_params_from, linear_eval, load_entries, TuningEntry}};
+
+#[allow(dead_code)]
+const LINEAR_EVAL_TEST_FENS: [&str; 3] = [
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+];
+
+#[test]
+#[allow(dead_code)]
+fn linear_eval_matches_eval_test() {
+    init();
+    let parameters = current_parameters();
+    for fen in LINEAR_EVAL_TEST_FENS {
+        let state = parse_fen_string(fen.to_string()).unwrap();
+        let entry = TuningEntry { result: 0.5, coefficients: coefficients(&state) };
+        let eval = state.classical_eval(None) as f64 / CENTI_PAWN as f64;
+        // The engine rounds the blended tables down to whole units, the tuner doesn't.
+        assert!((linear_eval(&entry, &parameters) - eval).abs() < 1.0, "Linear eval differs from eval for {}", fen);
+    }
+}
+
+#[test]
+#[allow(dead_code)]
+fn load_entries_test() {
+    init();
+    let path = env::temp_dir().join("rhobos_tuner_positions.txt");
+    fs::write(&path, concat!(
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1 [0.5]\n",
+        "4k3/8/8/8/8/8/2QQ4/4K3 w - - c9 \"1-0\";\n",
+        "\n",
+        "4k3/8/8/8/8/8/qr6/6K1 b - - 0 40; 0-1\n",
+    )).unwrap();
+    let entries = load_entries(path.to_str().unwrap()).unwrap();
+    fs::remove_file(&path).unwrap();
+    let results: Vec<f64> = entries.iter().map(|entry| entry.result).collect();
+    assert_eq!(results, vec![0.5, 1.0, 0.0]);
+}
+
+#[test]
+#[allow(dead_code)]
+fn eval_params_from_clamp_test() {
+    init();
+    let mut parameters = current_parameters();
+    parameters.iter_mut().for_each(|parameter| *parameter = 1e12);
+    let params = eval_params_from(&parameters);
+    let max = MAX_PARAMETER_CENTI_PAWNS * CENTI_PAWN;
+    assert_eq!(params.mobility, Score::new(max, max));
+    assert_eq!(params.midgame_tables[5][20], max);
+    parameters.iter_mut().for_each(|parameter| *parameter = -1e12);
+    assert_eq!(eval_params_from(&parameters).pawn_doubled, Score::new(-max, -max));
+}
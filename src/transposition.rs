@@ -43,7 +43,7 @@ pub struct TranspositionTable {
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum NodeType {
     PVNode,
     CutNode,
@@ -70,6 +70,16 @@ pub unsafe fn free_ttable() {
     unsafe { TRANSPOSITION_TABLE.data_pointer = null_mut(); }
 }
 
+// Empties every entry without reallocating, zeroed entries are treated as empty.
+#[cold]
+pub fn clear_ttable() {
+    unsafe {
+        if !TRANSPOSITION_TABLE.data_pointer.is_null() {
+            TRANSPOSITION_TABLE.data_pointer.write_bytes(0, TRANSPOSITION_TABLE.entries as usize);
+        }
+    }
+}
+
 #[cold]
 #[allow(dead_code)]
 pub unsafe fn calculate_ttable_usage() -> f64{
@@ -109,6 +119,18 @@ pub fn add_tt_state(state: &State, eval: Evaluation, best_move: Move, depth: Dep
     }};
 }
 
+// Depth 0 entries from quiescence only replace empty slots and other depth 0 entries so they never push out the
+// deeper entries negamax relies on. Empty slots are zeroed and so also read as depth 0.
+#[inline(always)]
+pub fn add_quiescence_tt_state(state: &State, eval: Evaluation, best_move: Move, node_type: NodeType) {
+    let index = tt_index(state.hashcode);
+    debug_assert!(index < unsafe { TRANSPOSITION_TABLE.entries as usize });
+    let (stored_depth, _) = parse_packed_depth_and_node(unsafe { (*TRANSPOSITION_TABLE.data_pointer.add(index)).data.packed_depth_and_node });
+    if stored_depth == 0 {
+        add_tt_state(state, eval, best_move, 0, node_type);
+    }
+}
+
 #[inline(always)]
 pub fn search_tt_state(state: &State) -> Option<TTableData> {
     let hash = state.hashcode;
@@ -0,0 +1,307 @@
+use std::{fs, time::Instant};
+
+use crate::{bitboard::{pop_lsb, Color, COLORS, EMPTY_BITBOARD}, endgame::{Endgame, EndgameKind, SCALE_FACTOR_NORMAL}, eval_params::{eval_params, EvalParams, MAX_PARAMETER_CENTI_PAWNS}, evaluation::{Evaluation, CENTI_PAWN, HIGHEST_EVAL, LOWEST_EVAL, MATE_VALUE_CUTOFF, TOTAL_PHASE_VALUE}, r#move::NULL_MOVE, parsing::parse_fen_string, score::Score, state::State, worker::Worker};
+
+// Layout of the tuned parameter vector, every value is in centipawns.
+const MIDGAME_TABLE_OFFSET: usize = 0;
+const ENDGAME_TABLE_OFFSET: usize = MIDGAME_TABLE_OFFSET + 6 * 64;
+// Tapered parameters take two slots, midgame then endgame.
+const PIECE_VALUE_OFFSET: usize = ENDGAME_TABLE_OFFSET + 6 * 64;
+const MOBILITY_OFFSET: usize = PIECE_VALUE_OFFSET + 12;
+const PAWN_ISOLATED_OFFSET: usize = MOBILITY_OFFSET + 2;
+const PAWN_DOUBLED_OFFSET: usize = PAWN_ISOLATED_OFFSET + 2;
+const KING_SAFETY_OFFSET: usize = PAWN_DOUBLED_OFFSET + 2;
+const BISHOP_PAIR_OFFSET: usize = KING_SAFETY_OFFSET + 200;
+const ROOK_OPEN_FILE_OFFSET: usize = BISHOP_PAIR_OFFSET + 2;
+const ROOK_SEMI_OPEN_FILE_OFFSET: usize = ROOK_OPEN_FILE_OFFSET + 2;
+const ROOK_SEVENTH_OFFSET: usize = ROOK_SEMI_OPEN_FILE_OFFSET + 2;
+const KNIGHT_OUTPOST_OFFSET: usize = ROOK_SEVENTH_OFFSET + 2;
+const PAWN_BACKWARD_OFFSET: usize = KNIGHT_OUTPOST_OFFSET + 2;
+const PAWN_CONNECTED_OFFSET: usize = PAWN_BACKWARD_OFFSET + 2;
+const PASSED_RANK_OFFSET: usize = PAWN_CONNECTED_OFFSET + 2;
+const PASSED_OWN_KING_DISTANCE_OFFSET: usize = PASSED_RANK_OFFSET + 16;
+const PASSED_ENEMY_KING_DISTANCE_OFFSET: usize = PASSED_OWN_KING_DISTANCE_OFFSET + 2;
+const KING_SHELTER_OFFSET: usize = PASSED_ENEMY_KING_DISTANCE_OFFSET + 2;
+const KING_STORM_OFFSET: usize = KING_SHELTER_OFFSET + 16;
+const THREAT_BY_PAWN_OFFSET: usize = KING_STORM_OFFSET + 16;
+const THREAT_BY_MINOR_OFFSET: usize = THREAT_BY_PAWN_OFFSET + 2;
+const THREAT_BY_ROOK_OFFSET: usize = THREAT_BY_MINOR_OFFSET + 2;
+pub const PARAMETER_COUNT: usize = THREAT_BY_ROOK_OFFSET + 2;
+
+// Same order as EvalParams::TAPERED_PARAMETER_NAMES.
+const TAPERED_OFFSETS: [usize; 20] = [
+    PIECE_VALUE_OFFSET, MOBILITY_OFFSET, PAWN_ISOLATED_OFFSET, PAWN_DOUBLED_OFFSET, KING_SAFETY_OFFSET, BISHOP_PAIR_OFFSET, ROOK_OPEN_FILE_OFFSET, ROOK_SEMI_OPEN_FILE_OFFSET, ROOK_SEVENTH_OFFSET, KNIGHT_OUTPOST_OFFSET,
+    PAWN_BACKWARD_OFFSET, PAWN_CONNECTED_OFFSET, PASSED_RANK_OFFSET, PASSED_OWN_KING_DISTANCE_OFFSET, PASSED_ENEMY_KING_DISTANCE_OFFSET,
+    KING_SHELTER_OFFSET, KING_STORM_OFFSET, THREAT_BY_PAWN_OFFSET, THREAT_BY_MINOR_OFFSET, THREAT_BY_ROOK_OFFSET,
+];
+
+const DEFAULT_EPOCHS: usize = 1000;
+const LEARNING_RATE: f64 = 1.0;
+const ADAM_BETA1: f64 = 0.9;
+const ADAM_BETA2: f64 = 0.999;
+const ADAM_EPSILON: f64 = 1e-8;
+// Quiescence lines longer than this are cut short, only reachable through a bug or a very odd position.
+const MAX_RESOLVE_PLIES: usize = 32;
+
+pub struct TuneConfig {
+    pub positions_path: String,
+    pub output_path: String,
+    pub epochs: usize,
+}
+
+// A quiet position reduced to the coefficient of every parameter it uses. The classical eval is linear in its
+// parameters so the eval of the position is the dot product of these with the parameter vector.
+pub struct TuningEntry {
+    pub result: f64,
+    pub coefficients: Vec<(usize, f64)>,
+}
+
+// Expects the tokens after tune, in the form <positions file> <output file> [epochs]
+pub fn parse_tune_command(tokens: &[&str]) -> Result<TuneConfig, String> {
+    let positions_path = tokens.first().ok_or("Expected a positions file")?.to_string();
+    let output_path = tokens.get(1).ok_or("Expected an output file")?.to_string();
+    let epochs = match tokens.get(2) {
+        Some(epochs) => epochs.parse().map_err(|_| format!("Invalid epoch count: {}", epochs))?,
+        None => DEFAULT_EPOCHS,
+    };
+    Ok(TuneConfig { positions_path, output_path, epochs })
+}
+
+pub fn run_tuner(config: TuneConfig) -> Result<(), String> {
+    let start = Instant::now();
+    let entries = load_entries(&config.positions_path)?;
+    if entries.is_empty() {
+        return Err(format!("No usable positions in {}", config.positions_path));
+    }
+    println!("Loaded {} positions in {:.1}s", entries.len(), start.elapsed().as_secs_f64());
+
+    let mut parameters = current_parameters();
+    let scaling = optimal_scaling(&entries, &parameters);
+    println!("Scaling constant {:.4}, initial loss {:.6}", scaling, loss(&entries, &parameters, scaling));
+
+    let mut first_moment = vec![0.0; PARAMETER_COUNT];
+    let mut second_moment = vec![0.0; PARAMETER_COUNT];
+    for epoch in 1..=config.epochs {
+        let gradient = gradient(&entries, &parameters, scaling);
+        for i in 0..PARAMETER_COUNT {
+            first_moment[i] = ADAM_BETA1 * first_moment[i] + (1.0 - ADAM_BETA1) * gradient[i];
+            second_moment[i] = ADAM_BETA2 * second_moment[i] + (1.0 - ADAM_BETA2) * gradient[i] * gradient[i];
+            let corrected_first = first_moment[i] / (1.0 - ADAM_BETA1.powi(epoch as i32));
+            let corrected_second = second_moment[i] / (1.0 - ADAM_BETA2.powi(epoch as i32));
+            parameters[i] -= LEARNING_RATE * corrected_first / (corrected_second.sqrt() + ADAM_EPSILON);
+        }
+        if epoch % 50 == 0 || epoch == config.epochs {
+            println!("Epoch {} loss {:.6}", epoch, loss(&entries, &parameters, scaling));
+            write_parameters(&config.output_path, &parameters)?;
+        }
+    }
+    println!("Tuned parameters written to {} in {:.1}s", config.output_path, start.elapsed().as_secs_f64());
+    Ok(())
+}
+
+// Reads one position per line, a FEN followed by the game result from white's perspective. Accepted result forms are
+// 1-0, 0-1 and 1/2-1/2, optionally quoted as in an EPD c9 opcode, or [1.0], [0.5] and [0.0].
+pub fn load_entries(path: &str) -> Result<Vec<TuningEntry>, String> {
+    let contents = fs::read_to_string(path).map_err(|err| format!("Unable to read positions file {}: {}", path, err))?;
+    let mut worker = Worker::new();
+    let mut entries = Vec::new();
+    for (line_number, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (fen, result) = parse_labelled_line(line).ok_or(format!("Unable to parse line {}: {}", line_number + 1, line))?;
+        let mut state = parse_fen_string(fen).map_err(|err| format!("Invalid FEN on line {}: {}", line_number + 1, err))?;
+        if let Some(entry) = tuning_entry(&mut worker, &mut state, result) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+fn parse_labelled_line(line: &str) -> Option<(String, f64)> {
+    let result = if line.contains("1/2-1/2") || line.contains("[0.5]") {
+        0.5
+    } else if line.contains("1-0") || line.contains("[1.0]") {
+        1.0
+    } else if line.contains("0-1") || line.contains("[0.0]") {
+        0.0
+    } else {
+        return None;
+    };
+    // Board, turn, castling and en passant are always present, the move counters only sometimes.
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 4 {
+        return None;
+    }
+    let mut fen_fields = fields[..4].to_vec();
+    let counters: Vec<&str> = fields[4..].iter().take(2).take_while(|field| field.parse::<u32>().is_ok()).copied().collect();
+    match counters.len() {
+        2 => fen_fields.extend(counters),
+        1 => fen_fields.extend([counters[0], "1"]),
+        _ => fen_fields.extend(["0", "1"]),
+    }
+    Some((fen_fields.join(" "), result))
+}
+
+// Plays out the quiescence principal variation so that the tuned eval is only ever applied to quiet positions.
+// Positions ending in check or mate are dropped since the static eval doesn't describe them.
+pub fn tuning_entry(worker: &mut Worker, state: &mut State, result: f64) -> Option<TuningEntry> {
+    worker.root_ply = state.ply;
+    for _ in 0..MAX_RESOLVE_PLIES {
+        let (eval, best_move) = match state.turn {
+            Color::White => worker.quiescence_search::<{Color::White}>(state, LOWEST_EVAL, HIGHEST_EVAL),
+            Color::Black => worker.quiescence_search::<{Color::Black}>(state, LOWEST_EVAL, HIGHEST_EVAL),
+        };
+        state.current_move_list().reset();
+        if eval.abs() >= MATE_VALUE_CUTOFF {
+            return None;
+        }
+        if best_move == NULL_MOVE {
+            break;
+        }
+        state.non_reversible_move(best_move);
+    }
+    // Specialized endgame evaluators don't depend on the parameters.
+    if state.check || matches!(state.endgame(), Some(Endgame { kind: EndgameKind::Evaluation(_), .. })) {
+        return None;
+    }
+    Some(TuningEntry { result, coefficients: coefficients(state) })
+}
+
+// Coefficient of every parameter in the white relative eval of the state.
+pub fn coefficients(state: &State) -> Vec<(usize, f64)> {
+    let trace = state.eval_trace();
+    let midgame_weight = trace.phase as f64 / TOTAL_PHASE_VALUE as f64;
+    let endgame_weight = 1.0 - midgame_weight;
+    let mut dense = vec![0.0; PARAMETER_COUNT];
+    for color in COLORS {
+        let sign = match color { Color::White => 1.0, Color::Black => -1.0 };
+        for piece_type in 0..6 {
+            let mut piece_board = unsafe { state.get_piece_board_raw(color as u8, piece_type) };
+            while piece_board != EMPTY_BITBOARD {
+                // Tables are stored from white's point of view, black's squares are mirrored into them.
+                let square = match color { Color::White => pop_lsb(&mut piece_board), Color::Black => pop_lsb(&mut piece_board) ^ 56 };
+                let table_index = piece_type as usize * 64 + square as usize;
+                dense[PIECE_VALUE_OFFSET + piece_type as usize * 2] += sign * midgame_weight;
+                dense[PIECE_VALUE_OFFSET + piece_type as usize * 2 + 1] += sign * endgame_weight;
+                dense[MIDGAME_TABLE_OFFSET + table_index] += sign * midgame_weight;
+                dense[ENDGAME_TABLE_OFFSET + table_index] += sign * endgame_weight;
+            }
+        }
+        let side = color as usize;
+        let mut tapered = |offset: usize, count: u32| {
+            dense[offset] += sign * count as f64 * midgame_weight;
+            dense[offset + 1] += sign * count as f64 * endgame_weight;
+        };
+        tapered(MOBILITY_OFFSET, trace.mobility_counts[side].iter().sum::<u32>());
+        tapered(PAWN_ISOLATED_OFFSET, trace.isolated_pawns[side]);
+        tapered(PAWN_DOUBLED_OFFSET, trace.doubled_pawns[side]);
+        tapered(KING_SAFETY_OFFSET + trace.king_attack_units[side].min(99) as usize * 2, 1);
+        tapered(BISHOP_PAIR_OFFSET, trace.bishop_pairs[side]);
+        tapered(ROOK_OPEN_FILE_OFFSET, trace.rooks_open_file[side]);
+        tapered(ROOK_SEMI_OPEN_FILE_OFFSET, trace.rooks_semi_open_file[side]);
+        tapered(ROOK_SEVENTH_OFFSET, trace.rooks_seventh[side]);
+        tapered(KNIGHT_OUTPOST_OFFSET, trace.knight_outposts[side]);
+        tapered(PAWN_BACKWARD_OFFSET, trace.backward_pawns[side]);
+        tapered(PAWN_CONNECTED_OFFSET, trace.connected_pawns[side]);
+        tapered(PASSED_OWN_KING_DISTANCE_OFFSET, trace.passed_own_king_distance[side]);
+        tapered(PASSED_ENEMY_KING_DISTANCE_OFFSET, trace.passed_enemy_king_distance[side]);
+        tapered(THREAT_BY_PAWN_OFFSET, trace.threats_by_pawn[side]);
+        tapered(THREAT_BY_MINOR_OFFSET, trace.threats_by_minor[side]);
+        tapered(THREAT_BY_ROOK_OFFSET, trace.threats_by_rook[side]);
+        for rank in 0..8 {
+            tapered(PASSED_RANK_OFFSET + rank * 2, trace.passed_ranks[side][rank]);
+            tapered(KING_SHELTER_OFFSET + rank * 2, trace.king_shelter[side][rank]);
+            tapered(KING_STORM_OFFSET + rank * 2, trace.king_storm[side][rank]);
+        }
+    }
+    // Scaling keeps the eval linear in the parameters.
+    let scale = trace.scale_factor as f64 / SCALE_FACTOR_NORMAL as f64;
+    dense.into_iter().enumerate().filter(|(_, coefficient)| *coefficient != 0.0).map(|(index, coefficient)| (index, coefficient * scale)).collect()
+}
+
+// The parameters the engine is currently using, in centipawns.
+pub fn current_parameters() -> Vec<f64> {
+    let params = eval_params();
+    let mut parameters = vec![0.0; PARAMETER_COUNT];
+    for piece_type in 0..6 {
+        for square in 0..64 {
+            parameters[MIDGAME_TABLE_OFFSET + piece_type * 64 + square] = params.midgame_tables[piece_type][square] as f64;
+            parameters[ENDGAME_TABLE_OFFSET + piece_type * 64 + square] = params.endgame_tables[piece_type][square] as f64;
+        }
+    }
+    let mut params = params.clone();
+    for (name, offset) in EvalParams::TAPERED_PARAMETER_NAMES.iter().zip(TAPERED_OFFSETS) {
+        for (i, score) in params.tapered_parameter(name).unwrap().iter().enumerate() {
+            parameters[offset + i * 2] = score.midgame() as f64;
+            parameters[offset + i * 2 + 1] = score.endgame() as f64;
+        }
+    }
+    parameters.iter_mut().for_each(|parameter| *parameter /= CENTI_PAWN as f64);
+    parameters
+}
+
+// Rounds the tuned values back to whole centipawns, clamped so they cannot overflow once scaled.
+pub fn eval_params_from(parameters: &[f64]) -> EvalParams {
+    let scaled = |index: usize| parameters[index].round().clamp(-MAX_PARAMETER_CENTI_PAWNS as f64, MAX_PARAMETER_CENTI_PAWNS as f64) as Evaluation * CENTI_PAWN;
+    let mut params = EvalParams::default_params();
+    for piece_type in 0..6 {
+        for square in 0..64 {
+            params.midgame_tables[piece_type][square] = scaled(MIDGAME_TABLE_OFFSET + piece_type * 64 + square);
+            params.endgame_tables[piece_type][square] = scaled(ENDGAME_TABLE_OFFSET + piece_type * 64 + square);
+        }
+    }
+    for (name, offset) in EvalParams::TAPERED_PARAMETER_NAMES.iter().zip(TAPERED_OFFSETS) {
+        for (i, score) in params.tapered_parameter(name).unwrap().iter_mut().enumerate() {
+            *score = Score::new(scaled(offset + i * 2), scaled(offset + i * 2 + 1));
+        }
+    }
+    params
+}
+
+// Eval in centipawns from white's perspective.
+pub fn linear_eval(entry: &TuningEntry, parameters: &[f64]) -> f64 {
+    entry.coefficients.iter().map(|(index, coefficient)| coefficient * parameters[*index]).sum()
+}
+
+fn sigmoid(eval: f64, scaling: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-scaling * eval / 400.0))
+}
+
+fn loss(entries: &[TuningEntry], parameters: &[f64], scaling: f64) -> f64 {
+    entries.iter().map(|entry| (entry.result - sigmoid(linear_eval(entry, parameters), scaling)).powi(2)).sum::<f64>() / entries.len() as f64
+}
+
+fn gradient(entries: &[TuningEntry], parameters: &[f64], scaling: f64) -> Vec<f64> {
+    let mut gradient = vec![0.0; PARAMETER_COUNT];
+    let sigmoid_slope = scaling * 10f64.ln() / 400.0;
+    for entry in entries {
+        let prediction = sigmoid(linear_eval(entry, parameters), scaling);
+        let error_slope = -2.0 * (entry.result - prediction) * prediction * (1.0 - prediction) * sigmoid_slope;
+        for (index, coefficient) in entry.coefficients.iter() {
+            gradient[*index] += error_slope * coefficient;
+        }
+    }
+    gradient.iter_mut().for_each(|value| *value /= entries.len() as f64);
+    gradient
+}
+
+// Scaling constant that best fits the current eval to the results, found by ternary search since the loss is unimodal in it.
+fn optimal_scaling(entries: &[TuningEntry], parameters: &[f64]) -> f64 {
+    let (mut low, mut high) = (0.0, 4.0);
+    for _ in 0..64 {
+        let low_third = low + (high - low) / 3.0;
+        let high_third = high - (high - low) / 3.0;
+        if loss(entries, parameters, low_third) < loss(entries, parameters, high_third) {
+            high = high_third;
+        } else {
+            low = low_third;
+        }
+    }
+    (low + high) / 2.0
+}
+
+// Writes the parameters as a parameter file that can be loaded at startup or with the EvalParams UCI option.
+fn write_parameters(path: &str, parameters: &[f64]) -> Result<(), String> {
+    fs::write(path, eval_params_from(parameters).to_string()).map_err(|err| format!("Unable to write {}: {}", path, err))
+}
@@ -1,14 +1,191 @@
+use std::{io::{stdin, stdout, Write}, time::Duration};
+
+use crate::{bitboard::Color, epd::{parse_epd_command, EpdConfig}, eval_params::{load_eval_params, set_eval_params, EvalParams}, evaluation::CENTI_PAWN, mate_search::MateSearchResult, move_pick::clear_history_tables, nnue::{load_network, set_use_nnue, use_nnue}, r#move::{set_chess960, uci_string_move, NULL_MOVE}, parsing::{parse_fen_string, starting_fen, uci_move_from_string}, search::{Depth, SearchLimits}, self_play::{parse_match_command, MatchConfig}, state::State, transposition::clear_ttable, tuner::{parse_tune_command, TuneConfig}, worker::Worker};
+
+const DEFAULT_SEARCH_TIME: Duration = Duration::from_secs(5);
+// Only a small part of the remaining time is used per move as there is no time management beyond this.
+const TIME_DIVISOR: u64 = 40;
+
 pub enum IORunMode {
     UCI,
     UserGame,
+    Tune(TuneConfig),
+    Epd(EpdConfig),
+    Match(MatchConfig),
 }
 
-pub static GAME_RUN_MODE: IORunMode = IORunMode::UCI;
-
-pub fn parse_uci_command(input: &str) -> Result<(), String>{
-    match input {
-        "uci" => todo!(),
-        "game" => todo!(),
+pub fn parse_run_mode(input: &str) -> Result<IORunMode, String> {
+    let tokens: Vec<&str> = input.split_whitespace().collect();
+    match tokens.first() {
+        Some(&"uci") => Ok(IORunMode::UCI),
+        Some(&"game") => Ok(IORunMode::UserGame),
+        Some(&"tune") => Ok(IORunMode::Tune(parse_tune_command(&tokens[1..])?)),
+        Some(&"epd") => Ok(IORunMode::Epd(parse_epd_command(&tokens[1..])?)),
+        Some(&"match") => Ok(IORunMode::Match(parse_match_command(&tokens[1..])?)),
         _ => Err("Unknown command".to_string())
     }
-} 
\ No newline at end of file
+}
+
+pub struct UciSession {
+    state: State,
+    worker: Worker,
+}
+
+// Runs the UCI protocol until quit is received or stdin is closed. Expects the initial uci command to have already been read.
+pub fn uci_loop() {
+    let mut session = UciSession { state: starting_fen(), worker: Worker::new() };
+    session.identify();
+    loop {
+        let mut input = String::new();
+        match stdin().read_line(&mut input) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => (),
+        }
+        if !session.handle_command(input.trim()) {
+            break;
+        }
+        stdout().flush().expect("Failed to flush stdout");
+    }
+}
+
+impl UciSession {
+    // Returns false once the session should end.
+    pub fn handle_command(&mut self, command: &str) -> bool {
+        let mut tokens = command.split_whitespace();
+        match tokens.next() {
+            Some("uci") => self.identify(),
+            Some("isready") => println!("readyok"),
+            Some("ucinewgame") => {
+                self.state = starting_fen();
+                self.worker = Worker::new();
+                clear_ttable();
+                clear_history_tables();
+            },
+            Some("position") => {
+                if let Err(err) = self.set_position(tokens.collect()) {
+                    println!("info string {}", err);
+                }
+            },
+            Some("setoption") => {
+                if let Err(err) = self.set_option(tokens.collect()) {
+                    println!("info string {}", err);
+                }
+            },
+            Some("go") => self.go(tokens.collect()),
+            Some("eval") => self.eval(),
+            Some("quit") => return false,
+            Some(other) => println!("info string Unknown command: {}", other),
+            None => (),
+        }
+        true
+    }
+
+    fn identify(&self) {
+        println!("id name Rhobos");
+        println!("id author csgarlock");
+        println!("option name EvalFile type string default <empty>");
+        println!("option name UseNNUE type check default false");
+        println!("option name EvalParams type string default <empty>");
+        println!("option name UCI_Chess960 type check default false");
+        println!("uciok");
+    }
+
+    // Expects the tokens after setoption, in the form name <id> [value <x>]
+    fn set_option(&mut self, tokens: Vec<&str>) -> Result<(), String> {
+        let value_index = tokens.iter().position(|token| *token == "value").unwrap_or(tokens.len());
+        let name = tokens.get(1..value_index).ok_or("Expected option name")?.join(" ");
+        let value = tokens.get(value_index + 1..).unwrap_or_default().join(" ");
+        match name.as_str() {
+            "EvalFile" => {
+                load_network(&value)?;
+                self.state.refresh_accumulator();
+            },
+            "UseNNUE" => {
+                set_use_nnue(value == "true")?;
+                self.state.refresh_accumulator();
+            },
+            "EvalParams" => {
+                if value.is_empty() || value == "<empty>" {
+                    set_eval_params(EvalParams::default_params());
+                } else {
+                    load_eval_params(&value)?;
+                }
+                self.state.incremental_eval = self.state.computed_incremental_eval();
+            },
+            "UCI_Chess960" => set_chess960(value == "true"),
+            _ => return Err(format!("Unknown option: {}", name)),
+        }
+        Ok(())
+    }
+
+    fn set_position(&mut self, tokens: Vec<&str>) -> Result<(), String> {
+        let moves_index = tokens.iter().position(|token| *token == "moves").unwrap_or(tokens.len());
+        let mut state = match tokens.first() {
+            Some(&"startpos") => starting_fen(),
+            Some(&"fen") => parse_fen_string(tokens[1..moves_index].join(" ")).map_err(|err| err.to_string())?,
+            _ => return Err("Expected startpos or fen".to_string()),
+        };
+        for move_string in tokens.iter().skip(moves_index + 1) {
+            match uci_move_from_string(&state, move_string) {
+                Some(m) => { state.non_reversible_move(m); },
+                None => return Err(format!("Illegal move: {}", move_string)),
+            }
+        }
+        self.state = state;
+        Ok(())
+    }
+
+    fn go(&mut self, tokens: Vec<&str>) {
+        let value_of = |name: &str| -> Option<u64> {
+            let index = tokens.iter().position(|token| *token == name)?;
+            tokens.get(index + 1)?.parse().ok()
+        };
+
+        if let Some(mate_in_moves) = value_of("mate") {
+            self.go_mate(mate_in_moves as Depth);
+            return;
+        }
+
+        let mut limits = SearchLimits { time: None, depth: value_of("depth").map(|depth| depth as Depth), nodes: value_of("nodes") };
+        limits.time = if let Some(move_time) = value_of("movetime") {
+            Some(Duration::from_millis(move_time))
+        } else {
+            let (time, increment) = match self.state.turn {
+                Color::White => (value_of("wtime"), value_of("winc")),
+                Color::Black => (value_of("btime"), value_of("binc")),
+            };
+            match time {
+                Some(time) => Some(Duration::from_millis(time / TIME_DIVISOR + increment.unwrap_or(0) / 2)),
+                // A depth or node limit on its own is searched to completion
+                None if limits.depth.is_some() || limits.nodes.is_some() => None,
+                None => Some(DEFAULT_SEARCH_TIME),
+            }
+        };
+        let best_move = self.worker.limited_search(&mut self.state, limits, false);
+        println!("bestmove {}", uci_string_move(best_move));
+    }
+
+    // Non standard command printing the evaluation breakdown of the current position.
+    fn eval(&self) {
+        print!("{}", self.state.eval_trace());
+        if use_nnue() {
+            println!("NNUE eval: {:.2}", self.state.nnue_eval(Color::White) as f64 / (CENTI_PAWN as f64 * 100.0));
+        }
+    }
+
+    fn go_mate(&mut self, mate_in_moves: Depth) {
+        let start_node_count = self.worker.nodes_searched;
+        match self.worker.mate_search(&mut self.state, mate_in_moves) {
+            MateSearchResult::Mate(line) => {
+                let moves = (line.len() as Depth + 1) / 2;
+                let pv: Vec<String> = line.iter().map(|m| uci_string_move(*m)).collect();
+                println!("info depth {} nodes {} score mate {} pv {}", line.len(), self.worker.nodes_searched - start_node_count, moves, pv.join(" "));
+                println!("bestmove {}", uci_string_move(line[0]));
+            },
+            MateSearchResult::NoMate => {
+                println!("info depth {} nodes {} string no mate within {} moves", mate_in_moves * 2 - 1, self.worker.nodes_searched - start_node_count, mate_in_moves);
+                println!("bestmove {}", uci_string_move(NULL_MOVE));
+            },
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use crate::{evaluation::Evaluation, search::Depth};
+use std::time::Instant;
+
+use crate::{evaluation::Evaluation, search::{Depth, SearchLimits}};
 
 
 pub struct Worker {
@@ -6,6 +8,13 @@ pub struct Worker {
     pub root_ply:    u16,
     pub nodes_searched: u64,
     pub last_ids_score: Evaluation,
+    pub limits: SearchLimits,
+    pub search_start: Instant,
+    pub start_node_count: u64,
+    // Set once a limit runs out in the middle of an iteration, every node then returns without storing anything.
+    pub stopped: bool,
+    // The first iteration always finishes so there is a move to return.
+    pub can_stop: bool,
 }
 
 impl Worker {
@@ -15,6 +24,11 @@ impl Worker {
             root_ply: 0,
             nodes_searched: 0,
             last_ids_score: 0,
+            limits: SearchLimits::default(),
+            search_start: Instant::now(),
+            start_node_count: 0,
+            stopped: false,
+            can_stop: false,
         }
     }
 